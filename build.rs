@@ -0,0 +1,12 @@
+use std::process::Command;
+
+fn main() {
+    // Recorded in each run's run_config.json; left unset outside a git checkout
+    let sha = Command::new("git").args(["rev-parse", "HEAD"]).output();
+    if let Some(output) = sha.ok().filter(|output| output.status.success()) {
+        let sha = String::from_utf8_lossy(&output.stdout);
+        println!("cargo:rustc-env=FLUX_GIT_SHA={}", sha.trim());
+    }
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs/heads");
+}
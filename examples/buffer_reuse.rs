@@ -0,0 +1,64 @@
+// examples/buffer_reuse.rs
+//
+// Process images into one reused encode buffer and compare against allocating a fresh
+// buffer per image. Run with `cargo run --release --example buffer_reuse -- 1000`.
+
+use std::env;
+
+use anyhow::Result;
+use tokio::time::Instant;
+
+use flux::{
+    config::ProcessorConfig,
+    image_processor::process_single_image_to_buffer,
+    memory_monitor::MemoryMonitor,
+    url_generator::{ImageSource, UrlGenerator},
+};
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let count = env::args()
+        .nth(1)
+        .and_then(|arg| arg.parse().ok())
+        .unwrap_or(1000);
+    let urls = UrlGenerator::new(count).urls();
+    let config = ProcessorConfig::default();
+    let mut monitor = MemoryMonitor::new();
+
+    let baseline_mb = monitor.current_usage_mb();
+    let start = Instant::now();
+    let mut buffer = Vec::new();
+    let mut encoded_bytes = 0;
+    for url in &urls {
+        process_single_image_to_buffer(url, &config, &mut buffer).await?;
+        encoded_bytes += buffer.len();
+    }
+    let reused_mb = monitor.current_usage_mb().saturating_sub(baseline_mb);
+    println!(
+        "reused buffer:  {} images, {} encoded bytes, {} ms, +{} MB RSS, buffer capacity {} bytes",
+        count,
+        encoded_bytes,
+        start.elapsed().as_millis(),
+        reused_mb,
+        buffer.capacity()
+    );
+
+    let baseline_mb = monitor.current_usage_mb();
+    let start = Instant::now();
+    let mut allocations = 0;
+    for url in &urls {
+        let mut fresh = Vec::new();
+        process_single_image_to_buffer(url, &config, &mut fresh).await?;
+        allocations += fresh.capacity();
+    }
+    let fresh_mb = monitor.current_usage_mb().saturating_sub(baseline_mb);
+    println!(
+        "fresh buffers:  {} images, {} bytes allocated, {} ms, +{} MB RSS",
+        count,
+        allocations,
+        start.elapsed().as_millis(),
+        fresh_mb
+    );
+
+    Ok(())
+}
@@ -0,0 +1,61 @@
+// src/batched/concurrency.rs
+
+use std::{
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
+/// Counts tasks active within a batch. Each task holds an [`ActiveTask`] from spawn
+/// until it completes; clones share the same counters.
+#[derive(Clone, Default)]
+pub struct ConcurrencyTracker {
+    active: Arc<AtomicUsize>,
+    max_active: Arc<AtomicUsize>,
+    busy_ns: Arc<AtomicU64>,
+}
+
+impl ConcurrencyTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn start(&self) -> ActiveTask {
+        let active = self.active.fetch_add(1, Ordering::Relaxed) + 1;
+        self.max_active.fetch_max(active, Ordering::Relaxed);
+        ActiveTask {
+            tracker: self.clone(),
+            started: Instant::now(),
+        }
+    }
+
+    pub fn active(&self) -> usize {
+        self.active.load(Ordering::Relaxed)
+    }
+
+    pub fn max_concurrent(&self) -> usize {
+        self.max_active.load(Ordering::Relaxed)
+    }
+
+    /// Time-weighted average of active tasks over `elapsed_ns`: total task time / wall time
+    pub fn avg_concurrent(&self, elapsed_ns: u64) -> f64 {
+        self.busy_ns.load(Ordering::Relaxed) as f64 / elapsed_ns.max(1) as f64
+    }
+}
+
+/// Marks one task as active until dropped
+pub struct ActiveTask {
+    tracker: ConcurrencyTracker,
+    started: Instant,
+}
+
+impl Drop for ActiveTask {
+    fn drop(&mut self) {
+        self.tracker.active.fetch_sub(1, Ordering::Relaxed);
+        self.tracker
+            .busy_ns
+            .fetch_add(self.started.elapsed().as_nanos() as u64, Ordering::Relaxed);
+    }
+}
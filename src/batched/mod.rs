@@ -1 +1,2 @@
+pub mod concurrency;
 pub mod processor;
@@ -1,118 +1,433 @@
 use crate::{
-    image_processor::process_single_image, memory_monitor::MemoryMonitor,
-    url_generator::UrlGenerator,
+    batched::concurrency::ConcurrencyTracker,
+    config::{ErrorPolicy, ProcessorConfig},
+    http_client::exponential_backoff_ms,
+    image_processor::{process_image_from_bytes_to, process_single_image_to, ImageMetrics},
+    memory_monitor::{spawn_peak_tracker, MemoryMonitor},
+    metrics::{percentiles, stddev, ProcessingRun, ServerTimingSplit},
+    output_sink::{OutputSink, SinkWriter},
+    progress::progress_bar,
+    streaming::download::fetch_image,
+    url_generator::{ImageSource, UrlGenerator},
+    validation::validate_count,
+    warmup::warm_up,
 };
 use anyhow::Result;
-use futures::future::join_all;
-use std::{
-    cmp::max,
-    path::Path,
-    sync::{
-        atomic::{AtomicU64, Ordering},
-        Arc,
-    },
-    time::Duration,
-};
+use futures::{future::join_all, stream::FuturesUnordered, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::{cmp::max, collections::HashMap, fs, path::PathBuf, sync::Arc, time::Duration};
 use tokio::{
     spawn,
+    sync::Semaphore,
+    task::JoinHandle,
     time::{self, sleep},
 };
-use tracing::info;
+use tracing::{info, warn};
 
+#[derive(Default, Serialize, Deserialize)]
 pub struct BatchedStats {
     pub total_images: usize,
     pub batch_size: usize,
     pub total_time_ms: u64,
+    /// Time spent paused between batches under `config.inter_batch_delay`, not counted in
+    /// `total_time_ms`
+    pub idle_time_ms: u64,
     pub peak_memory_mb: u64,
+    /// Highest CPU usage sampled during the run, as a percentage of one core
+    pub peak_cpu_percent: f32,
     pub avg_download_ms: u64,
     pub avg_resize_ms: u64,
+    pub p50_download_ms: u64,
+    pub p95_download_ms: u64,
+    pub p99_download_ms: u64,
+    pub p50_resize_ms: u64,
+    pub p95_resize_ms: u64,
+    pub p99_resize_ms: u64,
+    pub stddev_download_ms: f64,
+    pub stddev_resize_ms: f64,
+    /// Every image's download time, sorted
+    pub download_samples: Vec<u64>,
+    /// Server-side share of the download time, from images that sent `Server-Timing`
+    pub server_timing: ServerTimingSplit,
+    /// Images skipped because their output already existed, not counted in `total_images`
+    pub skipped_count: usize,
+    /// Saved files, or entry names when writing to a ZIP archive
+    pub saved_paths: Vec<PathBuf>,
+    /// Images downloaded ahead of their batch under `config.prefetch`
+    pub prefetched_images: usize,
+    /// Retry attempts across all batches
+    pub total_batch_retries: usize,
+    /// Batches that only succeeded on their final allowed retry
+    pub max_retries_hit_batches: usize,
+    /// Most tasks running at once in any batch
+    pub max_concurrent: usize,
+    /// Size of every batch in the order they ran; varies under `config.adaptive_batch`
+    pub batch_sizes: Vec<usize>,
+    /// Mean over batches of each batch's time-weighted active task count
+    pub avg_concurrent: f64,
+    /// Samples from `config.memory_timeline` taken during the run, as (offset from the
+    /// timeline's creation, MB); empty without a timeline
+    pub memory_timeline: Vec<(Duration, u64)>,
+    /// (URL, error) for every image still failing after `config.max_retries_per_batch`
+    /// retries under [`ErrorPolicy::CollectAndContinue`]. These aren't counted in
+    /// `total_images` or any of the timings.
+    pub errors: Vec<(String, String)>,
+}
+
+impl BatchedStats {
+    /// This run's row in a [`MetricsCollector`](crate::metrics::MetricsCollector) comparison
+    pub fn to_run(&self, approach: &str) -> ProcessingRun {
+        ProcessingRun::new(
+            approach,
+            self.total_images,
+            self.total_time_ms,
+            self.peak_memory_mb,
+            self.avg_download_ms,
+            self.avg_resize_ms,
+        )
+        .with_percentiles(
+            [
+                self.p50_download_ms,
+                self.p95_download_ms,
+                self.p99_download_ms,
+            ],
+            [self.p50_resize_ms, self.p95_resize_ms, self.p99_resize_ms],
+        )
+        .with_stddev(self.stddev_download_ms, self.stddev_resize_ms)
+        .with_peak_cpu(self.peak_cpu_percent)
+        .with_download_samples(self.download_samples.clone())
+    }
 }
 
 pub async fn process_batched(
     count: usize,
     batch_size: usize,
-    output_dir: &Path,
+    output: &OutputSink,
+    config: &ProcessorConfig,
+) -> Result<BatchedStats> {
+    validate_count(count)?;
+    let urls = UrlGenerator::for_config(count, config).urls();
+    process_batched_urls(urls, batch_size, output, config).await
+}
+
+async fn process_batched_urls(
+    urls: Vec<String>,
+    batch_size: usize,
+    output: &OutputSink,
+    config: &ProcessorConfig,
 ) -> Result<BatchedStats> {
+    let config = &config.with_shared_client()?;
+    let (urls, skipped_count) = output.skip_existing(urls, config);
+    let count = urls.len();
+    warm_up(&urls, config).await;
     info!(count, batch_size, "starting batch processing");
+    let writer = output.open()?;
 
-    let url_generator = UrlGenerator::new(count);
-    let urls = url_generator.generate();
+    let mut saved_paths = vec![];
+    let mut errors = vec![];
     let (mut total_download_time, mut total_resize_time, mut total_time_ms) = (0, 0, 0);
+    let mut idle_time_ms = 0;
+    let (mut download_samples, mut resize_samples) = (vec![], vec![]);
+    let mut server_timing = ServerTimingSplit::default();
 
-    let peak_memory_mb = Arc::new(AtomicU64::new(0));
-    let peak_clone = Arc::clone(&peak_memory_mb);
+    let (monitor_handle, peak) = spawn_peak_tracker(100, MemoryMonitor::with_baseline());
 
-    let monitor_handle = spawn(async move {
-        let mut memory_monitor = MemoryMonitor::new();
-        loop {
-            let curr_usage = memory_monitor.current_usage_mb();
-            peak_clone.store(
-                max(curr_usage, peak_clone.load(Ordering::Relaxed)),
-                Ordering::Relaxed,
-            );
-            sleep(Duration::from_millis(100)).await;
-        }
-    });
+    let run_start = std::time::Instant::now();
+    let mut memory_monitor = MemoryMonitor::new();
+    let mut batch_sizes = vec![];
+    let mut next_start = 0;
+    let prefetch_sem = Arc::new(Semaphore::new(config.download_concurrency));
+    let mut prefetched = HashMap::new();
+    let mut prefetched_images = 0;
+    let (mut total_batch_retries, mut max_retries_hit_batches) = (0, 0);
+    let (mut max_concurrent, mut total_avg_concurrent) = (0, 0.0);
 
-    for batch in urls.chunks(batch_size) {
-        let mut batch_tasks = vec![];
+    let progress = progress_bar(count, config.progress);
+    while next_start < count {
+        let size = match &config.adaptive_batch {
+            Some(adaptive) => {
+                adaptive.batch_size(memory_monitor.available_mb(), memory_monitor.total_mb())
+            }
+            None => batch_size.max(1),
+        };
+        let batch = &urls[next_start..count.min(next_start + size)];
+        next_start += batch.len();
+        batch_sizes.push(batch.len());
         let start_time = time::Instant::now();
+        let tracker = ConcurrencyTracker::new();
         info!(batch_size = batch.len(), "starting batch");
 
-        for url in batch {
-            let owned_url = url.clone();
-            let owned_path = output_dir.to_path_buf();
+        // Start on the next batch once this one is nearly drained. Its size isn't decided
+        // until it starts, so assume it matches this one.
+        let next_batch = &urls[next_start..count.min(next_start + size)];
+        let mut pending_prefetch = config
+            .prefetch
+            .filter(|_| !next_batch.is_empty())
+            .map(|policy| (policy, next_batch));
+        let mut prefetches = vec![];
+        let mut pending: Vec<String> = batch.to_vec();
+        let mut attempt = 0;
+        loop {
+            let mut batch_tasks = FuturesUnordered::new();
+            for url in &pending {
+                let owned_url = url.clone();
+                let owned_writer = writer.clone();
+                let owned_config = config.clone();
+                let active = tracker.start();
+
+                match prefetched.remove(url) {
+                    Some(bytes) => batch_tasks.push(spawn(async move {
+                        let _active = active;
+                        let res =
+                            save_prefetched(&owned_url, bytes, &owned_writer, &owned_config).await;
+                        (owned_url, res)
+                    })),
+                    None => batch_tasks.push(spawn(async move {
+                        let _active = active;
+                        let res =
+                            process_single_image_to(&owned_url, &owned_writer, &owned_config).await;
+                        (owned_url, res)
+                    })),
+                }
+            }
+
+            let mut remaining = pending.len();
+            let mut batch_results = vec![];
+            loop {
+                if let Some((policy, next_batch)) =
+                    pending_prefetch.take_if(|(policy, _)| remaining <= policy.trigger_at_remaining)
+                {
+                    prefetches = next_batch
+                        .iter()
+                        .take(policy.prefetch_count)
+                        .map(|u| {
+                            spawn(fetch_image(
+                                u.clone(),
+                                Arc::clone(&prefetch_sem),
+                                config.clone(),
+                            ))
+                        })
+                        .collect();
+                }
+
+                match batch_tasks.next().await {
+                    Some(res) => batch_results.push(res),
+                    None => break,
+                }
+                remaining -= 1;
+            }
+
+            let mut batch_error = None;
+            let mut failures = HashMap::new();
+            for res in batch_results {
+                let metric = match res {
+                    Ok((url, res)) => res.inspect_err(|e| {
+                        failures.insert(url, e.to_string());
+                    }),
+                    Err(e) => Err(e.into()),
+                };
+                match metric {
+                    Ok(metric) => {
+                        total_download_time += metric.download_ms;
+                        total_resize_time += metric.resize_ms;
+                        download_samples.push(metric.download_ms);
+                        resize_samples.push(metric.resize_ms);
+                        server_timing.record(&metric);
+                        if let Some(metrics) = &config.live_metrics {
+                            metrics.record_image("batched", metric.download_ms, metric.resize_ms);
+                        }
+                        pending.retain(|url| *url != metric.url);
+                        saved_paths.push(metric.output_path);
+                    }
+                    Err(e) => {
+                        batch_error.get_or_insert(e);
+                    }
+                }
+            }
 
-            batch_tasks.push(spawn(async move {
-                let task_metric = process_single_image(&owned_url, &owned_path).await.unwrap();
+            let Some(e) = batch_error else {
+                break;
+            };
+            if attempt == config.max_retries_per_batch
+                && config.error_policy == ErrorPolicy::CollectAndContinue
+            {
+                warn!(failed = pending.len(), error = %e, "images failed, continuing");
+                errors.extend(pending.drain(..).map(|url| {
+                    let message = failures.remove(&url).unwrap_or_else(|| e.to_string());
+                    (url, message)
+                }));
+                break;
+            }
+            if attempt == config.max_retries_per_batch {
+                monitor_handle.abort();
+                // The run is over, so the next batch's downloads are no longer needed
+                prefetches.iter().for_each(JoinHandle::abort);
+                if config.post_run_cleanup {
+                    drop(writer);
+                    match output {
+                        OutputSink::Directory(_) => remove_saved(&saved_paths),
+                        OutputSink::ZipArchive(path) => remove_saved(std::slice::from_ref(path)),
+                        OutputSink::Stdout(_) => {}
+                    }
+                }
+                return Err(e);
+            }
 
-                (task_metric.download_ms, task_metric.resize_ms)
-            }));
+            // Only the images that failed are retried
+            attempt += 1;
+            total_batch_retries += 1;
+            let backoff_ms =
+                exponential_backoff_ms(config.retry_backoff_base_ms, attempt as u32 - 1);
+            warn!(attempt, backoff_ms, failed = pending.len(), error = %e, "retrying batch");
+            sleep(Duration::from_millis(backoff_ms)).await;
+        }
+        if attempt > 0 && attempt == config.max_retries_per_batch {
+            max_retries_hit_batches += 1;
+        }
+
+        for res in join_all(prefetches).await {
+            match res? {
+                Ok(data) => {
+                    prefetched_images += 1;
+                    prefetched.insert(data.url.clone(), (data.bytes, data.download_ms as u64));
+                }
+                Err(e) => warn!(error = %e, "prefetch failed"),
+            }
         }
 
-        let batch_results = join_all(batch_tasks).await;
-        let batch_duration = start_time.elapsed().as_millis() as u64;
+        progress.inc(batch.len() as u64);
+        let batch_elapsed = start_time.elapsed();
+        let batch_duration = batch_elapsed.as_millis() as u64;
         total_time_ms += batch_duration;
-        info!(batch_time_ms = batch_duration, "batch complete");
+        let batch_avg_concurrent = tracker.avg_concurrent(batch_elapsed.as_nanos() as u64);
+        max_concurrent = max(max_concurrent, tracker.max_concurrent());
+        total_avg_concurrent += batch_avg_concurrent;
+        info!(
+            batch_time_ms = batch_duration,
+            max_concurrent = tracker.max_concurrent(),
+            avg_concurrent = batch_avg_concurrent,
+            "batch complete"
+        );
 
-        for res in batch_results {
-            let (task_download, task_resize) = res?;
-            total_download_time += task_download;
-            total_resize_time += task_resize;
+        // The memory monitor keeps sampling through the pause, so reclamation shows up in
+        // the timeline and the peak
+        if let Some(delay) = config.inter_batch_delay.filter(|_| next_start < count) {
+            let idle_start = time::Instant::now();
+            sleep(delay).await;
+            idle_time_ms += idle_start.elapsed().as_millis() as u64;
         }
     }
 
+    progress.finish();
+    writer.finish()?;
+    let processed = (count - errors.len()).max(1) as u64;
     monitor_handle.abort();
-    let peak_memory_mb = peak_memory_mb.load(Ordering::Relaxed);
+    let (peak_memory_mb, peak_cpu_percent) = (peak.memory_mb(), peak.cpu_percent());
+    if let Some(metrics) = &config.live_metrics {
+        metrics.record_peak_memory_mb(peak_memory_mb);
+    }
     info!(
         total_time_ms,
         peak_memory_mb,
-        avg_download_ms = total_download_time / count as u64,
-        avg_resize_ms = total_resize_time / count as u64,
+        avg_download_ms = total_download_time / processed,
+        avg_resize_ms = total_resize_time / processed,
         "batch processing complete"
     );
 
+    let [p50_download_ms, p95_download_ms, p99_download_ms] = percentiles(&mut download_samples);
+    let [p50_resize_ms, p95_resize_ms, p99_resize_ms] = percentiles(&mut resize_samples);
+
     Ok(BatchedStats {
-        total_images: count,
+        total_images: count - errors.len(),
         batch_size,
         total_time_ms,
+        idle_time_ms,
         peak_memory_mb,
-        avg_download_ms: total_download_time / count as u64,
-        avg_resize_ms: total_resize_time / count as u64,
+        peak_cpu_percent,
+        avg_download_ms: total_download_time / processed,
+        avg_resize_ms: total_resize_time / processed,
+        p50_download_ms,
+        p95_download_ms,
+        p99_download_ms,
+        p50_resize_ms,
+        p95_resize_ms,
+        p99_resize_ms,
+        stddev_download_ms: stddev(&download_samples),
+        stddev_resize_ms: stddev(&resize_samples),
+        download_samples,
+        server_timing,
+        skipped_count,
+        saved_paths,
+        prefetched_images,
+        total_batch_retries,
+        max_retries_hit_batches,
+        max_concurrent,
+        avg_concurrent: total_avg_concurrent / batch_sizes.len().max(1) as f64,
+        batch_sizes,
+        memory_timeline: config
+            .memory_timeline
+            .as_ref()
+            .map(|timeline| timeline.timeline_since(run_start))
+            .unwrap_or_default(),
+        errors,
     })
 }
 
+/// Finish an image whose bytes were prefetched during the previous batch
+async fn save_prefetched(
+    url: &str,
+    (bytes, download_ms): (Vec<u8>, u64),
+    output: &SinkWriter,
+    config: &ProcessorConfig,
+) -> Result<ImageMetrics> {
+    let mut metrics = process_image_from_bytes_to(url, &bytes, output, config).await?;
+    metrics.download_ms = download_ms;
+    Ok(metrics)
+}
+
+/// Remove files written by a failed run
+fn remove_saved(paths: &[PathBuf]) {
+    info!(files = paths.len(), "cleaning up failed run");
+    for path in paths {
+        if let Err(e) = fs::remove_file(path) {
+            warn!(path = %path.display(), error = %e, "failed to remove saved image");
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::fs;
+    use crate::{
+        config::{AdaptiveBatchConfig, PrefetchPolicy},
+        memory_monitor::MemoryTimeline,
+        test_support::jpeg_bytes,
+        url_generator::UrlTemplate,
+    };
+    use std::path::Path;
+    use wiremock::{
+        matchers::{any, path, path_regex},
+        Mock, MockServer, ResponseTemplate,
+    };
 
     #[tokio::test]
     async fn processes_in_batches() {
+        let server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(jpeg_bytes(400, 300)))
+            .mount(&server)
+            .await;
+
         let output = Path::new("test_output_batched");
         fs::create_dir_all(output).unwrap();
 
-        let stats = process_batched(10, 3, output).await.unwrap();
+        let config = ProcessorConfig {
+            url_template: Some(UrlTemplate::Custom(format!("{}/{{seed}}", server.uri()))),
+            ..Default::default()
+        };
+        let stats = process_batched(10, 3, &OutputSink::Directory(output.into()), &config)
+            .await
+            .unwrap();
 
         assert_eq!(stats.total_images, 10);
         assert_eq!(stats.batch_size, 3);
@@ -120,4 +435,336 @@ mod tests {
 
         fs::remove_dir_all(output).unwrap();
     }
+
+    #[test]
+    fn round_trips_stats() {
+        let stats = BatchedStats {
+            total_images: 30,
+            batch_size: 10,
+            batch_sizes: vec![10, 10, 10],
+            avg_concurrent: 7.5,
+            memory_timeline: vec![(Duration::from_millis(250), 180)],
+            ..Default::default()
+        };
+        let json = serde_json::to_string(&stats).unwrap();
+        let loaded: BatchedStats = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.batch_sizes, vec![10, 10, 10]);
+        assert_eq!(loaded.avg_concurrent, 7.5);
+        assert_eq!(loaded.memory_timeline, stats.memory_timeline);
+    }
+
+    #[tokio::test]
+    async fn rejects_zero_count() {
+        let output = OutputSink::Directory(PathBuf::from("test_output_batched_empty"));
+        let result = process_batched(0, 10, &output, &ProcessorConfig::default()).await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn cleans_up_failed_run() {
+        let server = MockServer::start().await;
+        Mock::given(path_regex("^/good/"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(jpeg_bytes(16, 16)))
+            .mount(&server)
+            .await;
+        Mock::given(path("/bad"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("not an image"))
+            .mount(&server)
+            .await;
+
+        let output = Path::new("test_output_batched_cleanup");
+        fs::create_dir_all(output).unwrap();
+
+        let urls = vec![
+            format!("{}/good/1", server.uri()),
+            format!("{}/good/2", server.uri()),
+            format!("{}/bad", server.uri()),
+        ];
+        let config = ProcessorConfig {
+            post_run_cleanup: true,
+            ..Default::default()
+        };
+
+        let result =
+            process_batched_urls(urls, 2, &OutputSink::Directory(output.into()), &config).await;
+
+        assert!(result.is_err());
+        assert_eq!(fs::read_dir(output).unwrap().count(), 0);
+
+        fs::remove_dir_all(output).unwrap();
+    }
+
+    #[tokio::test]
+    async fn prefetches_next_batch() {
+        let server = MockServer::start().await;
+        Mock::given(path_regex("^/good/"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(jpeg_bytes(16, 16)))
+            .mount(&server)
+            .await;
+
+        let output = Path::new("test_output_batched_prefetch");
+        fs::create_dir_all(output).unwrap();
+
+        let urls: Vec<String> = (0..6)
+            .map(|i| format!("{}/good/{}", server.uri(), i))
+            .collect();
+        let config = ProcessorConfig {
+            prefetch: Some(PrefetchPolicy {
+                trigger_at_remaining: 1,
+                prefetch_count: 2,
+            }),
+            ..Default::default()
+        };
+
+        let stats = process_batched_urls(urls, 3, &OutputSink::Directory(output.into()), &config)
+            .await
+            .unwrap();
+
+        assert_eq!(stats.prefetched_images, 2);
+        assert_eq!(stats.saved_paths.len(), 6);
+        assert_eq!(server.received_requests().await.unwrap().len(), 6);
+
+        fs::remove_dir_all(output).unwrap();
+    }
+
+    #[tokio::test]
+    async fn retries_failed_images() {
+        let server = MockServer::start().await;
+        Mock::given(path("/flaky"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("not an image"))
+            .up_to_n_times(1)
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        Mock::given(path_regex("^/(good/|flaky)"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(jpeg_bytes(16, 16)))
+            .mount(&server)
+            .await;
+
+        let output = Path::new("test_output_batched_retry");
+        fs::create_dir_all(output).unwrap();
+
+        let urls = vec![
+            format!("{}/good/1", server.uri()),
+            format!("{}/good/2", server.uri()),
+            format!("{}/flaky", server.uri()),
+        ];
+        let config = ProcessorConfig {
+            max_retries_per_batch: 2,
+            retry_backoff_base_ms: 10,
+            ..Default::default()
+        };
+
+        let stats = process_batched_urls(urls, 3, &OutputSink::Directory(output.into()), &config)
+            .await
+            .unwrap();
+
+        assert_eq!(stats.total_batch_retries, 1);
+        assert_eq!(stats.max_retries_hit_batches, 0);
+        assert_eq!(stats.saved_paths.len(), 3);
+        // The two good images aren't downloaded again on retry
+        assert_eq!(server.received_requests().await.unwrap().len(), 4);
+
+        fs::remove_dir_all(output).unwrap();
+    }
+
+    #[tokio::test]
+    async fn tracks_concurrency_within_batches() {
+        let server = MockServer::start().await;
+        Mock::given(path_regex("^/good/"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_bytes(jpeg_bytes(16, 16))
+                    .set_delay(Duration::from_millis(50)),
+            )
+            .mount(&server)
+            .await;
+
+        let output = Path::new("test_output_batched_concurrency");
+        fs::create_dir_all(output).unwrap();
+
+        let urls: Vec<String> = (0..7)
+            .map(|i| format!("{}/good/{}", server.uri(), i))
+            .collect();
+        let stats = process_batched_urls(
+            urls,
+            3,
+            &OutputSink::Directory(output.into()),
+            &ProcessorConfig::default(),
+        )
+        .await
+        .unwrap();
+
+        assert!(stats.max_concurrent >= 1);
+        assert!(stats.max_concurrent <= 3);
+        assert!(stats.avg_concurrent > 0.0);
+        assert!(stats.avg_concurrent <= 3.0);
+
+        fs::remove_dir_all(output).unwrap();
+    }
+
+    #[tokio::test]
+    async fn pauses_between_batches() {
+        let server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(jpeg_bytes(16, 16)))
+            .mount(&server)
+            .await;
+
+        let output = Path::new("test_output_batched_delay");
+        fs::create_dir_all(output).unwrap();
+
+        let config = ProcessorConfig {
+            inter_batch_delay: Some(Duration::from_millis(200)),
+            ..Default::default()
+        };
+        let urls: Vec<String> = (0..4).map(|i| format!("{}/{}", server.uri(), i)).collect();
+        let start = std::time::Instant::now();
+        let stats = process_batched_urls(urls, 2, &OutputSink::Directory(output.into()), &config)
+            .await
+            .unwrap();
+
+        // One pause between the two batches, none after the last
+        assert!(stats.idle_time_ms >= 200);
+        assert!(stats.idle_time_ms < 400);
+        assert!(stats.total_time_ms + stats.idle_time_ms <= start.elapsed().as_millis() as u64);
+
+        fs::remove_dir_all(output).unwrap();
+    }
+
+    #[tokio::test]
+    async fn exports_memory_timeline() {
+        let server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_bytes(jpeg_bytes(16, 16))
+                    .set_delay(Duration::from_millis(50)),
+            )
+            .mount(&server)
+            .await;
+
+        let output = Path::new("test_output_batched_timeline");
+        fs::create_dir_all(output).unwrap();
+
+        let timeline = Arc::new(MemoryTimeline::new(Duration::from_millis(10)));
+        let config = ProcessorConfig {
+            memory_timeline: Some(Arc::clone(&timeline)),
+            ..Default::default()
+        };
+        let urls: Vec<String> = (0..4).map(|i| format!("{}/{}", server.uri(), i)).collect();
+        let stats = process_batched_urls(urls, 2, &OutputSink::Directory(output.into()), &config)
+            .await
+            .unwrap();
+
+        assert!(!stats.memory_timeline.is_empty());
+        assert!(stats.memory_timeline.len() <= timeline.timeline().len());
+
+        fs::remove_dir_all(output).unwrap();
+    }
+
+    #[tokio::test]
+    async fn saves_into_zip_archive() {
+        let server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(jpeg_bytes(16, 16)))
+            .mount(&server)
+            .await;
+
+        let archive = PathBuf::from("test_output_batched.zip");
+        let urls: Vec<String> = (0..5).map(|i| format!("{}/{}", server.uri(), i)).collect();
+        let stats = process_batched_urls(
+            urls,
+            2,
+            &OutputSink::ZipArchive(archive.clone()),
+            &ProcessorConfig::default(),
+        )
+        .await
+        .unwrap();
+
+        let zip = zip::ZipArchive::new(fs::File::open(&archive).unwrap()).unwrap();
+        assert_eq!(zip.len(), 5);
+        assert!(stats
+            .saved_paths
+            .iter()
+            .all(|name| zip.index_for_path(name).is_some()));
+
+        fs::remove_file(&archive).unwrap();
+    }
+
+    #[tokio::test]
+    async fn shrinks_batches_when_memory_is_low() {
+        let server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(jpeg_bytes(16, 16)))
+            .mount(&server)
+            .await;
+
+        let output = Path::new("test_output_batched_adaptive");
+        fs::create_dir_all(output).unwrap();
+
+        // No machine has this much headroom, so every batch stays at the minimum
+        let config = ProcessorConfig {
+            adaptive_batch: Some(AdaptiveBatchConfig {
+                min_batch: 2,
+                max_batch: 8,
+                memory_headroom_mb: u64::MAX,
+            }),
+            ..Default::default()
+        };
+        let urls: Vec<String> = (0..5).map(|i| format!("{}/{}", server.uri(), i)).collect();
+        let stats = process_batched_urls(urls, 8, &OutputSink::Directory(output.into()), &config)
+            .await
+            .unwrap();
+
+        assert_eq!(stats.batch_sizes, vec![2, 2, 1]);
+        assert_eq!(fs::read_dir(output).unwrap().count(), 5);
+
+        fs::remove_dir_all(output).unwrap();
+    }
+
+    #[tokio::test]
+    async fn collects_errors_and_continues() {
+        let server = MockServer::start().await;
+        Mock::given(path_regex("^/good/"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(jpeg_bytes(16, 16)))
+            .mount(&server)
+            .await;
+        Mock::given(path("/bad"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("not an image"))
+            .mount(&server)
+            .await;
+
+        let output = Path::new("test_output_batched_collect_errors");
+        fs::create_dir_all(output).unwrap();
+
+        let urls: Vec<String> = ["good/1", "good/2", "bad", "good/3", "good/4"]
+            .iter()
+            .map(|path| format!("{}/{}", server.uri(), path))
+            .collect();
+        let config = ProcessorConfig {
+            error_policy: ErrorPolicy::CollectAndContinue,
+            max_retries_per_batch: 1,
+            retry_backoff_base_ms: 1,
+            ..Default::default()
+        };
+
+        let stats = process_batched_urls(
+            urls.clone(),
+            2,
+            &OutputSink::Directory(output.into()),
+            &config,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(stats.total_images, 4);
+        assert_eq!(stats.saved_paths.len(), 4);
+        assert_eq!(stats.total_batch_retries, 1);
+        assert_eq!(stats.errors.len(), 1);
+        assert_eq!(stats.errors[0].0, urls[2]);
+        assert_eq!(fs::read_dir(output).unwrap().count(), 4);
+
+        fs::remove_dir_all(output).unwrap();
+    }
 }
@@ -0,0 +1,73 @@
+// src/cache.rs
+
+use anyhow::Result;
+use sha2::{Digest, Sha256};
+use std::{fs, path::PathBuf};
+
+/// Downloaded images kept on disk by URL, each with the `ETag` it was served with in a
+/// `.etag` sidecar, so a later run can ask the server whether its copy is still current
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiskCache {
+    pub dir: PathBuf,
+}
+
+impl DiskCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Cached bytes and `ETag` for `url`, `None` unless both files are there
+    pub fn get(&self, url: &str) -> Option<(Vec<u8>, String)> {
+        let path = self.path(url);
+        let etag = fs::read_to_string(path.with_extension("etag")).ok()?;
+        let bytes = fs::read(path).ok()?;
+        Some((bytes, etag))
+    }
+
+    /// Store `bytes` for `url` along with the `etag` they were served with, replacing any
+    /// earlier entry
+    pub fn put(&self, url: &str, bytes: &[u8], etag: &str) -> Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let path = self.path(url);
+        let etag_path = path.with_extension("etag");
+        // The old ETag goes first and the new one last, so an interrupted write leaves no
+        // entry rather than bytes under the wrong ETag
+        if etag_path.exists() {
+            fs::remove_file(&etag_path)?;
+        }
+        fs::write(&path, bytes)?;
+        fs::write(etag_path, etag)?;
+        Ok(())
+    }
+
+    fn path(&self, url: &str) -> PathBuf {
+        self.dir
+            .join(format!("{:x}.bin", Sha256::digest(url.as_bytes())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_entries() {
+        let cache = DiskCache::new(std::env::temp_dir().join("flux_disk_cache"));
+        let url = "https://example.com/1.jpg";
+        assert!(cache.get(url).is_none());
+
+        cache.put(url, b"first", "\"v1\"").unwrap();
+        assert_eq!(
+            cache.get(url),
+            Some((b"first".to_vec(), "\"v1\"".to_string()))
+        );
+        cache.put(url, b"second", "\"v2\"").unwrap();
+        assert_eq!(
+            cache.get(url),
+            Some((b"second".to_vec(), "\"v2\"".to_string()))
+        );
+        assert!(cache.get("https://example.com/2.jpg").is_none());
+
+        fs::remove_dir_all(&cache.dir).unwrap();
+    }
+}
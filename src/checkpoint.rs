@@ -0,0 +1,146 @@
+// src/checkpoint.rs
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, BufWriter, ErrorKind, Write},
+    path::Path,
+};
+
+use crate::config::CheckpointConfig;
+
+/// One finished image, written as a line of the checkpoint file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CheckpointEntry {
+    pub url: String,
+    pub filename: String,
+}
+
+/// URLs already recorded in the checkpoint at `path`, empty if there is no checkpoint yet
+pub fn read_checkpoint(path: &Path) -> Result<HashSet<String>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(HashSet::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut urls = HashSet::new();
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        // A run killed mid-write can leave a truncated line; that image is redone
+        match serde_json::from_str::<CheckpointEntry>(&line) {
+            Ok(entry) => {
+                urls.insert(entry.url);
+            }
+            Err(_) => continue,
+        }
+    }
+    Ok(urls)
+}
+
+/// Appends entries to a checkpoint file, flushing to disk every `sync_interval` images
+pub struct CheckpointWriter {
+    file: BufWriter<File>,
+    sync_interval: usize,
+    pending: usize,
+}
+
+impl CheckpointWriter {
+    /// Open the checkpoint for appending, first cutting off any partial line left by a run
+    /// killed mid-write so new entries start on a line of their own
+    pub fn open(config: &CheckpointConfig) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&config.path)?;
+        let contents = fs::read(&config.path)?;
+        if contents.last().is_some_and(|&byte| byte != b'\n') {
+            let complete = contents
+                .iter()
+                .rposition(|&byte| byte == b'\n')
+                .map_or(0, |newline| newline + 1);
+            file.set_len(complete as u64)?;
+        }
+        Ok(Self {
+            file: BufWriter::new(file),
+            sync_interval: config.sync_interval.max(1),
+            pending: 0,
+        })
+    }
+
+    pub fn record(&mut self, entry: &CheckpointEntry) -> Result<()> {
+        writeln!(self.file, "{}", serde_json::to_string(entry)?)?;
+        self.pending += 1;
+        if self.pending >= self.sync_interval {
+            self.sync()?;
+        }
+        Ok(())
+    }
+
+    pub fn sync(&mut self) -> Result<()> {
+        self.file.flush()?;
+        self.file.get_ref().sync_data()?;
+        self.pending = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn ignores_truncated_last_line() {
+        let path = Path::new("test_checkpoint_truncated.jsonl");
+        let config = CheckpointConfig {
+            path: path.to_path_buf(),
+            sync_interval: 1,
+        };
+
+        let mut writer = CheckpointWriter::open(&config).unwrap();
+        writer
+            .record(&CheckpointEntry {
+                url: "https://example.com/1.jpg".to_string(),
+                filename: "abc.jpg".to_string(),
+            })
+            .unwrap();
+        drop(writer);
+        let mut file = OpenOptions::new().append(true).open(path).unwrap();
+        write!(file, "{{\"url\":\"https://exa").unwrap();
+
+        let urls = read_checkpoint(path).unwrap();
+        assert_eq!(urls.len(), 1);
+        assert!(urls.contains("https://example.com/1.jpg"));
+
+        // The resumed run's entries still land on lines of their own
+        let mut writer = CheckpointWriter::open(&config).unwrap();
+        writer
+            .record(&CheckpointEntry {
+                url: "https://example.com/2.jpg".to_string(),
+                filename: "def.jpg".to_string(),
+            })
+            .unwrap();
+        drop(writer);
+        let urls = read_checkpoint(path).unwrap();
+        assert_eq!(urls.len(), 2);
+        assert!(urls.contains("https://example.com/2.jpg"));
+
+        // Lines that don't parse are skipped rather than ending the read
+        let mut file = OpenOptions::new().append(true).open(path).unwrap();
+        writeln!(file, "not json").unwrap();
+        writeln!(
+            file,
+            "{{\"url\":\"https://example.com/3.jpg\",\"filename\":\"ghi.jpg\"}}"
+        )
+        .unwrap();
+        assert_eq!(read_checkpoint(path).unwrap().len(), 3);
+
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,57 @@
+// src/compression.rs
+
+use std::time::Instant;
+
+use anyhow::Result;
+
+/// zstd level used for channel payloads; image bytes are already compressed, so higher
+/// levels cost a lot of CPU for very little gain
+const LEVEL: i32 = 3;
+
+/// Sizes and timing for one compressed channel payload
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChannelCompression {
+    pub original_len: usize,
+    pub compressed_len: usize,
+    pub compress_ms: u64,
+}
+
+impl ChannelCompression {
+    /// Original size over compressed size; above 1.0 means the payload shrank
+    pub fn ratio(&self) -> f64 {
+        self.original_len as f64 / self.compressed_len.max(1) as f64
+    }
+}
+
+/// zstd-compress `bytes`, returning the payload and its stats
+pub fn compress(bytes: &[u8]) -> Result<(Vec<u8>, ChannelCompression)> {
+    let start = Instant::now();
+    let compressed = zstd::bulk::compress(bytes, LEVEL)?;
+    let stats = ChannelCompression {
+        original_len: bytes.len(),
+        compressed_len: compressed.len(),
+        compress_ms: start.elapsed().as_millis() as u64,
+    };
+    Ok((compressed, stats))
+}
+
+/// Reverse [`compress`]
+pub fn decompress(bytes: &[u8], stats: &ChannelCompression) -> Result<Vec<u8>> {
+    Ok(zstd::bulk::decompress(bytes, stats.original_len)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_bytes() {
+        let bytes: Vec<u8> = (0..10_000).map(|i| (i % 7) as u8).collect();
+
+        let (compressed, stats) = compress(&bytes).unwrap();
+        assert!(stats.ratio() > 1.0);
+        assert_eq!(stats.compressed_len, compressed.len());
+
+        assert_eq!(decompress(&compressed, &stats).unwrap(), bytes);
+    }
+}
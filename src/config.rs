@@ -0,0 +1,496 @@
+// src/config.rs
+
+use futures::future::BoxFuture;
+use std::{
+    fmt,
+    future::Future,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::sync::watch;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    cache::DiskCache,
+    http_client::{build_client, client_builder, ConnectTimingLayer},
+    image_processor::{ImageProcessor, ImageResult, ResizeConfig, SaveConfig},
+    live_metrics::LiveMetrics,
+    memory_monitor::MemoryTimeline,
+    streaming::rate_limit::RateLimiter,
+    url_generator::UrlTemplate,
+};
+
+/// Options shared by every processor
+#[derive(Debug, Clone)]
+pub struct ProcessorConfig {
+    /// Streaming: downloads allowed in flight at once
+    pub download_concurrency: usize,
+    /// Streaming: decode + resize jobs allowed to run at once
+    pub process_concurrency: usize,
+    /// Streaming: save workers writing files at once
+    pub save_concurrency: usize,
+    /// Streaming: capacity of the download → process channel
+    pub download_channel_capacity: usize,
+    /// Streaming: capacity of the process → save channel
+    pub process_channel_capacity: usize,
+    /// Streaming: most images allowed between download and save across all stages
+    pub max_in_flight: Option<usize>,
+    /// Streaming: save images in URL order rather than as they finish, with a single save
+    /// worker
+    pub preserve_order: bool,
+    /// Naive: have the memory monitor refresh every process instead of just this one, to
+    /// measure how much the monitor itself costs
+    pub full_memory_refresh: bool,
+    /// Spread output files across nested subdirectories instead of one flat directory
+    pub sharding: Option<OutputSharding>,
+    /// Skip URLs whose output file already exists in the output directory
+    pub skip_existing: bool,
+    /// Delete files saved during a batched run if any batch fails
+    pub post_run_cleanup: bool,
+    /// Batched: times to retry a batch's failed images before failing the run
+    pub max_retries_per_batch: usize,
+    /// Batched: backoff before the first retry, doubled for each retry after it up to
+    /// [`MAX_BACKOFF_MS`](crate::http_client::MAX_BACKOFF_MS)
+    pub retry_backoff_base_ms: u64,
+    /// Batched: pause between batches, like a job waiting on its work queue. Memory is
+    /// still sampled while paused, but the pause isn't counted in the run's time.
+    pub inter_batch_delay: Option<Duration>,
+    /// Times to reconnect after a failed connection before giving up on a download
+    pub connect_retries: u32,
+    /// Pause before each reconnect attempt
+    pub connect_retry_delay: Duration,
+    /// Times to retry a failed download request, including error statuses, before
+    /// giving up on the image
+    pub download_retries: usize,
+    /// Backoff before the first download retry, doubled for each retry after it
+    pub download_retry_base_delay_ms: u64,
+    /// Timeout and connection pooling for downloads, [`DownloadConfig::default`] when unset
+    pub download: Option<DownloadConfig>,
+    /// HTTP client shared by every download in a run. Processors build one from `download`
+    /// at the start of each run when this is unset.
+    pub http_client: Option<reqwest::Client>,
+    /// Naive, batched and sampled: download every image over `http_client` so connections
+    /// are reused, and only the images that opened one report `tls_handshake_ms`. That
+    /// takes a client with a [`ConnectTimingLayer`], like the one `with_shared_client`
+    /// builds. Off, each image opens and times its own connection.
+    pub reuse_connections: bool,
+    /// Streaming, batched and parallel: most downloads started per second, on top of the
+    /// concurrency limit
+    pub rate_limit: Option<f64>,
+    /// Bucket shared by every download in a run, built from `rate_limit` like `http_client`
+    pub rate_limiter: Option<RateLimiter>,
+    /// Write `manifest.jsonl` describing every file the streaming save stage wrote
+    pub output_manifest: bool,
+    /// Streaming: write each image's timings and size to `per_image.csv`
+    pub per_image: bool,
+    /// Let the naive processor download the next image while the current one is processed
+    pub semi_async_naive: bool,
+    /// Naive: record finished images so an interrupted run can pick up where it stopped
+    pub checkpoint: Option<CheckpointConfig>,
+    /// Streaming: delay each download by a random `[0, jitter_ms)` to stagger connections
+    pub jitter_ms: u64,
+    /// Unsharp mask applied after resizing, before the image is encoded
+    pub sharpen: Option<SharpenConfig>,
+    /// URLs the processors generate, Picsum 800x600 when unset
+    pub url_template: Option<UrlTemplate>,
+    /// Output size and filter, 256x256 Lanczos3 when unset
+    pub resize: Option<ResizeConfig>,
+    /// Encoder settings for saved images, JPEG quality 75 when unset
+    pub save: Option<SaveConfig>,
+    /// Naive, batched and streaming: backend that replaces the built-in decode → resize →
+    /// encode steps. Its output is saved as is, so `resize`, `sharpen` and `save` are ignored.
+    pub image_processor: Option<Arc<dyn ImageProcessor + Send + Sync>>,
+    /// How images are fitted to the output size
+    pub resize_mode: ResizeMode,
+    /// Streaming: abort downloads whose body grows past this many bytes
+    pub max_image_bytes: Option<usize>,
+    /// Streaming: reject responses whose `Content-Type` isn't `image/*` before reading them
+    pub verify_content_type: bool,
+    /// Streaming: reject bodies that don't start with a JPEG or PNG signature
+    pub verify_magic_bytes: bool,
+    /// Batched: size each batch from available memory instead of using the fixed size
+    pub adaptive_batch: Option<AdaptiveBatchConfig>,
+    /// Batched: start downloading the next batch before the current one has finished
+    pub prefetch: Option<PrefetchPolicy>,
+    /// Streaming: hand each processed image to this callback instead of writing it to disk
+    pub result_sink: Option<ResultSink>,
+    /// Streaming: URLs failing this check skip the download queue and go straight to dead letters
+    pub preflight_check: Option<PreflightCheck>,
+    /// Streaming: fetch images through a multipart batch endpoint where the server offers one
+    pub batch_download: Option<BatchDownloadConfig>,
+    /// Show a progress bar with throughput and ETA when stdout is a terminal
+    pub progress: bool,
+    /// Count finished images and their timings here as the run goes, for scraping
+    pub live_metrics: Option<Arc<LiveMetrics>>,
+    /// Streaming and batched: export this timeline's samples from the run in the stats
+    pub memory_timeline: Option<Arc<MemoryTimeline>>,
+    /// Streaming: stop starting new downloads once cancelled; images already downloading
+    /// still finish and are saved
+    pub cancellation: Option<CancellationToken>,
+    /// Streaming: zstd-compress downloaded bytes while they wait in the process channel
+    pub compress_channel: bool,
+    /// Streaming: pause new downloads while the process uses more than this many MB. Must be
+    /// above the pipeline's baseline usage, or downloads stay paused once nothing is in flight.
+    pub memory_limit_mb: Option<u64>,
+    /// Streaming: downloads wait for permits while this reads `true`. The pipeline drives it
+    /// from `memory_limit_mb`.
+    pub memory_pause: Option<watch::Receiver<bool>>,
+    /// Download and discard this many of the run's images before timing starts, so cold
+    /// connections and caches don't skew the first measurements
+    pub warmup_count: usize,
+    /// Streaming and parallel: keep downloads that came with an `ETag` here, and only take
+    /// the body again when the server says the cached copy has changed
+    pub disk_cache: Option<DiskCache>,
+    /// Naive, batched and streaming: what to do when a single image fails
+    pub error_policy: ErrorPolicy,
+}
+
+impl Default for ProcessorConfig {
+    fn default() -> Self {
+        ProcessorConfig {
+            download_concurrency: 8,
+            process_concurrency: 10,
+            save_concurrency: 1,
+            download_channel_capacity: 10,
+            process_channel_capacity: 10,
+            max_in_flight: None,
+            preserve_order: false,
+            full_memory_refresh: false,
+            sharding: None,
+            skip_existing: false,
+            post_run_cleanup: false,
+            max_retries_per_batch: 0,
+            retry_backoff_base_ms: 100,
+            inter_batch_delay: None,
+            connect_retries: 0,
+            connect_retry_delay: Duration::from_millis(500),
+            download_retries: 0,
+            download_retry_base_delay_ms: 100,
+            download: None,
+            http_client: None,
+            reuse_connections: true,
+            rate_limit: None,
+            rate_limiter: None,
+            output_manifest: false,
+            per_image: false,
+            semi_async_naive: false,
+            checkpoint: None,
+            jitter_ms: 0,
+            sharpen: None,
+            url_template: None,
+            resize: None,
+            save: None,
+            image_processor: None,
+            resize_mode: ResizeMode::Exact,
+            max_image_bytes: None,
+            verify_content_type: false,
+            verify_magic_bytes: false,
+            adaptive_batch: None,
+            prefetch: None,
+            result_sink: None,
+            preflight_check: None,
+            batch_download: None,
+            progress: false,
+            live_metrics: None,
+            memory_timeline: None,
+            cancellation: None,
+            compress_channel: false,
+            memory_limit_mb: None,
+            memory_pause: None,
+            warmup_count: 0,
+            disk_cache: None,
+            error_policy: ErrorPolicy::FailFast,
+        }
+    }
+}
+
+impl ProcessorConfig {
+    /// Copy of this config with `http_client` set, built from `download` unless one was
+    /// supplied, so every download in the run reuses the same connection pool. Likewise
+    /// `rate_limiter` from `rate_limit`, so the limit covers the whole run.
+    pub fn with_shared_client(&self) -> reqwest::Result<ProcessorConfig> {
+        let mut config = self.clone();
+        if config.http_client.is_none() {
+            // Timed so every image can report the connect its own download paid for
+            let client = client_builder(&config.download.unwrap_or_default())
+                .connector_layer(ConnectTimingLayer::new())
+                .build()?;
+            config.http_client = Some(client);
+        }
+        if config.rate_limiter.is_none() {
+            config.rate_limiter = config.rate_limit.map(RateLimiter::new);
+        }
+        Ok(config)
+    }
+
+    /// The run's shared client, or a fresh one from `download` when there is none
+    pub fn client(&self) -> reqwest::Result<reqwest::Client> {
+        match &self.http_client {
+            Some(client) => Ok(client.clone()),
+            None => build_client(&self.download.unwrap_or_default()),
+        }
+    }
+
+    /// One-line sketch of the streaming stages and the limits between them, e.g.
+    /// `URLs → [Download (concurrency=8)] →(ch:10)→ [Process (concurrency=10)] →(ch:10)→ [Save]`
+    pub fn render_diagram(&self) -> String {
+        let last_stage = if self.result_sink.is_some() {
+            "Sink".to_string()
+        } else if self.save_concurrency > 1 {
+            format!("Save (concurrency={})", self.save_concurrency)
+        } else {
+            "Save".to_string()
+        };
+        let mut diagram = format!(
+            "URLs → [Download (concurrency={})] →(ch:{})→ [Process (concurrency={})] →(ch:{})→ [{}]",
+            self.download_concurrency,
+            self.download_channel_capacity,
+            self.process_concurrency,
+            self.process_channel_capacity,
+            last_stage
+        );
+        if let Some(max_in_flight) = self.max_in_flight {
+            diagram.push_str(&format!(" (max_in_flight={})", max_in_flight));
+        }
+        diagram
+    }
+}
+
+/// How a run treats an image that fails to download, decode or save
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ErrorPolicy {
+    /// Stop the run with the first error. Streaming lets the images already in the pipeline
+    /// finish, then fails the run if any failed to download; images turned away by the
+    /// configured checks are only counted.
+    #[default]
+    FailFast,
+    /// Record the failure in the run's `errors` and carry on with the remaining images
+    CollectAndContinue,
+}
+
+/// Strategy for resizing to the configured output size
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ResizeMode {
+    /// Stretch to the exact size, distorting non-matching aspect ratios
+    #[default]
+    Exact,
+    /// Keep the aspect ratio and shrink to fit inside the size, so one side may come out
+    /// shorter
+    Fit,
+    /// Keep the aspect ratio, scale to cover the size and center-crop the overflow
+    Cover,
+    /// Keep the aspect ratio and fill the leftover bands with `color` (letterbox/pillarbox)
+    Padded { color: [u8; 3] },
+}
+
+/// Unsharp mask parameters. `radius` is the blur sigma, `threshold` the minimum
+/// brightness difference that gets sharpened, and `amount` scales the effect (1.0 = full)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SharpenConfig {
+    pub amount: f32,
+    pub radius: f32,
+    pub threshold: u8,
+}
+
+/// Async callback that receives finished images in place of the save stage
+#[derive(Clone)]
+pub struct ResultSink(Arc<dyn Fn(ImageResult) -> BoxFuture<'static, ()> + Send + Sync>);
+
+impl ResultSink {
+    pub fn new<F, Fut>(sink: F) -> Self
+    where
+        F: Fn(ImageResult) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        Self(Arc::new(move |result| Box::pin(sink(result))))
+    }
+
+    pub async fn call(&self, result: ImageResult) {
+        (self.0)(result).await
+    }
+}
+
+impl fmt::Debug for ResultSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ResultSink")
+    }
+}
+
+/// Synchronous URL predicate run before an image is queued for download
+#[derive(Clone)]
+pub struct PreflightCheck(Arc<dyn Fn(&str) -> bool + Send + Sync>);
+
+impl PreflightCheck {
+    pub fn new(check: impl Fn(&str) -> bool + Send + Sync + 'static) -> Self {
+        Self(Arc::new(check))
+    }
+
+    pub fn allows(&self, url: &str) -> bool {
+        (self.0)(url)
+    }
+}
+
+impl fmt::Debug for PreflightCheck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("PreflightCheck")
+    }
+}
+
+/// HTTP client settings for downloads, and the smallest image worth keeping
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DownloadConfig {
+    /// Limit on a whole request, from connecting until the body has been read
+    pub request_timeout: Duration,
+    /// Limit on establishing a connection, for servers that don't answer at all
+    pub connect_timeout: Duration,
+    /// Limit on each read from an open connection, for headers or body chunks that stall;
+    /// a slow body that keeps arriving never trips it
+    pub read_timeout: Duration,
+    /// Interval of TCP keepalive probes on open connections
+    pub tcp_keepalive: Duration,
+    /// Idle connections kept open per host for reuse
+    pub pool_max_idle_per_host: usize,
+    /// Decoded images narrower than this are rejected by the streaming process stage
+    pub min_width: Option<u32>,
+    /// Decoded images shorter than this are rejected by the streaming process stage
+    pub min_height: Option<u32>,
+    /// Whether concurrent downloads each get a connection or share one HTTP/2 connection
+    pub strategy: DownloadStrategy,
+}
+
+impl Default for DownloadConfig {
+    fn default() -> Self {
+        DownloadConfig {
+            request_timeout: Duration::from_secs(30),
+            connect_timeout: Duration::from_secs(10),
+            read_timeout: Duration::from_secs(15),
+            tcp_keepalive: Duration::from_secs(60),
+            pool_max_idle_per_host: 16,
+            min_width: None,
+            min_height: None,
+            strategy: DownloadStrategy::IndependentConnections,
+        }
+    }
+}
+
+/// How concurrent downloads share connections to a host
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DownloadStrategy {
+    /// HTTP/1.1, one connection per download in flight, pooled for reuse
+    #[default]
+    IndependentConnections,
+    /// HTTP/2 without negotiation, every download a stream on one connection. Only for
+    /// servers known to speak HTTP/2; others fail every request. Suits CDNs that throttle
+    /// connections per client.
+    Http2Multiplexed,
+}
+
+/// Endpoint that accepts a multipart POST of `url` fields and answers with a
+/// multipart response holding one image per part
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatchDownloadConfig {
+    pub endpoint: String,
+    pub max_urls_per_request: usize,
+}
+
+/// JSONL checkpoint of processed images, flushed to disk every `sync_interval` images
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CheckpointConfig {
+    pub path: PathBuf,
+    pub sync_interval: usize,
+}
+
+/// Batch sizes between `min_batch` and `max_batch`, chosen before each batch from the
+/// memory available at the time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AdaptiveBatchConfig {
+    pub min_batch: usize,
+    pub max_batch: usize,
+    /// Below this much available memory, batches drop to `min_batch`
+    pub memory_headroom_mb: u64,
+}
+
+impl AdaptiveBatchConfig {
+    /// `min_batch` while available memory is under the headroom, otherwise scaled linearly
+    /// with how much of the memory above the headroom is available
+    pub fn batch_size(&self, available_mb: u64, total_mb: u64) -> usize {
+        let min_batch = self.min_batch.max(1);
+        let max_batch = self.max_batch.max(min_batch);
+        if available_mb < self.memory_headroom_mb {
+            return min_batch;
+        }
+        let usable_mb = total_mb.saturating_sub(self.memory_headroom_mb).max(1);
+        let fraction =
+            ((available_mb - self.memory_headroom_mb) as f64 / usable_mb as f64).min(1.0);
+        min_batch + ((max_batch - min_batch) as f64 * fraction).round() as usize
+    }
+}
+
+/// Once only `trigger_at_remaining` downloads of the current set are outstanding,
+/// fetch up to `prefetch_count` URLs of the next set ahead of time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PrefetchPolicy {
+    pub trigger_at_remaining: usize,
+    pub prefetch_count: usize,
+}
+
+/// Nest files under `depth` directories named by `chars`-long slices of the filename
+/// E.g. depth=2, chars=2 → `output/ab/cd/abcdef...jpg`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutputSharding {
+    pub depth: usize,
+    pub chars: usize,
+}
+
+impl OutputSharding {
+    /// Directory that `filename` belongs in under `output_dir`
+    pub fn shard_dir(&self, output_dir: &Path, filename: &str) -> PathBuf {
+        let mut dir = output_dir.to_path_buf();
+        let prefix: Vec<char> = filename.chars().take(self.depth * self.chars).collect();
+        for chunk in prefix.chunks(self.chars.max(1)) {
+            dir.push(chunk.iter().collect::<String>());
+        }
+        dir
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_pipeline_diagram() {
+        let config = ProcessorConfig {
+            download_concurrency: 3,
+            process_concurrency: 5,
+            download_channel_capacity: 7,
+            process_channel_capacity: 2,
+            ..Default::default()
+        };
+        assert_eq!(
+            config.render_diagram(),
+            "URLs → [Download (concurrency=3)] →(ch:7)→ [Process (concurrency=5)] →(ch:2)→ [Save]"
+        );
+    }
+
+    #[test]
+    fn shards_by_filename_prefix() {
+        let sharding = OutputSharding { depth: 2, chars: 2 };
+        let dir = sharding.shard_dir(Path::new("output"), "abcdef.jpg");
+        assert_eq!(dir, Path::new("output/ab/cd"));
+    }
+
+    #[test]
+    fn scales_batches_with_available_memory() {
+        let adaptive = AdaptiveBatchConfig {
+            min_batch: 2,
+            max_batch: 12,
+            memory_headroom_mb: 1000,
+        };
+        assert_eq!(adaptive.batch_size(500, 11000), 2);
+        assert_eq!(adaptive.batch_size(1000, 11000), 2);
+        assert_eq!(adaptive.batch_size(6000, 11000), 7);
+        assert_eq!(adaptive.batch_size(11000, 11000), 12);
+    }
+}
@@ -0,0 +1,104 @@
+// src/error.rs
+
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+#[derive(Debug, Clone, PartialEq, Error, Serialize, Deserialize)]
+pub enum ProcessingError {
+    #[error("{url} exceeded the size limit after {bytes_received} bytes")]
+    ImageTooLarge { url: String, bytes_received: usize },
+    #[error("failed to download {url}: {message}")]
+    Download { url: String, message: String },
+    #[error("{url} is not an image: {reason}")]
+    NotAnImage { url: String, reason: String },
+    #[error("{url} was rejected by the preflight check")]
+    PreflightRejected { url: String },
+    #[error("{url} was skipped because the run was cancelled")]
+    Cancelled { url: String },
+}
+
+impl ProcessingError {
+    pub fn download(url: &str, error: impl std::fmt::Display) -> Self {
+        ProcessingError::Download {
+            url: url.to_string(),
+            message: error.to_string(),
+        }
+    }
+
+    /// Whether the image failed to download, as opposed to being turned away on purpose by
+    /// a configured check or skipped by cancellation
+    pub fn is_failure(&self) -> bool {
+        matches!(self, ProcessingError::Download { .. })
+    }
+
+    /// The URL of the image that was dropped
+    pub fn url(&self) -> &str {
+        match self {
+            ProcessingError::ImageTooLarge { url, .. }
+            | ProcessingError::Download { url, .. }
+            | ProcessingError::NotAnImage { url, .. }
+            | ProcessingError::PreflightRejected { url }
+            | ProcessingError::Cancelled { url } => url,
+        }
+    }
+}
+
+/// A whole streaming stage that failed or panicked, as opposed to a single dropped image
+#[derive(Debug, Clone, PartialEq, Error, Serialize, Deserialize)]
+pub enum PipelineError {
+    #[error("{stage} stage failed: {message}")]
+    Failed { stage: String, message: String },
+    #[error("{stage} stage panicked: {message}")]
+    Panicked { stage: String, message: String },
+}
+
+impl PipelineError {
+    /// Name of the stage, e.g. `download`
+    pub fn stage(&self) -> &str {
+        match self {
+            PipelineError::Failed { stage, .. } | PipelineError::Panicked { stage, .. } => stage,
+        }
+    }
+}
+
+/// Images that were dropped from a run, kept for inspection instead of aborting it.
+/// Clones share the same queue.
+#[derive(Debug, Clone, Default)]
+pub struct DeadLetterQueue {
+    errors: Arc<Mutex<Vec<ProcessingError>>>,
+}
+
+impl DeadLetterQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&self, error: ProcessingError) {
+        self.errors.lock().unwrap().push(error);
+    }
+
+    pub fn len(&self) -> usize {
+        self.errors.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of queued errors matching `predicate`
+    pub fn count(&self, predicate: impl Fn(&ProcessingError) -> bool) -> usize {
+        self.errors
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|e| predicate(e))
+            .count()
+    }
+
+    /// Snapshot of everything queued so far
+    pub fn errors(&self) -> Vec<ProcessingError> {
+        self.errors.lock().unwrap().clone()
+    }
+}
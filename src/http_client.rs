@@ -0,0 +1,395 @@
+// src/http_client.rs
+
+use std::{
+    cell::Cell,
+    collections::HashMap,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use rand::Rng;
+use tower::{Layer, Service};
+use tracing::warn;
+
+use crate::config::{DownloadConfig, DownloadStrategy};
+
+/// Client builder with the timeouts, keepalive, pool limits and connection strategy from
+/// `config` applied
+pub fn client_builder(config: &DownloadConfig) -> reqwest::ClientBuilder {
+    let builder = reqwest::Client::builder()
+        .timeout(config.request_timeout)
+        .connect_timeout(config.connect_timeout)
+        .read_timeout(config.read_timeout)
+        .tcp_keepalive(config.tcp_keepalive)
+        // Connection reads and writes are only logged at trace level
+        .connection_verbose(true);
+    match config.strategy {
+        DownloadStrategy::IndependentConnections => {
+            builder.pool_max_idle_per_host(config.pool_max_idle_per_host)
+        }
+        // The pool hands the one HTTP/2 connection to every request rather than opening more
+        DownloadStrategy::Http2Multiplexed => {
+            builder.http2_prior_knowledge().pool_max_idle_per_host(1)
+        }
+    }
+}
+
+pub fn build_client(config: &DownloadConfig) -> reqwest::Result<reqwest::Client> {
+    client_builder(config).build()
+}
+
+tokio::task_local! {
+    /// Connect time of the connection opened for the request a [`time_connect`] is awaiting
+    static REQUEST_CONNECT: Cell<Option<Duration>>;
+}
+
+/// Await `request`, also returning how long it spent opening a new connection through a
+/// [`ConnectTimingLayer`]. `None` when it reused a pooled connection, so on a client shared
+/// across concurrent requests each one only sees the connection it opened itself.
+pub async fn time_connect<T>(request: impl Future<Output = T>) -> (T, Option<Duration>) {
+    REQUEST_CONNECT
+        .scope(Cell::new(None), async {
+            let output = request.await;
+            (output, REQUEST_CONNECT.with(Cell::get))
+        })
+        .await
+}
+
+/// Connector layer that records how long new connections take to establish
+/// (DNS + TCP connect + TLS handshake), both as the client's latest connect and for the
+/// request that opened it, see [`time_connect`]. Pooled connections skip the connector,
+/// so nothing is recorded when a connection is reused.
+#[derive(Clone, Default)]
+pub struct ConnectTimingLayer {
+    last_connect: Arc<Mutex<Option<Duration>>>,
+}
+
+impl ConnectTimingLayer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Take the most recent connect duration, leaving `None` behind
+    pub fn take_last(&self) -> Option<Duration> {
+        self.last_connect.lock().unwrap().take()
+    }
+}
+
+impl<S> Layer<S> for ConnectTimingLayer {
+    type Service = ConnectTiming<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ConnectTiming {
+            inner,
+            last_connect: Arc::clone(&self.last_connect),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ConnectTiming<S> {
+    inner: S,
+    last_connect: Arc<Mutex<Option<Duration>>>,
+}
+
+impl<S, R> Service<R> for ConnectTiming<S>
+where
+    S: Service<R>,
+    S::Future: Send + 'static,
+    S::Response: Send + 'static,
+    S::Error: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<S::Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: R) -> Self::Future {
+        let last_connect = Arc::clone(&self.last_connect);
+        let connecting = self.inner.call(req);
+
+        Box::pin(async move {
+            let start = Instant::now();
+            let conn = connecting.await?;
+            let elapsed = start.elapsed();
+            *last_connect.lock().unwrap() = Some(elapsed);
+            // Unset when the connect outlived its request and finishes for the pool
+            let _ = REQUEST_CONNECT.try_with(|connect| connect.set(Some(elapsed)));
+            Ok(conn)
+        })
+    }
+}
+
+/// Parse a `Server-Timing` header into metric name → duration in ms,
+/// e.g. `cdn;dur=10.5, origin;dur=45.2`. Metrics without a `dur` are skipped.
+pub fn parse_server_timing(header: &str) -> HashMap<String, f64> {
+    header
+        .split(',')
+        .filter_map(|metric| {
+            let mut params = metric.split(';').map(str::trim);
+            let name = params.next().filter(|name| !name.is_empty())?;
+            let dur = params.find_map(|param| {
+                let (key, value) = param.split_once('=')?;
+                key.trim()
+                    .eq_ignore_ascii_case("dur")
+                    .then(|| value.trim().trim_matches('"').parse().ok())
+                    .flatten()
+            })?;
+            Some((name.to_string(), dur))
+        })
+        .collect()
+}
+
+/// `Server-Timing` entries from `response`, `None` if the header is absent
+pub fn server_timing(response: &reqwest::Response) -> Option<HashMap<String, f64>> {
+    let header = response.headers().get("server-timing")?.to_str().ok()?;
+    Some(parse_server_timing(header))
+}
+
+/// Longest wait any single retry backs off for
+pub const MAX_BACKOFF_MS: u64 = 60_000;
+
+/// `base_ms * 2^exponent`, capped at [`MAX_BACKOFF_MS`] rather than overflowing
+pub fn exponential_backoff_ms(base_ms: u64, exponent: u32) -> u64 {
+    2u64.checked_pow(exponent)
+        .map_or(u64::MAX, |factor| base_ms.saturating_mul(factor))
+        .min(MAX_BACKOFF_MS)
+}
+
+/// Run `request` for `url` until it succeeds, retrying up to `max_retries` times on any
+/// error. Retry `n` first waits `base_delay_ms * 2^(n - 1)`, capped at [`MAX_BACKOFF_MS`],
+/// plus up to `base_delay_ms` of jitter. Returns the result along with the number of retries
+/// it took.
+pub async fn download_with_retry<T, F, Fut>(
+    url: &str,
+    max_retries: usize,
+    base_delay_ms: u64,
+    mut request: F,
+) -> reqwest::Result<(T, usize)>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = reqwest::Result<T>>,
+{
+    let mut retries = 0;
+    loop {
+        match request().await {
+            Err(e) if retries < max_retries => {
+                let jitter_ms = rand::rng().random_range(0..base_delay_ms.max(1));
+                let delay_ms =
+                    exponential_backoff_ms(base_delay_ms, retries.try_into().unwrap_or(u32::MAX))
+                        .saturating_add(jitter_ms);
+                retries += 1;
+                warn!(url, retries, delay_ms, error = %e, "download failed, retrying");
+                tokio::time::sleep(Duration::from_millis(delay_ms)).await;
+            }
+            res => return res.map(|value| (value, retries)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+    use wiremock::{matchers::any, Mock, MockServer, ResponseTemplate};
+
+    #[tokio::test]
+    async fn records_only_new_connections() {
+        let server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&server)
+            .await;
+
+        let timing = ConnectTimingLayer::new();
+        let client = reqwest::Client::builder()
+            .connector_layer(timing.clone())
+            .build()
+            .unwrap();
+
+        client.get(server.uri()).send().await.unwrap();
+        assert!(timing.take_last().is_some());
+
+        client.get(server.uri()).send().await.unwrap();
+        assert!(timing.take_last().is_none());
+    }
+
+    #[tokio::test]
+    async fn attributes_connects_to_requests() {
+        let server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_millis(50)))
+            .mount(&server)
+            .await;
+
+        let client = reqwest::Client::builder()
+            .connector_layer(ConnectTimingLayer::new())
+            .build()
+            .unwrap();
+        let request = || time_connect(client.get(server.uri()).send());
+
+        // Two at once open a connection each, which a third then reuses
+        let ((first, first_connect), (second, second_connect)) = tokio::join!(request(), request());
+        first.unwrap();
+        second.unwrap();
+        assert!(first_connect.is_some());
+        assert!(second_connect.is_some());
+        let (third, third_connect) = request().await;
+        third.unwrap();
+        assert!(third_connect.is_none());
+
+        // Without the layer nothing is timed
+        let (response, connect) = time_connect(reqwest::get(server.uri())).await;
+        response.unwrap();
+        assert!(connect.is_none());
+    }
+
+    #[tokio::test]
+    async fn multiplexes_over_http2() {
+        let server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_millis(50)))
+            .mount(&server)
+            .await;
+
+        let timing = ConnectTimingLayer::new();
+        let client = client_builder(&DownloadConfig {
+            strategy: DownloadStrategy::Http2Multiplexed,
+            ..Default::default()
+        })
+        .connector_layer(timing.clone())
+        .build()
+        .unwrap();
+
+        let responses =
+            futures::future::join_all((0..5).map(|_| client.get(server.uri()).send())).await;
+        for response in responses {
+            assert_eq!(response.unwrap().version(), reqwest::Version::HTTP_2);
+        }
+        // Once the burst is done, later requests go out on the same connection
+        assert!(timing.take_last().is_some());
+        client.get(server.uri()).send().await.unwrap();
+        assert!(timing.take_last().is_none());
+    }
+
+    #[tokio::test]
+    async fn retries_failed_downloads() {
+        let server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(2)
+            .mount(&server)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_string("ok"))
+            .mount(&server)
+            .await;
+
+        let get = || async { reqwest::get(server.uri()).await?.error_for_status() };
+        assert!(download_with_retry(&server.uri(), 0, 1, get).await.is_err());
+
+        let (response, retries) = download_with_retry(&server.uri(), 3, 1, get).await.unwrap();
+        assert_eq!(retries, 1);
+        assert_eq!(response.text().await.unwrap(), "ok");
+    }
+
+    #[tokio::test]
+    async fn times_out_slow_requests() {
+        let server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(5)))
+            .mount(&server)
+            .await;
+
+        let client = build_client(&DownloadConfig {
+            request_timeout: Duration::from_millis(1),
+            ..Default::default()
+        })
+        .unwrap();
+        let start = Instant::now();
+        let err = client.get(server.uri()).send().await.unwrap_err();
+
+        assert!(err.is_timeout());
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    /// Answer every request with a 2-byte body, waiting `header_delay` before the status
+    /// line and `body_delay` between the headers and the body
+    async fn slow_server(header_delay: Duration, body_delay: Duration) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut request = [0; 1024];
+                    let _ = socket.read(&mut request).await;
+                    tokio::time::sleep(header_delay).await;
+                    let headers = b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\n";
+                    socket.write_all(headers).await?;
+                    socket.flush().await?;
+                    tokio::time::sleep(body_delay).await;
+                    socket.write_all(b"ok").await
+                });
+            }
+        });
+        url
+    }
+
+    #[tokio::test]
+    async fn times_out_stalled_reads() {
+        let quick = Duration::from_millis(100);
+        let slow = Duration::from_millis(400);
+        let client = |connect_timeout, read_timeout| {
+            build_client(&DownloadConfig {
+                connect_timeout,
+                read_timeout,
+                ..Default::default()
+            })
+            .unwrap()
+        };
+
+        // Slow headers: the connection is up, so only the read timeout can fire
+        let url = slow_server(slow, Duration::ZERO).await;
+        let response = client(quick, Duration::from_secs(5)).get(&url).send().await;
+        assert_eq!(response.unwrap().text().await.unwrap(), "ok");
+        let err = client(Duration::from_secs(5), quick)
+            .get(&url)
+            .send()
+            .await
+            .unwrap_err();
+        assert!(err.is_timeout());
+
+        // Slow body: headers arrive in time and the read timeout fires while reading the body
+        let url = slow_server(Duration::ZERO, slow).await;
+        let response = client(quick, quick).get(&url).send().await.unwrap();
+        assert!(response.text().await.unwrap_err().is_timeout());
+        let response = client(quick, Duration::from_secs(5)).get(&url).send().await;
+        assert_eq!(response.unwrap().text().await.unwrap(), "ok");
+    }
+
+    #[test]
+    fn caps_exponential_backoff() {
+        assert_eq!(exponential_backoff_ms(100, 0), 100);
+        assert_eq!(exponential_backoff_ms(100, 3), 800);
+        assert_eq!(exponential_backoff_ms(100, 20), MAX_BACKOFF_MS);
+        assert_eq!(exponential_backoff_ms(100, 70), MAX_BACKOFF_MS);
+        assert_eq!(exponential_backoff_ms(u64::MAX, 1), MAX_BACKOFF_MS);
+    }
+
+    #[test]
+    fn parses_server_timing() {
+        let timing = parse_server_timing("cdn;dur=10.5, origin;desc=\"Origin\";dur=45.2, miss");
+        assert_eq!(timing.len(), 2);
+        assert_eq!(timing["cdn"], 10.5);
+        assert_eq!(timing["origin"], 45.2);
+    }
+}
@@ -1,107 +1,1339 @@
 // src/image_processor.rs
 
 use anyhow::Result;
-use image;
+use futures::future::BoxFuture;
+use image::{
+    codecs::jpeg::JpegEncoder, imageops::FilterType, ColorType, DynamicImage, ImageFormat,
+};
 use sha2::{Digest, Sha256};
 use std::{
-    cmp::max,
-    path::Path,
-    sync::{
-        atomic::{AtomicU64, Ordering},
-        Arc,
-    },
-    time::{Duration, Instant},
+    collections::HashMap,
+    fmt,
+    fs::{self, File},
+    io::{BufWriter, Cursor, Seek, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Instant,
 };
-use tokio::{spawn, time::sleep};
+use tokio::task::JoinHandle;
+use tracing::info;
 
-use crate::memory_monitor::MemoryMonitor;
+use crate::{
+    config::{ProcessorConfig, ResizeMode, SharpenConfig},
+    http_client::{
+        client_builder, download_with_retry, server_timing, time_connect, ConnectTimingLayer,
+    },
+    memory_monitor::{spawn_peak_tracker, MemoryMonitor, PeakReadings},
+    output_sink::SinkWriter,
+    url_generator::InputSource,
+};
 
 #[derive(Debug, Clone)]
 pub struct ImageMetrics {
     pub url: String,
     pub download_ms: u64,
+    /// Time to open a new connection (DNS, TCP connect and, for HTTPS, the TLS handshake),
+    /// `None` if a pooled connection was reused. Over a shared client only the image whose
+    /// download opened the connection reports it.
+    pub tls_handshake_ms: Option<u64>,
+    /// Server-side durations (ms) reported through the `Server-Timing` header
+    pub server_timing: Option<HashMap<String, f64>>,
     pub decode_ms: u64,
+    /// Resize time, including any sharpening
     pub resize_ms: u64,
     pub save_ms: u64,
     pub bytes_downloaded: usize,
+    /// Size of the encoded output, which varies with `SaveConfig::format`
+    pub bytes_saved: u64,
+    /// `bytes_saved / bytes_downloaded`, 0.0 when nothing was downloaded
+    pub compression_ratio: f64,
     pub peak_memory_mb: u64,
+    /// Highest CPU usage sampled while the image was processed, as a percentage of one core.
+    /// The first sample only starts the measurement, so images that finish within one
+    /// sampling interval report 0 and the naive runs track CPU over the whole run instead.
+    pub peak_cpu_percent: f32,
+    /// Time the memory monitor spent sampling memory while the image was processed
+    pub monitor_overhead_us: u64,
+    pub output_path: PathBuf,
 }
 
-/// Process a single image: download → decode → resize → save
-pub async fn process_single_image(url: &str, output_dir: &Path) -> Result<ImageMetrics> {
-    let peak_memory_mb = Arc::new(AtomicU64::new(0));
-    let peak_clone = Arc::clone(&peak_memory_mb);
-
-    let monitor_handle = spawn(async move {
-        let mut memory_monitor = MemoryMonitor::new();
-        loop {
-            let curr_usage = memory_monitor.current_usage_mb();
-            peak_clone.store(
-                max(curr_usage, peak_clone.load(Ordering::Relaxed)),
-                Ordering::Relaxed,
-            );
-            sleep(Duration::from_millis(100)).await;
+impl ImageMetrics {
+    pub fn builder() -> ImageMetricsBuilder {
+        ImageMetricsBuilder::default()
+    }
+}
+
+/// Collects per-stage timings as they are measured; `url` and download are required
+#[derive(Debug, Clone, Default)]
+pub struct ImageMetricsBuilder {
+    url: Option<String>,
+    download: Option<(u64, usize)>,
+    tls_handshake_ms: Option<u64>,
+    server_timing: Option<HashMap<String, f64>>,
+    decode_ms: u64,
+    resize_ms: u64,
+    save_ms: u64,
+    bytes_saved: u64,
+    peak_memory_mb: u64,
+    peak_cpu_percent: f32,
+    monitor_overhead_us: u64,
+    output_path: PathBuf,
+}
+
+impl ImageMetricsBuilder {
+    pub fn with_url(mut self, url: &str) -> Self {
+        self.url = Some(url.to_string());
+        self
+    }
+
+    pub fn with_download(mut self, ms: u64, bytes: usize) -> Self {
+        self.download = Some((ms, bytes));
+        self
+    }
+
+    pub fn with_tls_handshake(mut self, ms: Option<u64>) -> Self {
+        self.tls_handshake_ms = ms;
+        self
+    }
+
+    pub fn with_server_timing(mut self, timing: Option<HashMap<String, f64>>) -> Self {
+        self.server_timing = timing;
+        self
+    }
+
+    pub fn with_decode(mut self, ms: u64) -> Self {
+        self.decode_ms = ms;
+        self
+    }
+
+    pub fn with_resize(mut self, ms: u64) -> Self {
+        self.resize_ms = ms;
+        self
+    }
+
+    pub fn with_save(mut self, ms: u64) -> Self {
+        self.save_ms = ms;
+        self
+    }
+
+    pub fn with_bytes_saved(mut self, bytes: u64) -> Self {
+        self.bytes_saved = bytes;
+        self
+    }
+
+    pub fn with_peak_memory(mut self, mb: u64) -> Self {
+        self.peak_memory_mb = mb;
+        self
+    }
+
+    pub fn with_peak_cpu(mut self, percent: f32) -> Self {
+        self.peak_cpu_percent = percent;
+        self
+    }
+
+    pub fn with_monitor_overhead(mut self, us: u64) -> Self {
+        self.monitor_overhead_us = us;
+        self
+    }
+
+    /// Peak memory, CPU and monitor overhead from a [`spawn_peak_tracker`] task
+    pub fn with_peaks(self, peak: &PeakReadings) -> Self {
+        self.with_peak_memory(peak.memory_mb())
+            .with_peak_cpu(peak.cpu_percent())
+            .with_monitor_overhead(peak.monitor_us())
+    }
+
+    pub fn with_output_path(mut self, path: PathBuf) -> Self {
+        self.output_path = path;
+        self
+    }
+
+    pub fn build(self) -> Result<ImageMetrics> {
+        let url = self
+            .url
+            .ok_or_else(|| anyhow::anyhow!("image metrics missing url"))?;
+        let (download_ms, bytes_downloaded) = self
+            .download
+            .ok_or_else(|| anyhow::anyhow!("image metrics for {} missing download", url))?;
+
+        Ok(ImageMetrics {
+            url,
+            download_ms,
+            tls_handshake_ms: self.tls_handshake_ms,
+            server_timing: self.server_timing,
+            decode_ms: self.decode_ms,
+            resize_ms: self.resize_ms,
+            save_ms: self.save_ms,
+            bytes_downloaded,
+            bytes_saved: self.bytes_saved,
+            compression_ratio: compression_ratio(self.bytes_saved, bytes_downloaded),
+            peak_memory_mb: self.peak_memory_mb,
+            peak_cpu_percent: self.peak_cpu_percent,
+            monitor_overhead_us: self.monitor_overhead_us,
+            output_path: self.output_path,
+        })
+    }
+}
+
+/// Saved over downloaded size, 0.0 when nothing was downloaded
+pub fn compression_ratio(bytes_saved: u64, bytes_downloaded: usize) -> f64 {
+    if bytes_downloaded == 0 {
+        return 0.0;
+    }
+    bytes_saved as f64 / bytes_downloaded as f64
+}
+
+/// File format of saved thumbnails
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// JPEG at this quality, from 1 to 100
+    Jpeg(u8),
+    Png,
+    /// Lossless WebP, the only WebP encoding the `image` crate offers
+    #[cfg(feature = "webp")]
+    WebP,
+}
+
+impl Default for OutputFormat {
+    fn default() -> Self {
+        OutputFormat::Jpeg(75)
+    }
+}
+
+impl OutputFormat {
+    /// Extension of saved files, without the dot
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Jpeg(_) => "jpg",
+            OutputFormat::Png => "png",
+            #[cfg(feature = "webp")]
+            OutputFormat::WebP => "webp",
+        }
+    }
+}
+
+/// Encoder settings for saved thumbnails
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SaveConfig {
+    /// `Jpeg(75)` by default
+    pub format: OutputFormat,
+}
+
+/// Encode `img` as `format` into `writer`
+pub(crate) fn encode<W: Write + Seek>(
+    img: &DynamicImage,
+    writer: &mut W,
+    format: OutputFormat,
+) -> Result<()> {
+    match format {
+        OutputFormat::Jpeg(quality) => {
+            img.write_with_encoder(JpegEncoder::new_with_quality(writer, quality))?
+        }
+        OutputFormat::Png => img.write_to(writer, ImageFormat::Png)?,
+        // The WebP encoder only takes 8-bit RGB(A)
+        #[cfg(feature = "webp")]
+        OutputFormat::WebP => match img {
+            DynamicImage::ImageRgb8(_) | DynamicImage::ImageRgba8(_) => {
+                img.write_to(writer, ImageFormat::WebP)?
+            }
+            img => DynamicImage::ImageRgba8(img.to_rgba8()).write_to(writer, ImageFormat::WebP)?,
+        },
+    }
+    Ok(())
+}
+
+/// Encode `img` to `path.tmp` alongside `path`, then rename it into place so a killed
+/// process never leaves a half-written file at `path`. The format follows `save.format`.
+/// Returns the size of the saved file.
+pub fn save_atomic(img: &DynamicImage, path: &Path, save: &SaveConfig) -> Result<u64> {
+    write_atomic(path, |writer| encode(img, writer, save.format))
+}
+
+/// Like [`save_atomic`], for bytes that are already encoded
+pub(crate) fn save_bytes_atomic(bytes: &[u8], path: &Path) -> Result<u64> {
+    write_atomic(path, |writer| Ok(writer.write_all(bytes)?))
+}
+
+fn write_atomic(
+    path: &Path,
+    write: impl FnOnce(&mut BufWriter<File>) -> Result<()>,
+) -> Result<u64> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+
+    let written = File::create(&tmp)
+        .map_err(anyhow::Error::from)
+        .and_then(|file| {
+            let mut writer = BufWriter::new(file);
+            write(&mut writer)?;
+            Ok(writer.into_inner()?.metadata()?.len())
+        });
+    let bytes = match written {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            let _ = fs::remove_file(&tmp);
+            return Err(e);
+        }
+    };
+    fs::rename(&tmp, path)?;
+    Ok(bytes)
+}
+
+/// SHA256-based name for `url`, relative to the output directory and including any shard
+/// directories
+pub fn output_name(url: &str, config: &ProcessorConfig) -> PathBuf {
+    let extension = config.save.unwrap_or_default().format.extension();
+    let filename = format!("{:x}.{}", Sha256::digest(url.as_bytes()), extension);
+    match config.sharding {
+        Some(sharding) => sharding.shard_dir(Path::new(""), &filename).join(filename),
+        None => PathBuf::from(filename),
+    }
+}
+
+/// With `config.skip_existing` set, drop URLs whose output is already in `output_dir`.
+/// Returns the URLs left to process and how many were skipped.
+pub fn skip_existing(
+    urls: Vec<String>,
+    output_dir: &Path,
+    config: &ProcessorConfig,
+) -> (Vec<String>, usize) {
+    if !config.skip_existing {
+        return (urls, 0);
+    }
+    let count = urls.len();
+    let urls: Vec<String> = urls
+        .into_iter()
+        .filter(|url| !output_dir.join(output_name(url, config)).exists())
+        .collect();
+    let skipped = count - urls.len();
+    if skipped > 0 {
+        info!(skipped, "skipping images that are already saved");
+    }
+    (urls, skipped)
+}
+
+/// Output path for `url` under `output_dir`, creating shard directories on demand
+pub fn output_path(url: &str, output_dir: &Path, config: &ProcessorConfig) -> Result<PathBuf> {
+    let path = output_dir.join(output_name(url, config));
+    if let (Some(_), Some(dir)) = (config.sharding, path.parent()) {
+        fs::create_dir_all(dir)?;
+    }
+    Ok(path)
+}
+
+/// Output size and sampling filter for resized images
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ResizeConfig {
+    pub width: u32,
+    pub height: u32,
+    pub filter: FilterType,
+}
+
+impl Default for ResizeConfig {
+    fn default() -> Self {
+        ResizeConfig {
+            width: 256,
+            height: 256,
+            filter: FilterType::Lanczos3,
+        }
+    }
+}
+
+/// Resize `img` to `resize.width`×`resize.height` according to `mode`. Every mode but
+/// [`ResizeMode::Fit`] produces exactly that size.
+pub fn resize_to(img: &DynamicImage, resize: &ResizeConfig, mode: ResizeMode) -> DynamicImage {
+    match mode {
+        ResizeMode::Exact => img.resize_exact(resize.width, resize.height, resize.filter),
+        ResizeMode::Fit => img.resize(resize.width, resize.height, resize.filter),
+        ResizeMode::Cover => resize_to_cover(img, resize.width, resize.height, resize.filter),
+        ResizeMode::Padded { color } => {
+            resize_with_padding(img, resize.width, resize.height, resize.filter, color)
         }
+    }
+}
+
+/// Scale `img` without distortion until it covers `width`×`height`, then crop the centered
+/// `width`×`height` region
+pub fn resize_to_cover(
+    img: &DynamicImage,
+    width: u32,
+    height: u32,
+    filter: FilterType,
+) -> DynamicImage {
+    let scale = f64::max(
+        width as f64 / img.width() as f64,
+        height as f64 / img.height() as f64,
+    );
+    let cover_width = ((img.width() as f64 * scale).ceil() as u32).max(width);
+    let cover_height = ((img.height() as f64 * scale).ceil() as u32).max(height);
+    let resized = img.resize_exact(cover_width, cover_height, filter);
+    resized.crop_imm(
+        (cover_width - width) / 2,
+        (cover_height - height) / 2,
+        width,
+        height,
+    )
+}
+
+/// Fit `img` inside `width`×`height` without distortion, centering it and filling the
+/// leftover bands on either side with `padding_color`
+pub fn resize_with_padding(
+    img: &DynamicImage,
+    width: u32,
+    height: u32,
+    filter: FilterType,
+    padding_color: [u8; 3],
+) -> DynamicImage {
+    let scale = f64::min(
+        width as f64 / img.width() as f64,
+        height as f64 / img.height() as f64,
+    );
+    let fit_width = ((img.width() as f64 * scale).round() as u32).clamp(1, width);
+    let fit_height = ((img.height() as f64 * scale).round() as u32).clamp(1, height);
+    let resized = img.resize_exact(fit_width, fit_height, filter).to_rgb8();
+
+    let mut canvas = image::RgbImage::from_pixel(width, height, image::Rgb(padding_color));
+    image::imageops::overlay(
+        &mut canvas,
+        &resized,
+        ((width - fit_width) / 2) as i64,
+        ((height - fit_height) / 2) as i64,
+    );
+    DynamicImage::ImageRgb8(canvas)
+}
+
+/// [`make_contact_sheet_with`] on an opaque black background
+pub fn make_contact_sheet(
+    image_paths: &[PathBuf],
+    columns: usize,
+    output_path: &Path,
+) -> Result<()> {
+    make_contact_sheet_with(image_paths, columns, output_path, [0, 0, 0, 255])
+}
+
+/// Tile the images at `image_paths` in row-major order, `columns` to a row, into one image
+/// saved to `output_path` in the format its extension names. Every cell is as big as the
+/// largest image, smaller ones sit in its top-left corner, and cells left over in the last
+/// row are filled with `background` (RGBA).
+pub fn make_contact_sheet_with(
+    image_paths: &[PathBuf],
+    columns: usize,
+    output_path: &Path,
+    background: [u8; 4],
+) -> Result<()> {
+    anyhow::ensure!(columns > 0, "contact sheet needs at least one column");
+    anyhow::ensure!(!image_paths.is_empty(), "no images for the contact sheet");
+    let thumbnails = image_paths
+        .iter()
+        .map(|path| Ok(image::open(path)?.to_rgba8()))
+        .collect::<Result<Vec<_>>>()?;
+    let cell_width = thumbnails.iter().map(|img| img.width()).max().unwrap_or(0);
+    let cell_height = thumbnails.iter().map(|img| img.height()).max().unwrap_or(0);
+    let rows = thumbnails.len().div_ceil(columns);
+
+    let mut sheet = image::RgbaImage::from_pixel(
+        cell_width * columns as u32,
+        cell_height * rows as u32,
+        image::Rgba(background),
+    );
+    for (i, thumbnail) in thumbnails.iter().enumerate() {
+        let (row, column) = (i / columns, i % columns);
+        image::imageops::overlay(
+            &mut sheet,
+            thumbnail,
+            column as i64 * cell_width as i64,
+            row as i64 * cell_height as i64,
+        );
+    }
+    // JPEG has no alpha channel to encode
+    let sheet = DynamicImage::ImageRgba8(sheet);
+    if ImageFormat::from_path(output_path)? == ImageFormat::Jpeg {
+        sheet.to_rgb8().save(output_path)?;
+    } else {
+        sheet.save(output_path)?;
+    }
+    info!(images = thumbnails.len(), path = %output_path.display(), "saved contact sheet");
+    Ok(())
+}
+
+/// Apply an unsharp mask, blending with the original when `amount` is below 1.0. The
+/// result keeps the source's colour type, alpha included.
+pub fn sharpen(img: &DynamicImage, config: &SharpenConfig) -> DynamicImage {
+    let sharpened = img.unsharpen(config.radius, config.threshold as i32);
+    if (config.amount - 1.0).abs() < f32::EPSILON {
+        return sharpened;
+    }
+
+    // Blend in floating point, alpha included, then go back to the source's colour type
+    let original = img.to_rgba32f();
+    let mut blended = sharpened.to_rgba32f();
+    for (orig, sharp) in original.iter().zip(blended.iter_mut()) {
+        *sharp = (orig + config.amount * (*sharp - orig)).clamp(0.0, 1.0);
+    }
+    let blended = DynamicImage::ImageRgba32F(blended);
+    match img.color() {
+        ColorType::L8 => blended.to_luma8().into(),
+        ColorType::La8 => blended.to_luma_alpha8().into(),
+        ColorType::Rgb8 => blended.to_rgb8().into(),
+        ColorType::Rgba8 => blended.to_rgba8().into(),
+        ColorType::L16 => blended.to_luma16().into(),
+        ColorType::La16 => blended.to_luma_alpha16().into(),
+        ColorType::Rgb16 => blended.to_rgb16().into(),
+        ColorType::Rgba16 => blended.to_rgba16().into(),
+        ColorType::Rgb32F => blended.to_rgb32f().into(),
+        _ => blended,
+    }
+}
+
+/// Copy `img` into an `[height, width, 3]` RGB array
+#[cfg(feature = "ndarray")]
+pub fn image_to_ndarray(img: &DynamicImage) -> ndarray::Array3<u8> {
+    let rgb = img.to_rgb8();
+    let (width, height) = rgb.dimensions();
+    ndarray::Array3::from_shape_vec((height as usize, width as usize, 3), rgb.into_raw())
+        .expect("RGB buffer always holds height * width * 3 bytes")
+}
+
+/// Build an RGB image from an `[height, width, 3]` array
+#[cfg(feature = "ndarray")]
+pub fn ndarray_to_image(arr: ndarray::ArrayView3<u8>) -> DynamicImage {
+    let (height, width, _) = arr.dim();
+    let buffer = image::RgbImage::from_fn(width as u32, height as u32, |x, y| {
+        let (x, y) = (x as usize, y as usize);
+        image::Rgb([arr[[y, x, 0]], arr[[y, x, 1]], arr[[y, x, 2]]])
     });
+    DynamicImage::ImageRgb8(buffer)
+}
 
-    let download_start = Instant::now();
-    let img_bytes = reqwest::get(url).await?.bytes().await?;
-    let download_end = Instant::now();
-    let download_ms = (download_end - download_start).as_millis() as u64;
+/// Rough processing cost of an encoded image, read from its header without decoding it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ImageComplexity {
+    /// Relative cost; only meaningful compared against other scores
+    pub score: f64,
+    pub estimated_resize_ms: u64,
+}
+
+/// Approximate Lanczos3 cost of resizing one megapixel of source image to a thumbnail
+const RESIZE_MS_PER_MEGAPIXEL: f64 = 20.0;
+
+/// Estimate how expensive `bytes` will be to decode and resize. Combines the source
+/// dimensions, how many bits each pixel takes to encode (flat images compress well, detailed
+/// ones don't) and, for JPEGs, how fine the quantization tables are.
+pub fn estimate_complexity(bytes: &[u8]) -> Result<ImageComplexity> {
+    let (width, height, avg_quantization) = match parse_jpeg_header(bytes) {
+        Some(header) => header,
+        None => {
+            let (width, height) = image::ImageReader::new(std::io::Cursor::new(bytes))
+                .with_guessed_format()?
+                .into_dimensions()?;
+            (width, height, None)
+        }
+    };
+
+    let pixels = (width as f64 * height as f64).max(1.0);
+    let megapixels = pixels / 1_000_000.0;
+    let bits_per_pixel = bytes.len() as f64 * 8.0 / pixels;
+    // Small quantization values keep more detail, so higher quality JPEGs cost more
+    let quality = avg_quantization.map_or(1.0, |quant| 1.0 - quant.min(255.0) / 255.0);
+
+    Ok(ImageComplexity {
+        score: megapixels * (1.0 + bits_per_pixel) * (0.5 + quality),
+        estimated_resize_ms: (megapixels * RESIZE_MS_PER_MEGAPIXEL).round() as u64,
+    })
+}
+
+/// Width, height and mean quantization value from a JPEG's markers, `None` if not a JPEG
+fn parse_jpeg_header(bytes: &[u8]) -> Option<(u32, u32, Option<f64>)> {
+    if !bytes.starts_with(&[0xFF, 0xD8]) {
+        return None;
+    }
+
+    let (mut dimensions, mut quant_sum, mut quant_count) = (None, 0u64, 0u64);
+    let mut pos = 2;
+    while pos + 4 <= bytes.len() && bytes[pos] == 0xFF {
+        let marker = bytes[pos + 1];
+        let len = u16::from_be_bytes([bytes[pos + 2], bytes[pos + 3]]) as usize;
+        let segment = bytes.get(pos + 4..pos + 2 + len)?;
+        match marker {
+            // DQT: one or more tables, each a precision/id byte then 64 values
+            0xDB => {
+                let mut table = segment;
+                while let Some((&info, rest)) = table.split_first() {
+                    let wide = info >> 4 == 1;
+                    let size = if wide { 128 } else { 64 };
+                    let values = rest.get(..size)?;
+                    if wide {
+                        quant_sum += values
+                            .chunks(2)
+                            .map(|v| u16::from_be_bytes([v[0], v[1]]) as u64)
+                            .sum::<u64>();
+                    } else {
+                        quant_sum += values.iter().map(|&v| v as u64).sum::<u64>();
+                    }
+                    quant_count += 64;
+                    table = &rest[size..];
+                }
+            }
+            // SOFn (excluding DHT, JPG and DAC, which share the range)
+            0xC0..=0xCF if !matches!(marker, 0xC4 | 0xC8 | 0xCC) => {
+                let height = u16::from_be_bytes([*segment.get(1)?, *segment.get(2)?]);
+                let width = u16::from_be_bytes([*segment.get(3)?, *segment.get(4)?]);
+                dimensions = Some((width as u32, height as u32));
+            }
+            // Start of scan: entropy-coded data follows, no more headers
+            0xDA => break,
+            _ => {}
+        }
+        pos += 2 + len;
+    }
+
+    let (width, height) = dimensions?;
+    let avg_quantization = (quant_count > 0).then(|| quant_sum as f64 / quant_count as f64);
+    Some((width, height, avg_quantization))
+}
+
+/// A processed image delivered to a [`ResultSink`](crate::config::ResultSink)
+#[derive(Debug, Clone)]
+pub struct ImageResult {
+    pub url: String,
+    pub image: DynamicImage,
+    pub download_ms: u64,
+    pub resize_ms: u64,
+}
+
+/// Timings for turning downloaded bytes into a saved thumbnail
+#[derive(Debug, Clone)]
+pub struct SavedImage {
+    pub decode_ms: u64,
+    pub resize_ms: u64,
+    pub save_ms: u64,
+    pub bytes_saved: u64,
+    /// Saved file, or the entry name when saving into a ZIP archive
+    pub output_path: PathBuf,
+}
+
+/// Encoded output of an [`ImageProcessor`] and the time each step took
+#[derive(Debug, Clone, Default)]
+pub struct ProcessedBytes {
+    pub bytes: Vec<u8>,
+    pub decode_ms: u64,
+    pub resize_ms: u64,
+    pub encode_ms: u64,
+}
+
+/// Backend that turns downloaded bytes into the bytes that get saved. The future is boxed
+/// so processors can be used as `dyn ImageProcessor`.
+pub trait ImageProcessor: fmt::Debug {
+    fn process<'a>(&'a self, bytes: &'a [u8]) -> BoxFuture<'a, Result<ProcessedBytes>>;
+}
+
+/// Decode → resize (→ sharpen) → encode with the `image` crate, as the processors do
+/// when no backend is configured. Work runs on the awaiting task.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefaultImageProcessor {
+    pub resize: ResizeConfig,
+    pub resize_mode: ResizeMode,
+    pub sharpen: Option<SharpenConfig>,
+    pub save: SaveConfig,
+}
+
+impl DefaultImageProcessor {
+    pub fn from_config(config: &ProcessorConfig) -> Self {
+        DefaultImageProcessor {
+            resize: config.resize.unwrap_or_default(),
+            resize_mode: config.resize_mode,
+            sharpen: config.sharpen,
+            save: config.save.unwrap_or_default(),
+        }
+    }
+
+    /// Decode → resize (→ sharpen) downloaded bytes, returning decode and resize times
+    fn decode_and_resize(&self, bytes: &[u8]) -> Result<(DynamicImage, u64, u64)> {
+        let decode_start = Instant::now();
+        let img = image::load_from_memory(bytes)?;
+        let decode_end = Instant::now();
+        let decode_ms = (decode_end - decode_start).as_millis() as u64;
+
+        // Sharpening is counted as part of the resize
+        let resize_start = Instant::now();
+        let mut resized_img = resize_to(&img, &self.resize, self.resize_mode);
+        if let Some(sharpen_config) = &self.sharpen {
+            resized_img = sharpen(&resized_img, sharpen_config);
+        }
+        let resize_ms = resize_start.elapsed().as_millis() as u64;
+
+        Ok((resized_img, decode_ms, resize_ms))
+    }
+}
+
+impl ImageProcessor for DefaultImageProcessor {
+    fn process<'a>(&'a self, bytes: &'a [u8]) -> BoxFuture<'a, Result<ProcessedBytes>> {
+        Box::pin(async move {
+            let (img, decode_ms, resize_ms) = self.decode_and_resize(bytes)?;
+            let encode_start = Instant::now();
+            let mut encoded = Vec::new();
+            encode(&img, &mut Cursor::new(&mut encoded), self.save.format)?;
+            Ok(ProcessedBytes {
+                bytes: encoded,
+                decode_ms,
+                resize_ms,
+                encode_ms: encode_start.elapsed().as_millis() as u64,
+            })
+        })
+    }
+}
 
-    let decode_start = Instant::now();
-    let img = image::load_from_memory(&img_bytes)?;
-    let decode_end = Instant::now();
-    let decode_ms = (decode_end - decode_start).as_millis() as u64;
+/// Returns the downloaded bytes unchanged, for exercising pipelines without resize work
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoopImageProcessor;
 
-    let resize_start = Instant::now();
-    let resized_img = img.resize_exact(256, 256, image::imageops::FilterType::Lanczos3);
-    let resize_end = Instant::now();
-    let resize_ms = (resize_end - resize_start).as_millis() as u64;
+impl ImageProcessor for NoopImageProcessor {
+    fn process<'a>(&'a self, bytes: &'a [u8]) -> BoxFuture<'a, Result<ProcessedBytes>> {
+        Box::pin(async move {
+            Ok(ProcessedBytes {
+                bytes: bytes.to_vec(),
+                ..Default::default()
+            })
+        })
+    }
+}
 
-    let filename = format!("{:x}.jpg", Sha256::digest(url.as_bytes()));
+fn decode_and_resize(bytes: &[u8], config: &ProcessorConfig) -> Result<(DynamicImage, u64, u64)> {
+    DefaultImageProcessor::from_config(config).decode_and_resize(bytes)
+}
+
+/// Decode → resize → save bytes that have already been downloaded from `url`
+pub fn resize_and_save(
+    url: &str,
+    bytes: &[u8],
+    output_dir: &Path,
+    config: &ProcessorConfig,
+) -> Result<SavedImage> {
+    resize_and_save_to(url, bytes, &SinkWriter::directory(output_dir), config)
+}
+
+/// [`resize_and_save`] into an opened [`OutputSink`](crate::output_sink::OutputSink)
+pub(crate) fn resize_and_save_to(
+    url: &str,
+    bytes: &[u8],
+    output: &SinkWriter,
+    config: &ProcessorConfig,
+) -> Result<SavedImage> {
+    let (resized_img, decode_ms, resize_ms) = decode_and_resize(bytes, config)?;
 
     let save_start = Instant::now();
-    resized_img.save(output_dir.join(filename))?;
+    let (path, bytes_saved) = output.save(url, &resized_img, config)?;
     let save_end = Instant::now();
     let save_ms = (save_end - save_start).as_millis() as u64;
 
-    monitor_handle.abort();
-    let peak_memory_mb = peak_memory_mb.load(Ordering::Relaxed);
-
-    Ok(ImageMetrics {
-        url: url.to_string(),
-        download_ms,
+    Ok(SavedImage {
         decode_ms,
         resize_ms,
         save_ms,
-        bytes_downloaded: img_bytes.len(),
-        peak_memory_mb,
+        bytes_saved,
+        output_path: path,
+    })
+}
+
+/// [`resize_and_save_to`], or the configured `image_processor` backend's output saved as is
+pub(crate) async fn process_and_save_to(
+    url: &str,
+    bytes: &[u8],
+    output: &SinkWriter,
+    config: &ProcessorConfig,
+) -> Result<SavedImage> {
+    let Some(processor) = &config.image_processor else {
+        return resize_and_save_to(url, bytes, output, config);
+    };
+    let processed = processor.process(bytes).await?;
+
+    let save_start = Instant::now();
+    let (path, bytes_saved) = output.save_encoded(url, &processed.bytes, config)?;
+    let save_ms = save_start.elapsed().as_millis() as u64;
+
+    Ok(SavedImage {
+        decode_ms: processed.decode_ms,
+        resize_ms: processed.resize_ms,
+        save_ms: processed.encode_ms + save_ms,
+        bytes_saved,
+        output_path: path,
+    })
+}
+
+/// Response body plus the timings gathered while fetching it
+pub(crate) struct Download {
+    pub bytes: Vec<u8>,
+    pub download_ms: u64,
+    pub tls_handshake_ms: Option<u64>,
+    pub server_timing: Option<HashMap<String, f64>>,
+}
+
+pub(crate) async fn download(url: &str, config: &ProcessorConfig) -> Result<Download> {
+    if let InputSource::LocalFile(path) = InputSource::parse(url) {
+        let read_start = Instant::now();
+        let bytes = tokio::fs::read(path).await?;
+        return Ok(Download {
+            bytes,
+            download_ms: read_start.elapsed().as_millis() as u64,
+            tls_handshake_ms: None,
+            server_timing: None,
+        });
+    }
+
+    // Off, a client of its own, so the image always pays for and times a connect
+    let own_client;
+    let client = match config
+        .http_client
+        .as_ref()
+        .filter(|_| config.reuse_connections)
+    {
+        Some(client) => client,
+        None => {
+            own_client = client_builder(&config.download.unwrap_or_default())
+                .connector_layer(ConnectTimingLayer::new())
+                .build()?;
+            &own_client
+        }
+    };
+
+    // Only a download that opened a connection of its own gets a connect time; a pooled
+    // one was paid for by an earlier image
+    let download_start = Instant::now();
+    let (response, tls_handshake) = time_connect(download_with_retry(
+        url,
+        config.download_retries,
+        config.download_retry_base_delay_ms,
+        || async { client.get(url).send().await?.error_for_status() },
+    ))
+    .await;
+    let (response, _) = response?;
+    let server_timing = server_timing(&response);
+    let bytes = response.bytes().await?.to_vec();
+
+    Ok(Download {
+        bytes,
+        download_ms: download_start.elapsed().as_millis() as u64,
+        tls_handshake_ms: tls_handshake.map(|connect| connect.as_millis() as u64),
+        server_timing,
     })
 }
 
+/// Process a single image: download → decode → resize → save
+pub async fn process_single_image(
+    url: &str,
+    output_dir: &Path,
+    config: &ProcessorConfig,
+) -> Result<ImageMetrics> {
+    process_single_image_to(url, &SinkWriter::directory(output_dir), config).await
+}
+
+/// [`process_single_image`] into an opened [`OutputSink`](crate::output_sink::OutputSink)
+pub(crate) async fn process_single_image_to(
+    url: &str,
+    output: &SinkWriter,
+    config: &ProcessorConfig,
+) -> Result<ImageMetrics> {
+    // Started before the download so the peaks cover the body as well as the decode
+    let (monitor_handle, peak) = spawn_image_tracker(config);
+    let processed = async {
+        let downloaded = download(url, config).await?;
+        let saved = process_and_save_to(url, &downloaded.bytes, output, config).await?;
+        anyhow::Ok((downloaded, saved))
+    }
+    .await;
+    monitor_handle.abort();
+    let (downloaded, saved) = processed?;
+
+    downloaded_image_metrics(url, downloaded, saved)
+        .with_peaks(&peak)
+        .build()
+}
+
+/// [`process_single_image`] for bytes that were already downloaded: decode → resize → save.
+/// `url` only names the output file; `download_ms` is 0.
+pub async fn process_image_from_bytes(
+    url: &str,
+    bytes: &[u8],
+    output_dir: &Path,
+    config: &ProcessorConfig,
+) -> Result<ImageMetrics> {
+    process_image_from_bytes_to(url, bytes, &SinkWriter::directory(output_dir), config).await
+}
+
+/// [`process_image_from_bytes`] into an opened [`OutputSink`](crate::output_sink::OutputSink)
+pub(crate) async fn process_image_from_bytes_to(
+    url: &str,
+    bytes: &[u8],
+    output: &SinkWriter,
+    config: &ProcessorConfig,
+) -> Result<ImageMetrics> {
+    let (monitor_handle, peak) = spawn_image_tracker(config);
+    let saved = process_and_save_to(url, bytes, output, config).await;
+    monitor_handle.abort();
+
+    saved_image_metrics(url, saved?)
+        .with_download(0, bytes.len())
+        .with_peaks(&peak)
+        .build()
+}
+
+/// Peak tracker for a single image, counting only what the image allocates
+fn spawn_image_tracker(config: &ProcessorConfig) -> (JoinHandle<()>, Arc<PeakReadings>) {
+    let mut memory_monitor = MemoryMonitor::with_baseline();
+    memory_monitor.set_full_refresh(config.full_memory_refresh);
+    spawn_peak_tracker(100, memory_monitor)
+}
+
+/// Metrics for `saved`, still missing the download and the peaks
+fn saved_image_metrics(url: &str, saved: SavedImage) -> ImageMetricsBuilder {
+    ImageMetrics::builder()
+        .with_url(url)
+        .with_decode(saved.decode_ms)
+        .with_resize(saved.resize_ms)
+        .with_save(saved.save_ms)
+        .with_bytes_saved(saved.bytes_saved)
+        .with_output_path(saved.output_path)
+}
+
+/// Metrics for an image fetched with [`download`] and then `saved`, still missing the peaks
+pub(crate) fn downloaded_image_metrics(
+    url: &str,
+    downloaded: Download,
+    saved: SavedImage,
+) -> ImageMetricsBuilder {
+    saved_image_metrics(url, saved)
+        .with_download(downloaded.download_ms, downloaded.bytes.len())
+        .with_tls_handshake(downloaded.tls_handshake_ms)
+        .with_server_timing(downloaded.server_timing)
+}
+
+/// Like [`process_single_image`], but encodes the thumbnail into `output` (cleared
+/// first) instead of writing a file, so callers can reuse one allocation across images.
+/// `save_ms` is the encode time; no memory monitor is run, so `peak_memory_mb` and
+/// `peak_cpu_percent` are 0.
+pub async fn process_single_image_to_buffer(
+    url: &str,
+    config: &ProcessorConfig,
+    output: &mut Vec<u8>,
+) -> Result<ImageMetrics> {
+    let downloaded = download(url, config).await?;
+    let (resized_img, decode_ms, resize_ms) = decode_and_resize(&downloaded.bytes, config)?;
+
+    let encode_start = Instant::now();
+    output.clear();
+    encode(
+        &resized_img,
+        &mut Cursor::new(&mut *output),
+        config.save.unwrap_or_default().format,
+    )?;
+    let encode_ms = encode_start.elapsed().as_millis() as u64;
+
+    ImageMetrics::builder()
+        .with_url(url)
+        .with_download(downloaded.download_ms, downloaded.bytes.len())
+        .with_tls_handshake(downloaded.tls_handshake_ms)
+        .with_server_timing(downloaded.server_timing)
+        .with_decode(decode_ms)
+        .with_resize(resize_ms)
+        .with_save(encode_ms)
+        .with_bytes_saved(output.len() as u64)
+        .build()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::fs;
+    use crate::test_support::jpeg_bytes;
+    use wiremock::{matchers::any, Mock, MockServer, ResponseTemplate};
 
     #[tokio::test]
-    async fn processes_single_image() {
-        let output = Path::new("test_output");
+    async fn swaps_image_processor_backends() {
+        let bytes = jpeg_bytes(64, 48);
+
+        let processed = DefaultImageProcessor::default()
+            .process(&bytes)
+            .await
+            .unwrap();
+        let thumbnail = image::load_from_memory(&processed.bytes).unwrap();
+        assert_eq!((thumbnail.width(), thumbnail.height()), (256, 256));
+
+        let processed = NoopImageProcessor.process(&bytes).await.unwrap();
+        assert_eq!(processed.bytes, bytes);
+    }
+
+    #[tokio::test]
+    async fn encodes_into_reused_buffer() {
+        let server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(jpeg_bytes(64, 48)))
+            .mount(&server)
+            .await;
+
+        let mut buffer = Vec::new();
+        for i in 0..2 {
+            let url = format!("{}/{}", server.uri(), i);
+            let metrics =
+                process_single_image_to_buffer(&url, &ProcessorConfig::default(), &mut buffer)
+                    .await
+                    .unwrap();
+
+            assert_eq!(metrics.url, url);
+            assert!(metrics.tls_handshake_ms.is_some());
+            let decoded = image::load_from_memory(&buffer).unwrap();
+            assert_eq!((decoded.width(), decoded.height()), (256, 256));
+        }
+    }
+
+    #[test]
+    fn saves_atomically() {
+        let output = Path::new("test_output_atomic");
         fs::create_dir_all(output).unwrap();
+        let path = output.join("image.jpg");
 
-        let url = "https://picsum.photos/seed/1/800/600";
-        let result = process_single_image(url, output).await;
+        save_atomic(&DynamicImage::new_rgb8(8, 8), &path, &SaveConfig::default()).unwrap();
+        let names: Vec<_> = fs::read_dir(output)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(names, vec!["image.jpg"]);
 
-        if let Err(e) = &result {
-            eprintln!("Error: {:?}", e);
+        // A directory in the way of the temp file makes the next write fail partway
+        let saved = fs::read(&path).unwrap();
+        fs::create_dir(output.join("image.jpg.tmp")).unwrap();
+        assert!(save_atomic(
+            &DynamicImage::new_rgb8(16, 16),
+            &path,
+            &SaveConfig::default()
+        )
+        .is_err());
+        assert_eq!(fs::read(&path).unwrap(), saved);
+
+        fs::remove_dir_all(output).unwrap();
+    }
+
+    #[test]
+    fn lower_quality_saves_smaller_files() {
+        let output = Path::new("test_output_quality");
+        fs::create_dir_all(output).unwrap();
+        let img = DynamicImage::ImageRgb8(image::RgbImage::from_fn(64, 64, |x, y| {
+            image::Rgb([(x * 4) as u8, (y * 4) as u8, ((x * y) % 256) as u8])
+        }));
+
+        let save = |quality| SaveConfig {
+            format: OutputFormat::Jpeg(quality),
+        };
+        let high = save_atomic(&img, &output.join("high.jpg"), &save(95)).unwrap();
+        let low = save_atomic(&img, &output.join("low.jpg"), &save(10)).unwrap();
+        assert!(low < high);
+        assert_eq!(fs::metadata(output.join("low.jpg")).unwrap().len(), low);
+
+        fs::remove_dir_all(output).unwrap();
+    }
+
+    #[test]
+    fn saves_in_configured_format() {
+        let output = Path::new("test_output_formats");
+        fs::create_dir_all(output).unwrap();
+        let img = DynamicImage::new_rgb8(16, 16);
+
+        let formats = [
+            (OutputFormat::Jpeg(75), ImageFormat::Jpeg),
+            (OutputFormat::Png, ImageFormat::Png),
+            #[cfg(feature = "webp")]
+            (OutputFormat::WebP, ImageFormat::WebP),
+        ];
+        for (format, expected) in formats {
+            let config = ProcessorConfig {
+                save: Some(SaveConfig { format }),
+                ..Default::default()
+            };
+            let path = output_path("https://example.com/a.jpg", output, &config).unwrap();
+            assert_eq!(path.extension().unwrap(), format.extension());
+
+            save_atomic(&img, &path, &config.save.unwrap()).unwrap();
+            let saved = fs::read(&path).unwrap();
+            assert_eq!(image::guess_format(&saved).unwrap(), expected);
+        }
+
+        fs::remove_dir_all(output).unwrap();
+    }
+
+    #[test]
+    fn resizes_to_configured_size() {
+        let img = DynamicImage::new_rgb8(400, 300);
+        let resize = ResizeConfig {
+            width: 64,
+            height: 48,
+            filter: FilterType::Nearest,
+        };
+
+        let exact = resize_to(&img, &resize, ResizeMode::Exact);
+        assert_eq!((exact.width(), exact.height()), (64, 48));
+
+        let padded = resize_to(&img, &resize, ResizeMode::Padded { color: [0; 3] });
+        assert_eq!((padded.width(), padded.height()), (64, 48));
+    }
+
+    #[test]
+    fn preserves_aspect_ratio() {
+        let img = DynamicImage::new_rgb8(400, 200);
+        let resize = ResizeConfig {
+            width: 100,
+            height: 100,
+            filter: FilterType::Nearest,
+        };
+
+        let fit = resize_to(&img, &resize, ResizeMode::Fit);
+        assert_eq!((fit.width(), fit.height()), (100, 50));
+
+        let cover = resize_to(&img, &resize, ResizeMode::Cover);
+        assert_eq!((cover.width(), cover.height()), (100, 100));
+    }
+
+    #[test]
+    fn covers_by_cropping_the_center() {
+        // Left and right thirds red, middle third green: covering a square keeps only green
+        let mut img = image::RgbImage::from_pixel(300, 100, image::Rgb([255, 0, 0]));
+        for x in 100..200 {
+            for y in 0..100 {
+                img.put_pixel(x, y, image::Rgb([0, 255, 0]));
+            }
+        }
+
+        let cover =
+            resize_to_cover(&DynamicImage::ImageRgb8(img), 50, 50, FilterType::Nearest).to_rgb8();
+
+        assert_eq!(cover.dimensions(), (50, 50));
+        for (x, y) in [(0, 0), (49, 0), (0, 49), (49, 49)] {
+            assert_eq!(cover.get_pixel(x, y).0, [0, 255, 0]);
         }
-        assert!(result.is_ok());
-        let metrics = result.unwrap();
-        assert!(metrics.download_ms > 0);
-        assert!(metrics.bytes_downloaded > 0);
+    }
+
+    #[test]
+    fn pads_to_exact_size() {
+        let img = DynamicImage::ImageRgb8(image::RgbImage::from_pixel(
+            400,
+            200,
+            image::Rgb([255, 255, 255]),
+        ));
+        let color = [10, 20, 30];
+
+        let padded = resize_with_padding(&img, 256, 256, FilterType::Lanczos3, color).to_rgb8();
+
+        assert_eq!(padded.dimensions(), (256, 256));
+        for (x, y) in [(0, 0), (255, 0), (0, 255), (255, 255)] {
+            assert_eq!(padded.get_pixel(x, y).0, color);
+        }
+        // Letterboxed: the image spans the full width through the middle rows
+        assert_eq!(padded.get_pixel(0, 128).0, [255, 255, 255]);
+        assert_eq!(padded.get_pixel(128, 60).0, color);
+    }
+
+    #[test]
+    fn tiles_contact_sheet() {
+        let dir = std::env::temp_dir().join("flux_contact_sheet");
+        fs::create_dir_all(&dir).unwrap();
+        let paths: Vec<_> = [[255, 0, 0], [0, 255, 0], [0, 0, 255]]
+            .iter()
+            .enumerate()
+            .map(|(i, color)| {
+                let path = dir.join(format!("{}.png", i));
+                image::RgbImage::from_pixel(4, 4, image::Rgb(*color))
+                    .save(&path)
+                    .unwrap();
+                path
+            })
+            .collect();
+        let sheet_path = dir.join("sheet.png");
+
+        let background = [9, 9, 9, 255];
+        make_contact_sheet_with(&paths, 2, &sheet_path, background).unwrap();
+
+        let sheet = image::open(&sheet_path).unwrap().to_rgba8();
+        assert_eq!(sheet.dimensions(), (8, 8));
+        assert_eq!(sheet.get_pixel(0, 0).0, [255, 0, 0, 255]);
+        assert_eq!(sheet.get_pixel(4, 0).0, [0, 255, 0, 255]);
+        assert_eq!(sheet.get_pixel(0, 4).0, [0, 0, 255, 255]);
+        // Three images in two columns leave the last cell blank
+        assert_eq!(sheet.get_pixel(4, 4).0, background);
+        assert!(make_contact_sheet(&[], 2, &sheet_path).is_err());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn solid_images_are_less_complex() {
+        let encode = |img: DynamicImage| {
+            let mut bytes = Vec::new();
+            img.write_to(
+                &mut std::io::Cursor::new(&mut bytes),
+                image::ImageFormat::Jpeg,
+            )
+            .unwrap();
+            bytes
+        };
+        let solid = encode(DynamicImage::new_rgb8(256, 256));
+        let textured = encode(DynamicImage::ImageRgb8(image::RgbImage::from_fn(
+            256,
+            256,
+            |x, y| {
+                let n = (x.wrapping_mul(7919) ^ y.wrapping_mul(104729)).wrapping_mul(2654435761);
+                image::Rgb([(n >> 8) as u8, (n >> 16) as u8, (n >> 24) as u8])
+            },
+        )));
+
+        let (width, height, quantization) = parse_jpeg_header(&solid).unwrap();
+        assert_eq!((width, height), (256, 256));
+        assert!(quantization.is_some());
+
+        let solid_complexity = estimate_complexity(&solid).unwrap();
+        let textured_complexity = estimate_complexity(&textured).unwrap();
+        assert!(solid_complexity.score < textured_complexity.score);
+        assert_eq!(
+            solid_complexity.estimated_resize_ms,
+            textured_complexity.estimated_resize_ms
+        );
+    }
+
+    #[cfg(feature = "ndarray")]
+    #[test]
+    fn round_trips_through_ndarray() {
+        let img = DynamicImage::ImageRgb8(image::RgbImage::from_fn(7, 5, |x, y| {
+            image::Rgb([(x * 30) as u8, (y * 50) as u8, ((x + y) * 10) as u8])
+        }));
+
+        let arr = image_to_ndarray(&img);
+        assert_eq!(arr.shape(), &[5, 7, 3]);
+        assert_eq!(arr[[1, 2, 0]], 60);
+
+        let restored = ndarray_to_image(arr.view());
+        assert_eq!(restored.to_rgb8(), img.to_rgb8());
+    }
+
+    #[tokio::test]
+    async fn processes_downloaded_bytes() {
+        let output = Path::new("test_output_from_bytes");
+        fs::create_dir_all(output).unwrap();
+        let bytes = jpeg_bytes(400, 300);
+        let config = ProcessorConfig {
+            resize: Some(ResizeConfig {
+                width: 40,
+                height: 30,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let metrics =
+            process_image_from_bytes("https://example.com/a.jpg", &bytes, output, &config)
+                .await
+                .unwrap();
+
+        assert_eq!(metrics.download_ms, 0);
+        assert_eq!(metrics.bytes_downloaded, bytes.len());
+        assert!(metrics.bytes_saved > 0);
+        let saved = image::open(&metrics.output_path).unwrap();
+        assert_eq!((saved.width(), saved.height()), (40, 30));
+
+        fs::remove_dir_all(output).unwrap();
+    }
+
+    #[tokio::test]
+    async fn processes_single_image() {
+        let server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(jpeg_bytes(80, 60)))
+            .mount(&server)
+            .await;
+        let output = Path::new("test_output");
+        fs::create_dir_all(output).unwrap();
+
+        // Over the shared client only the first image opens a connection
+        let config = ProcessorConfig::default().with_shared_client().unwrap();
+        let first = process_single_image(&format!("{}/1", server.uri()), output, &config)
+            .await
+            .unwrap();
+        assert!(first.bytes_downloaded > 0);
+        assert!(first.output_path.exists());
+        assert!(first.tls_handshake_ms.is_some());
+        let second = process_single_image(&format!("{}/2", server.uri()), output, &config)
+            .await
+            .unwrap();
+        assert!(second.tls_handshake_ms.is_none());
 
-        // Cleanup
         fs::remove_dir_all(output).unwrap();
     }
+
+    #[test]
+    fn sharpening_changes_edges() {
+        let img = DynamicImage::ImageRgb8(image::RgbImage::from_fn(32, 32, |x, _| {
+            if x < 16 {
+                image::Rgb([100, 100, 100])
+            } else {
+                image::Rgb([150, 150, 150])
+            }
+        }));
+        let config = SharpenConfig {
+            amount: 1.0,
+            radius: 2.0,
+            threshold: 0,
+        };
+
+        let sharpened = sharpen(&img, &config);
+        assert_ne!(sharpened.to_rgb8().as_raw(), img.to_rgb8().as_raw());
+
+        let half = sharpen(
+            &img,
+            &SharpenConfig {
+                amount: 0.5,
+                ..config
+            },
+        );
+        assert_ne!(half.to_rgb8().as_raw(), sharpened.to_rgb8().as_raw());
+    }
+
+    #[test]
+    fn blended_sharpening_keeps_alpha() {
+        let img = DynamicImage::ImageRgba8(image::RgbaImage::from_fn(32, 32, |x, _| {
+            if x < 16 {
+                image::Rgba([100, 100, 100, 0])
+            } else {
+                image::Rgba([150, 150, 150, 255])
+            }
+        }));
+        let config = SharpenConfig {
+            amount: 0.5,
+            radius: 2.0,
+            threshold: 0,
+        };
+
+        let sharpened = sharpen(&img, &config);
+        assert_eq!(sharpened.color(), ColorType::Rgba8);
+        let sharpened = sharpened.to_rgba8();
+        assert_eq!(sharpened.get_pixel(0, 0)[3], 0);
+        assert_eq!(sharpened.get_pixel(31, 0)[3], 255);
+    }
+
+    #[test]
+    fn builder_requires_url_and_download() {
+        assert!(ImageMetrics::builder()
+            .with_download(10, 100)
+            .build()
+            .is_err());
+        assert!(ImageMetrics::builder().with_url("a").build().is_err());
+
+        let metrics = ImageMetrics::builder()
+            .with_url("a")
+            .with_download(10, 100)
+            .with_resize(5)
+            .with_bytes_saved(25)
+            .build()
+            .unwrap();
+        assert_eq!(metrics.download_ms, 10);
+        assert_eq!(metrics.bytes_downloaded, 100);
+        assert_eq!(metrics.resize_ms, 5);
+        assert_eq!(metrics.compression_ratio, 0.25);
+        assert_eq!(compression_ratio(25, 0), 0.0);
+    }
 }
@@ -0,0 +1,27 @@
+pub mod batched;
+pub mod cache;
+pub mod checkpoint;
+pub mod compression;
+pub mod config;
+pub mod error;
+pub mod http_client;
+pub mod live_metrics;
+pub mod image_processor;
+pub mod manifest;
+pub mod memory_monitor;
+pub mod metrics;
+pub mod naive;
+pub mod output_sink;
+pub mod parallel;
+pub mod progress;
+pub mod run_config;
+pub mod sampling;
+pub mod streaming;
+#[cfg(feature = "otel")]
+pub mod telemetry;
+pub mod url_generator;
+pub mod validation;
+pub mod warmup;
+
+#[cfg(test)]
+mod test_support;
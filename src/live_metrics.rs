@@ -0,0 +1,161 @@
+// src/live_metrics.rs
+
+use anyhow::Result;
+use axum::{
+    extract::State,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use prometheus::{
+    Encoder, Histogram, HistogramOpts, IntCounterVec, IntGauge, Opts, Registry, TextEncoder,
+};
+use std::{fmt, sync::Arc};
+use tokio::net::TcpListener;
+use tokio_util::sync::CancellationToken;
+
+/// Counters the processors update as images finish, so a run can be scraped while it is
+/// still going
+pub struct LiveMetrics {
+    registry: Registry,
+    images_processed: IntCounterVec,
+    download_duration: Histogram,
+    resize_duration: Histogram,
+    peak_memory: IntGauge,
+}
+
+impl LiveMetrics {
+    pub fn new() -> Result<Self> {
+        let images_processed = IntCounterVec::new(
+            Opts::new("flux_images_processed_total", "Images saved, by approach"),
+            &["approach"],
+        )?;
+        let download_duration = Histogram::with_opts(HistogramOpts::new(
+            "flux_download_duration_seconds",
+            "Time to download one image",
+        ))?;
+        let resize_duration = Histogram::with_opts(HistogramOpts::new(
+            "flux_resize_duration_seconds",
+            "Time to decode and resize one image",
+        ))?;
+        let peak_memory = IntGauge::new(
+            "flux_peak_memory_bytes",
+            "Highest memory usage seen by any finished run",
+        )?;
+
+        let registry = Registry::new();
+        registry.register(Box::new(images_processed.clone()))?;
+        registry.register(Box::new(download_duration.clone()))?;
+        registry.register(Box::new(resize_duration.clone()))?;
+        registry.register(Box::new(peak_memory.clone()))?;
+        Ok(LiveMetrics {
+            registry,
+            images_processed,
+            download_duration,
+            resize_duration,
+            peak_memory,
+        })
+    }
+
+    /// Count one saved image for `approach` and observe its timings
+    pub fn record_image(&self, approach: &str, download_ms: u64, resize_ms: u64) {
+        self.images_processed.with_label_values(&[approach]).inc();
+        self.download_duration.observe(download_ms as f64 / 1000.0);
+        self.resize_duration.observe(resize_ms as f64 / 1000.0);
+    }
+
+    /// Raise the peak memory gauge to `mb` unless an earlier run went higher
+    pub fn record_peak_memory_mb(&self, mb: u64) {
+        let bytes = (mb * 1024 * 1024) as i64;
+        if bytes > self.peak_memory.get() {
+            self.peak_memory.set(bytes);
+        }
+    }
+
+    /// Every metric in the Prometheus text exposition format
+    pub fn render(&self) -> Result<String> {
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        Ok(String::from_utf8(buffer)?)
+    }
+}
+
+impl fmt::Debug for LiveMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("LiveMetrics")
+    }
+}
+
+/// Serve `metrics` at `/metrics` on `listener` until `shutdown` is cancelled, then finish
+/// any scrape in progress and return
+pub async fn serve_metrics(
+    listener: TcpListener,
+    metrics: Arc<LiveMetrics>,
+    shutdown: CancellationToken,
+) -> Result<()> {
+    let app = Router::new()
+        .route("/metrics", get(scrape))
+        .with_state(metrics);
+    axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown.cancelled_owned())
+        .await?;
+    Ok(())
+}
+
+async fn scrape(State(metrics): State<Arc<LiveMetrics>>) -> Response {
+    match metrics.render() {
+        Ok(body) => ([(header::CONTENT_TYPE, prometheus::TEXT_FORMAT)], body).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn serves_recorded_metrics() {
+        let metrics = Arc::new(LiveMetrics::new().unwrap());
+        metrics.record_image("streaming", 120, 30);
+        metrics.record_image("streaming", 80, 20);
+        metrics.record_image("naive", 200, 40);
+        metrics.record_peak_memory_mb(64);
+        metrics.record_peak_memory_mb(32);
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/metrics", listener.local_addr().unwrap());
+        let shutdown = CancellationToken::new();
+        let server = tokio::spawn(serve_metrics(
+            listener,
+            Arc::clone(&metrics),
+            shutdown.clone(),
+        ));
+
+        let text = reqwest::get(&url).await.unwrap().text().await.unwrap();
+        shutdown.cancel();
+        server.await.unwrap().unwrap();
+
+        let scrape =
+            prometheus_parse::Scrape::parse(text.lines().map(|line| Ok(line.to_string()))).unwrap();
+        let streaming = scrape
+            .samples
+            .iter()
+            .find(|sample| {
+                sample.metric == "flux_images_processed_total"
+                    && sample.labels.get("approach") == Some("streaming")
+            })
+            .unwrap();
+        assert_eq!(streaming.value, prometheus_parse::Value::Counter(2.0));
+        let peak = scrape
+            .samples
+            .iter()
+            .find(|sample| sample.metric == "flux_peak_memory_bytes")
+            .unwrap();
+        assert_eq!(
+            peak.value,
+            prometheus_parse::Value::Gauge(64.0 * 1024.0 * 1024.0)
+        );
+        assert!(text.contains("flux_download_duration_seconds_count 3"));
+    }
+}
@@ -1,30 +1,66 @@
-mod url_generator;
-mod image_processor;
-mod memory_monitor;
-mod naive;
-mod batched;
-mod streaming;
-mod metrics;
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
 
-use std::{env, fs, path::Path};
+use image::imageops::FilterType;
 
 use anyhow::Result;
+use tokio::net::TcpListener;
+use tokio_util::sync::CancellationToken;
 use tracing::{info, warn};
-use tracing_subscriber::{fmt, EnvFilter};
+use tracing_subscriber::{
+    fmt::{self, writer::BoxMakeWriter},
+    layer::SubscriberExt,
+    util::SubscriberInitExt,
+    EnvFilter, Layer,
+};
 
-use crate::{
+use flux::{
     batched::processor::process_batched,
-    metrics::{MetricsCollector, ProcessingRun},
-    naive::processor::process_naive,
-    streaming::pipeline::process_streaming,
+    cache::DiskCache,
+    config::{DownloadConfig, DownloadStrategy, ProcessorConfig, ResizeMode},
+    image_processor::{make_contact_sheet, OutputFormat, ResizeConfig, SaveConfig},
+    live_metrics::{serve_metrics, LiveMetrics},
+    metrics::MetricsCollector,
+    naive::processor::{process_naive, process_naive_concurrent},
+    output_sink::{OutputSink, RecordFormat},
+    parallel::processor::process_parallel,
+    run_config::RunConfig,
+    streaming::{pipeline::StreamingPipeline, sweep::sweep_channel_capacity},
+    url_generator::{ImageSource, UrlGenerator, UrlTemplate},
 };
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
-    fmt().with_env_filter(filter).init();
+    // Logs move to stderr when stdout carries records, before the args are parsed so their
+    // warnings go there too
+    let records_to_stdout = env::args()
+        .skip_while(|arg| arg != "--output-format")
+        .nth(1)
+        .is_some_and(|format| format != "files");
+    let writer = if records_to_stdout {
+        BoxMakeWriter::new(io::stderr)
+    } else {
+        BoxMakeWriter::new(io::stdout)
+    };
+    let subscriber =
+        tracing_subscriber::registry().with(fmt::layer().with_writer(writer).with_filter(filter));
+    // Built with `--features otel`, spans are also exported to OTEL_EXPORTER_OTLP_ENDPOINT
+    #[cfg(feature = "otel")]
+    let otel_provider = flux::telemetry::otlp_provider()?;
+    #[cfg(feature = "otel")]
+    subscriber
+        .with(flux::telemetry::otel_layer(&otel_provider))
+        .init();
+    #[cfg(not(feature = "otel"))]
+    subscriber.init();
 
-    let count = parse_count_arg().unwrap_or(200);
+    let args = parse_args();
+    let count = args.count.unwrap_or(200);
 
     info!(count, "flux image processor started");
 
@@ -32,16 +68,92 @@ async fn main() -> Result<()> {
     fs::create_dir_all(base_dir)?;
 
     let naive_dir = base_dir.join("naive");
+    let naive_pipelined_dir = base_dir.join("naive-pipelined");
+    let naive_concurrent_dir = base_dir.join("naive-concurrent");
     let batched_dir = base_dir.join("batched");
+    let parallel_dir = base_dir.join("parallel");
     let streaming_dir = base_dir.join("streaming");
     fs::create_dir_all(&naive_dir)?;
+    fs::create_dir_all(&naive_pipelined_dir)?;
+    fs::create_dir_all(&naive_concurrent_dir)?;
     fs::create_dir_all(&batched_dir)?;
+    fs::create_dir_all(&parallel_dir)?;
     fs::create_dir_all(&streaming_dir)?;
 
+    // Scraped while the processors run; shut down once they are all done
+    let live_metrics = match args.metrics_port {
+        Some(port) => {
+            let metrics = Arc::new(LiveMetrics::new()?);
+            let listener = TcpListener::bind(("0.0.0.0", port)).await?;
+            info!(port, "serving metrics at /metrics");
+            let shutdown = CancellationToken::new();
+            let server = tokio::spawn(serve_metrics(
+                listener,
+                Arc::clone(&metrics),
+                shutdown.clone(),
+            ));
+            Some((metrics, shutdown, server))
+        }
+        None => None,
+    };
+
+    let config = ProcessorConfig {
+        download_concurrency: 8,
+        process_concurrency: 10,
+        download_channel_capacity: 10,
+        process_channel_capacity: 10,
+        resize: args.resize,
+        save: args.save,
+        url_template: args.url_template,
+        rate_limit: args.rate_limit,
+        warmup_count: args.warmup,
+        download: args.download,
+        disk_cache: args.cache_dir.map(DiskCache::new),
+        resize_mode: args.resize_mode.unwrap_or_default(),
+        live_metrics: live_metrics.as_ref().map(|(metrics, _, _)| Arc::clone(metrics)),
+        ..Default::default()
+    };
+
+    // Records replace saved files, so only the streaming pipeline runs and nothing is compared
+    if let Some(format) = args.output_format {
+        let stats = StreamingPipeline::builder()
+            .config(config)
+            .output_sink(OutputSink::Stdout(format))
+            .build()?
+            .run(count)
+            .await?;
+        info!(
+            total_time_ms = stats.total_time_ms,
+            avg_download_ms = stats.avg_download_ms,
+            avg_resize_ms = stats.avg_resize_ms,
+            "streaming summary"
+        );
+        if let Some((_, shutdown, server)) = live_metrics {
+            shutdown.cancel();
+            server.await??;
+        }
+        #[cfg(feature = "otel")]
+        otel_provider.shutdown()?;
+        return Ok(());
+    }
+
+    // Only the three main approaches, once per count, in place of the usual comparison
+    if let Some(counts) = args.sweep_counts {
+        sweep_counts(&counts, base_dir, &config).await?;
+        if let Some((_, shutdown, server)) = live_metrics {
+            shutdown.cancel();
+            server.await??;
+        }
+        #[cfg(feature = "otel")]
+        otel_provider.shutdown()?;
+        return Ok(());
+    }
+
     if tracing::enabled!(tracing::Level::INFO) {
         println!();
     }
-    let naive_stats = process_naive(count, &naive_dir).await?;
+    RunConfig::new("naive", count, &config).save(&naive_dir)?;
+    let naive_stats = process_naive(count, &naive_dir, &config).await?;
     info!(
         total_time_ms = naive_stats.total_time_ms,
         peak_memory_mb = naive_stats.peak_memory_mb,
@@ -50,10 +162,92 @@ async fn main() -> Result<()> {
         "naive summary"
     );
 
+    // The same run again, with every image opening its own connection
+    let naive_fresh_stats = if args.compare_connection_reuse {
+        if tracing::enabled!(tracing::Level::INFO) {
+            println!();
+        }
+        let fresh_dir = base_dir.join("naive-fresh-connections");
+        fs::create_dir_all(&fresh_dir)?;
+        let fresh_config = ProcessorConfig {
+            reuse_connections: false,
+            ..config.clone()
+        };
+        RunConfig::new("naive-fresh-connections", count, &fresh_config).save(&fresh_dir)?;
+        let stats = process_naive(count, &fresh_dir, &fresh_config).await?;
+        info!(
+            reused_avg_download_ms = naive_stats.avg_download_ms,
+            fresh_avg_download_ms = stats.avg_download_ms,
+            "connection reuse comparison"
+        );
+        Some(stats)
+    } else {
+        None
+    };
+
+    // The same run again, with the memory monitor refreshing every process on each sample
+    let naive_full_refresh_stats = if args.compare_memory_refresh {
+        if tracing::enabled!(tracing::Level::INFO) {
+            println!();
+        }
+        let full_dir = base_dir.join("naive-full-refresh");
+        fs::create_dir_all(&full_dir)?;
+        let full_config = ProcessorConfig {
+            full_memory_refresh: true,
+            ..config.clone()
+        };
+        RunConfig::new("naive-full-refresh", count, &full_config).save(&full_dir)?;
+        let stats = process_naive(count, &full_dir, &full_config).await?;
+        info!(
+            targeted_monitor_us = naive_stats.monitor_overhead_us,
+            full_monitor_us = stats.monitor_overhead_us,
+            "memory refresh comparison"
+        );
+        Some(stats)
+    } else {
+        None
+    };
+
+    if tracing::enabled!(tracing::Level::INFO) {
+        println!();
+    }
+    let pipelined_config = ProcessorConfig {
+        semi_async_naive: true,
+        ..config.clone()
+    };
+    RunConfig::new("naive-pipelined", count, &pipelined_config).save(&naive_pipelined_dir)?;
+    let naive_pipelined_stats =
+        process_naive(count, &naive_pipelined_dir, &pipelined_config).await?;
+    info!(
+        total_time_ms = naive_pipelined_stats.total_time_ms,
+        peak_memory_mb = naive_pipelined_stats.peak_memory_mb,
+        avg_download_ms = naive_pipelined_stats.avg_download_ms,
+        avg_resize_ms = naive_pipelined_stats.avg_resize_ms,
+        "naive-pipelined summary"
+    );
+
+    if tracing::enabled!(tracing::Level::INFO) {
+        println!();
+    }
+    RunConfig::new("naive-concurrent", count, &config).save(&naive_concurrent_dir)?;
+    let naive_concurrent_stats =
+        process_naive_concurrent(count, 10, &naive_concurrent_dir, &config).await?;
+    info!(
+        total_time_ms = naive_concurrent_stats.total_time_ms,
+        peak_memory_mb = naive_concurrent_stats.peak_memory_mb,
+        avg_download_ms = naive_concurrent_stats.avg_download_ms,
+        avg_resize_ms = naive_concurrent_stats.avg_resize_ms,
+        "naive-concurrent summary"
+    );
+
     if tracing::enabled!(tracing::Level::INFO) {
         println!();
     }
-    let batched_stats = process_batched(count, 10, &batched_dir).await?;
+    RunConfig::new("batched", count, &config)
+        .with_batch_size(10)
+        .save(&batched_dir)?;
+    let batched_output = OutputSink::Directory(batched_dir.clone());
+    let batched_stats = process_batched(count, 10, &batched_output, &config).await?;
     info!(
         total_time_ms = batched_stats.total_time_ms,
         peak_memory_mb = batched_stats.peak_memory_mb,
@@ -65,7 +259,26 @@ async fn main() -> Result<()> {
     if tracing::enabled!(tracing::Level::INFO) {
         println!();
     }
-    let streaming_stats = process_streaming(count, &streaming_dir, 8, 10, 10).await?;
+    RunConfig::new("parallel", count, &config).save(&parallel_dir)?;
+    let parallel_stats = process_parallel(count, &parallel_dir, &config).await?;
+    info!(
+        total_time_ms = parallel_stats.total_time_ms,
+        peak_memory_mb = parallel_stats.peak_memory_mb,
+        avg_download_ms = parallel_stats.avg_download_ms,
+        avg_resize_ms = parallel_stats.avg_resize_ms,
+        "parallel summary"
+    );
+
+    if tracing::enabled!(tracing::Level::INFO) {
+        println!();
+    }
+    RunConfig::new("streaming", count, &config).save(&streaming_dir)?;
+    let streaming_stats = StreamingPipeline::builder()
+        .config(config.clone())
+        .output_dir(streaming_dir.clone())
+        .build()?
+        .run(count)
+        .await?;
     info!(
         total_time_ms = streaming_stats.total_time_ms,
         peak_memory_mb = streaming_stats.peak_memory_mb,
@@ -73,6 +286,44 @@ async fn main() -> Result<()> {
         avg_resize_ms = streaming_stats.avg_resize_ms,
         "streaming summary"
     );
+    if config.disk_cache.is_some() {
+        info!(
+            hits = streaming_stats.cache_hits,
+            misses = streaming_stats.cache_misses,
+            "download cache"
+        );
+    }
+
+    // The same run again, with every download a stream on one HTTP/2 connection
+    let streaming_http2_stats = if args.compare_download_strategy {
+        if tracing::enabled!(tracing::Level::INFO) {
+            println!();
+        }
+        let http2_dir = base_dir.join("streaming-http2");
+        fs::create_dir_all(&http2_dir)?;
+        let http2_config = ProcessorConfig {
+            download: Some(DownloadConfig {
+                strategy: DownloadStrategy::Http2Multiplexed,
+                ..config.download.unwrap_or_default()
+            }),
+            ..config.clone()
+        };
+        RunConfig::new("streaming-http2", count, &http2_config).save(&http2_dir)?;
+        let stats = StreamingPipeline::builder()
+            .config(http2_config)
+            .output_dir(http2_dir)
+            .build()?
+            .run(count)
+            .await?;
+        info!(
+            independent_total_time_ms = streaming_stats.total_time_ms,
+            http2_total_time_ms = stats.total_time_ms,
+            "download strategy comparison"
+        );
+        Some(stats)
+    } else {
+        None
+    };
 
     let naive_throughput = (naive_stats.total_images as f64 / naive_stats.total_time_ms as f64) * 1000.0;
     let batched_throughput = (batched_stats.total_images as f64 / batched_stats.total_time_ms as f64) * 1000.0;
@@ -94,44 +345,490 @@ async fn main() -> Result<()> {
     );
 
     let mut collector = MetricsCollector::new();
-    collector.add_run(ProcessingRun::new(
-        "naive",
-        naive_stats.total_images,
-        naive_stats.total_time_ms,
-        naive_stats.peak_memory_mb,
-        naive_stats.avg_download_ms,
-        naive_stats.avg_resize_ms,
-    ));
-    collector.add_run(ProcessingRun::new(
-        "batched",
-        batched_stats.total_images,
-        batched_stats.total_time_ms,
-        batched_stats.peak_memory_mb,
-        batched_stats.avg_download_ms,
-        batched_stats.avg_resize_ms,
-    ));
-    collector.add_run(ProcessingRun::new(
+    collector.add_run(naive_stats.to_run("naive"));
+    if let Some(fresh) = naive_fresh_stats {
+        collector.add_run(fresh.to_run("naive-fresh-connections"));
+    }
+    if let Some(full) = naive_full_refresh_stats {
+        collector.add_run(full.to_run("naive-full-refresh"));
+        collector.add_custom_metric(
+            "naive",
+            "Monitor (ms)".to_string(),
+            naive_stats.monitor_overhead_us as f64 / 1000.0,
+        )?;
+        collector.add_custom_metric(
+            "naive-full-refresh",
+            "Monitor (ms)".to_string(),
+            full.monitor_overhead_us as f64 / 1000.0,
+        )?;
+    }
+    collector.add_run(naive_pipelined_stats.to_run("naive-pipelined"));
+    collector.add_run(naive_concurrent_stats.to_run("naive-concurrent"));
+    collector.add_run(batched_stats.to_run("batched"));
+    collector.add_run(parallel_stats.to_run("parallel"));
+    collector.add_run(streaming_stats.to_run("streaming"));
+    if let Some(http2) = streaming_http2_stats {
+        collector.add_run(http2.to_run("streaming-http2"));
+    }
+    collector.add_custom_metric(
         "streaming",
-        streaming_stats.total_images,
-        streaming_stats.total_time_ms,
-        streaming_stats.peak_memory_mb,
-        streaming_stats.avg_download_ms,
-        streaming_stats.avg_resize_ms,
-    ));
+        "First save (ms)".to_string(),
+        streaming_stats.pipeline_start_latency_ms as f64,
+    )?;
+    collector.add_custom_metric(
+        "streaming",
+        "Last save (ms)".to_string(),
+        streaming_stats.time_to_last_save_ms as f64,
+    )?;
+    collector.add_custom_metric(
+        "streaming",
+        "Avg TTFB (ms)".to_string(),
+        streaming_stats.avg_ttfb_ms as f64,
+    )?;
+    collector.add_custom_metric(
+        "streaming",
+        "Avg TTLB (ms)".to_string(),
+        streaming_stats.avg_ttlb_ms as f64,
+    )?;
+    // Saved over downloaded size, from the per-image metrics only the naive runs keep
+    for (approach, min, avg, max) in [
+        (
+            "naive",
+            naive_stats.min_compression_ratio,
+            naive_stats.avg_compression_ratio,
+            naive_stats.max_compression_ratio,
+        ),
+        (
+            "naive-pipelined",
+            naive_pipelined_stats.min_compression_ratio,
+            naive_pipelined_stats.avg_compression_ratio,
+            naive_pipelined_stats.max_compression_ratio,
+        ),
+        (
+            "naive-concurrent",
+            naive_concurrent_stats.min_compression_ratio,
+            naive_concurrent_stats.avg_compression_ratio,
+            naive_concurrent_stats.max_compression_ratio,
+        ),
+    ] {
+        collector.add_custom_metric(approach, "Min compression".to_string(), min)?;
+        collector.add_custom_metric(approach, "Avg compression".to_string(), avg)?;
+        collector.add_custom_metric(approach, "Max compression".to_string(), max)?;
+    }
+    // Server-side share of the download time, for runs that keep per-image metrics
+    for (approach, split) in [
+        ("naive", &naive_stats.server_timing),
+        ("naive-concurrent", &naive_concurrent_stats.server_timing),
+        ("batched", &batched_stats.server_timing),
+    ] {
+        collector.add_server_timing(approach, split)?;
+    }
+
+    if args.contact_sheet {
+        for (approach, dir) in [
+            ("naive", &naive_dir),
+            ("naive-pipelined", &naive_pipelined_dir),
+            ("naive-concurrent", &naive_concurrent_dir),
+            ("batched", &batched_dir),
+            ("parallel", &parallel_dir),
+            ("streaming", &streaming_dir),
+        ] {
+            let sheet_path = base_dir.join(format!("{}-contact-sheet.png", approach));
+            save_contact_sheet(dir, count, &sheet_path);
+        }
+    }
 
     collector.print_comparison();
+    if args.histogram {
+        collector.print_latency_histogram(10);
+    }
+
+    // The streaming run again in each output format, weighing encode time against file size
+    if args.compare_formats {
+        let jpeg = match OutputFormat::default() {
+            OutputFormat::Jpeg(default) => OutputFormat::Jpeg(args.quality.unwrap_or(default)),
+            format => format,
+        };
+        let formats = [
+            jpeg,
+            OutputFormat::Png,
+            #[cfg(feature = "webp")]
+            OutputFormat::WebP,
+        ];
+        let mut format_collector = MetricsCollector::new();
+        for format in formats {
+            let name = format!("streaming-{}", format.extension());
+            let format_dir = base_dir.join(&name);
+            fs::create_dir_all(&format_dir)?;
+            let format_config = ProcessorConfig {
+                save: Some(SaveConfig { format }),
+                ..config.clone()
+            };
+            RunConfig::new(&name, count, &format_config).save(&format_dir)?;
+            let stats = StreamingPipeline::builder()
+                .config(format_config)
+                .output_dir(format_dir)
+                .build()?
+                .run(count)
+                .await?;
+            info!(
+                format = format.extension(),
+                total_time_ms = stats.total_time_ms,
+                avg_saved_bytes = stats.avg_saved_bytes,
+                "format summary"
+            );
+            format_collector.add_run(stats.to_run(&name));
+            format_collector.add_custom_metric(
+                &name,
+                "Avg file (KB)".to_string(),
+                stats.avg_saved_bytes as f64 / 1024.0,
+            )?;
+        }
+        format_collector.print_comparison();
+    }
+
+    // The streaming run again in each resize mode, weighing resize time against file size
+    if args.compare_resize_modes {
+        let mut mode_collector = MetricsCollector::new();
+        for (name, mode) in RESIZE_MODES {
+            let name = format!("streaming-{}", name);
+            let mode_dir = base_dir.join(&name);
+            fs::create_dir_all(&mode_dir)?;
+            let mode_config = ProcessorConfig {
+                resize_mode: mode,
+                ..config.clone()
+            };
+            RunConfig::new(&name, count, &mode_config).save(&mode_dir)?;
+            let stats = StreamingPipeline::builder()
+                .config(mode_config)
+                .output_dir(mode_dir)
+                .build()?
+                .run(count)
+                .await?;
+            mode_collector.add_run(stats.to_run(&name));
+            mode_collector.add_custom_metric(
+                &name,
+                "Avg file (KB)".to_string(),
+                stats.avg_saved_bytes as f64 / 1024.0,
+            )?;
+        }
+        mode_collector.print_comparison();
+    }
+
+    // The streaming run again per channel capacity, weighing throughput against memory
+    if args.sweep_capacity {
+        let capacities = [1, 5, 10, 50, 100];
+        let sweep_dir = base_dir.join("streaming-capacity");
+        let results = sweep_channel_capacity(count, &capacities, &sweep_dir, &config).await?;
+        let mut capacity_collector = MetricsCollector::new();
+        for (capacity, stats) in capacities.iter().zip(results) {
+            capacity_collector.add_run(stats.to_run(&format!("streaming-cap-{}", capacity)));
+        }
+        capacity_collector.print_comparison();
+    }
+
+    if let Some((_, shutdown, server)) = live_metrics {
+        shutdown.cancel();
+        server.await??;
+    }
+    #[cfg(feature = "otel")]
+    otel_provider.shutdown()?;
+
+    Ok(())
+}
+
+/// Run naive, batched and streaming at each of `counts`, print throughput with approaches
+/// as rows and counts as columns, and save that matrix to `count_sweep.csv`
+async fn sweep_counts(counts: &[usize], base_dir: &Path, config: &ProcessorConfig) -> Result<()> {
+    let mut collector = MetricsCollector::new();
+    for &count in counts {
+        let count_dir = base_dir.join(format!("sweep-{}", count));
+        let (naive_dir, batched_dir, streaming_dir) = (
+            count_dir.join("naive"),
+            count_dir.join("batched"),
+            count_dir.join("streaming"),
+        );
+        fs::create_dir_all(&naive_dir)?;
+        fs::create_dir_all(&batched_dir)?;
+        fs::create_dir_all(&streaming_dir)?;
+
+        RunConfig::new("naive", count, config).save(&naive_dir)?;
+        let naive = process_naive(count, &naive_dir, config).await?;
+        RunConfig::new("batched", count, config)
+            .with_batch_size(10)
+            .save(&batched_dir)?;
+        let batched_output = OutputSink::Directory(batched_dir);
+        let batched = process_batched(count, 10, &batched_output, config).await?;
+        RunConfig::new("streaming", count, config).save(&streaming_dir)?;
+        let streaming = StreamingPipeline::builder()
+            .config(config.clone())
+            .output_dir(streaming_dir)
+            .build()?
+            .run(count)
+            .await?;
+        info!(
+            count,
+            naive_ms = naive.total_time_ms,
+            batched_ms = batched.total_time_ms,
+            streaming_ms = streaming.total_time_ms,
+            "count sweep step"
+        );
+
+        collector.add_run(naive.to_run("naive"));
+        collector.add_run(batched.to_run("batched"));
+        collector.add_run(streaming.to_run("streaming"));
+    }
 
+    collector.print_comparison();
+    let csv_path = base_dir.join("count_sweep.csv");
+    match collector.save_scaling_csv(&csv_path) {
+        Ok(()) => info!(path = %csv_path.display(), "saved count sweep"),
+        Err(e) => warn!(error = %e, "not saving count sweep"),
+    }
     Ok(())
 }
 
-fn parse_count_arg() -> Option<usize> {
+/// Tile up to `count` of the images saved under `dir` into a sheet at `output_path`, warning
+/// instead of failing the run when that doesn't work out
+fn save_contact_sheet(dir: &Path, count: usize, output_path: &Path) {
+    let paths = UrlGenerator::from_directory(dir, &["jpg", "jpeg", "png", "webp"]).map(|files| {
+        files
+            .urls()
+            .into_iter()
+            .take(count)
+            .map(PathBuf::from)
+            .collect::<Vec<_>>()
+    });
+    let saved =
+        paths.and_then(|paths| make_contact_sheet(&paths, CONTACT_SHEET_COLUMNS, output_path));
+    if let Err(e) = saved {
+        warn!(dir = %dir.display(), error = %e, "not saving contact sheet");
+    }
+}
+
+/// Thumbnails per row on `--contact-sheet` sheets
+const CONTACT_SHEET_COLUMNS: usize = 10;
+
+#[derive(Default)]
+struct Args {
+    count: Option<usize>,
+    resize: Option<ResizeConfig>,
+    save: Option<SaveConfig>,
+    /// `--quality`, kept for the JPEG run of `--compare-formats` whatever `--format` is
+    quality: Option<u8>,
+    url_template: Option<UrlTemplate>,
+    metrics_port: Option<u16>,
+    rate_limit: Option<f64>,
+    warmup: usize,
+    histogram: bool,
+    download: Option<DownloadConfig>,
+    compare_connection_reuse: bool,
+    /// `None` saves files as usual
+    output_format: Option<RecordFormat>,
+    sweep_capacity: bool,
+    resize_mode: Option<ResizeMode>,
+    compare_resize_modes: bool,
+    compare_memory_refresh: bool,
+    /// Image counts for `--sweep-counts`
+    sweep_counts: Option<Vec<usize>>,
+    compare_download_strategy: bool,
+    contact_sheet: bool,
+    cache_dir: Option<PathBuf>,
+    compare_formats: bool,
+}
+
+/// `--resize-mode` names; `fill` pads with black
+const RESIZE_MODES: [(&str, ResizeMode); 4] = [
+    ("exact", ResizeMode::Exact),
+    ("fit", ResizeMode::Fit),
+    ("cover", ResizeMode::Cover),
+    ("fill", ResizeMode::Padded { color: [0; 3] }),
+];
+
+/// `[count] [--width N] [--height N] [--filter NAME] [--format jpeg|png|webp] [--quality N]
+/// [--url-template T] [--img-width N] [--img-height N] [--metrics-port PORT] [--rate-limit N]
+/// [--warmup N] [--histogram] [--connect-timeout-ms N] [--read-timeout-ms N] [--input-dir DIR]
+/// [--compare-connection-reuse] [--output-format files|ndjson|csv] [--sweep-capacity]
+/// [--resize-mode exact|fit|cover|fill|compare] [--compare-memory-refresh]
+/// [--sweep-counts N,N,...] [--compare-download-strategy] [--contact-sheet] [--cache-dir DIR]
+/// [--compare-formats]`;
+/// invalid values fall back to defaults. `--quality` only applies to JPEG. `--input-dir`
+/// processes the images under DIR instead of downloading any, all of them unless a count is
+/// given. `--compare-connection-reuse` repeats the naive run with a fresh
+/// connection per image. `--output-format ndjson|csv` runs only the streaming pipeline, writing one
+/// record per image to stdout instead of saving files. `--sweep-capacity` repeats the streaming run
+/// across a range of channel capacities. `--resize-mode compare` repeats it once per mode.
+/// `--compare-memory-refresh` repeats the naive run with the memory monitor refreshing every
+/// process, showing what memory sampling costs. `--sweep-counts 10,50,100` runs only naive, batched and
+/// streaming, once per count, and compares their throughput across counts.
+/// `--compare-download-strategy` repeats the streaming run multiplexing every download over one
+/// HTTP/2 connection, which the server must support. `--contact-sheet` tiles each approach's
+/// saved images into `<approach>-contact-sheet.png` next to their directories. `--cache-dir`
+/// keeps downloads served with an `ETag` under DIR and revalidates them on later runs.
+/// `--compare-formats` repeats the streaming run saving JPEG at `--quality`, PNG and, with the
+/// `webp` feature, WebP.
+fn parse_args() -> Args {
+    let mut parsed = Args::default();
+    let (mut format, mut quality) = (None, None);
+    let (mut url_template, mut input_dir) = (None, None);
+    let (mut img_width, mut img_height) = (None, None);
     let mut args = env::args().skip(1);
-    let count = args.next()?;
-    match count.parse::<usize>() {
-        Ok(value) => Some(value),
-        Err(_) => {
-            warn!(arg = %count, "invalid count arg, falling back to default");
-            None
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--width" | "--height" | "--filter" => {
+                let Some(value) = args.next() else {
+                    warn!(arg = %arg, "missing value, ignoring");
+                    continue;
+                };
+                let resize = parsed.resize.get_or_insert_with(ResizeConfig::default);
+                let ok = match arg.as_str() {
+                    "--width" => value.parse().map(|width| resize.width = width).is_ok(),
+                    "--height" => value.parse().map(|height| resize.height = height).is_ok(),
+                    _ => parse_filter(&value).map(|filter| resize.filter = filter).is_some(),
+                };
+                if !ok {
+                    warn!(arg = %arg, value = %value, "invalid value, falling back to default");
+                }
+            }
+            "--format" => match args.next().as_deref().and_then(parse_format) {
+                Some(value) => format = Some(value),
+                None => warn!(arg = %arg, "invalid format, falling back to default"),
+            },
+            "--quality" => match args.next().map(|value| value.parse::<u8>()) {
+                Some(Ok(value @ 1..=100)) => quality = Some(value),
+                _ => warn!(arg = %arg, "invalid quality, falling back to default"),
+            },
+            "--url-template" => match args.next() {
+                Some(template) => url_template = Some(template),
+                None => warn!(arg = %arg, "missing value, ignoring"),
+            },
+            "--cache-dir" => match args.next() {
+                Some(dir) => parsed.cache_dir = Some(PathBuf::from(dir)),
+                None => warn!(arg = %arg, "missing value, ignoring"),
+            },
+            "--input-dir" => match args.next() {
+                Some(dir) => input_dir = Some(dir),
+                None => warn!(arg = %arg, "missing value, ignoring"),
+            },
+            "--metrics-port" => match args.next().map(|value| value.parse()) {
+                Some(Ok(port)) => parsed.metrics_port = Some(port),
+                _ => warn!(arg = %arg, "invalid metrics port, ignoring"),
+            },
+            "--histogram" => parsed.histogram = true,
+            "--compare-connection-reuse" => parsed.compare_connection_reuse = true,
+            "--sweep-capacity" => parsed.sweep_capacity = true,
+            "--compare-memory-refresh" => parsed.compare_memory_refresh = true,
+            "--compare-download-strategy" => parsed.compare_download_strategy = true,
+            "--contact-sheet" => parsed.contact_sheet = true,
+            "--compare-formats" => parsed.compare_formats = true,
+            "--sweep-counts" => {
+                let counts = args.next().and_then(|value| {
+                    value
+                        .split(',')
+                        .map(|count| count.trim().parse::<usize>().ok())
+                        .collect::<Option<Vec<_>>>()
+                });
+                match counts {
+                    Some(counts) if !counts.is_empty() => parsed.sweep_counts = Some(counts),
+                    _ => warn!(arg = %arg, "invalid count list, ignoring"),
+                }
+            }
+            "--resize-mode" => match args.next().as_deref() {
+                Some("compare") => parsed.compare_resize_modes = true,
+                name => match name.and_then(parse_resize_mode) {
+                    Some(mode) => parsed.resize_mode = Some(mode),
+                    None => warn!(arg = %arg, "invalid resize mode, falling back to default"),
+                },
+            },
+            "--output-format" => match args.next().as_deref() {
+                Some("files") => parsed.output_format = None,
+                Some("ndjson") => parsed.output_format = Some(RecordFormat::Ndjson),
+                Some("csv") => parsed.output_format = Some(RecordFormat::Csv),
+                _ => warn!(arg = %arg, "invalid output format, falling back to files"),
+            },
+            "--connect-timeout-ms" | "--read-timeout-ms" => {
+                let Some(Ok(ms)) = args.next().map(|value| value.parse()) else {
+                    warn!(arg = %arg, "invalid timeout, falling back to default");
+                    continue;
+                };
+                let download = parsed.download.get_or_insert_with(DownloadConfig::default);
+                match arg.as_str() {
+                    "--connect-timeout-ms" => download.connect_timeout = Duration::from_millis(ms),
+                    _ => download.read_timeout = Duration::from_millis(ms),
+                }
+            }
+            "--warmup" => match args.next().map(|value| value.parse()) {
+                Some(Ok(count)) => parsed.warmup = count,
+                _ => warn!(arg = %arg, "invalid warmup count, ignoring"),
+            },
+            "--rate-limit" => match args.next().map(|value| value.parse::<f64>()) {
+                Some(Ok(rate)) if rate > 0.0 => parsed.rate_limit = Some(rate),
+                _ => warn!(arg = %arg, "invalid rate limit, ignoring"),
+            },
+            "--img-width" | "--img-height" => match args.next().map(|value| value.parse()) {
+                Some(Ok(size)) if arg == "--img-width" => img_width = Some(size),
+                Some(Ok(size)) => img_height = Some(size),
+                _ => warn!(arg = %arg, "invalid image size, falling back to default"),
+            },
+            _ => match arg.parse::<usize>() {
+                Ok(value) => parsed.count = Some(value),
+                Err(_) => warn!(arg = %arg, "invalid count arg, falling back to default"),
+            },
+        }
+    }
+    parsed.quality = quality;
+    if format.is_some() || quality.is_some() {
+        let format = match format.unwrap_or_default() {
+            OutputFormat::Jpeg(default) => OutputFormat::Jpeg(quality.unwrap_or(default)),
+            format => format,
+        };
+        parsed.save = Some(SaveConfig { format });
+    }
+    if url_template.is_some() || img_width.is_some() || img_height.is_some() {
+        let (width, height) = (img_width.unwrap_or(800), img_height.unwrap_or(600));
+        parsed.url_template = Some(match url_template {
+            Some(template) => UrlTemplate::custom(&template, width, height),
+            None => UrlTemplate::Picsum { width, height },
+        });
+    }
+    if let Some(dir) = input_dir {
+        match UrlGenerator::from_directory(Path::new(&dir), &["jpg", "jpeg", "png"]) {
+            Ok(files) => {
+                let files = files.urls();
+                parsed.count.get_or_insert(files.len());
+                parsed.url_template = Some(UrlTemplate::Fixed(files));
+            }
+            Err(e) => warn!(dir = %dir, error = %e, "failed to list input directory, ignoring"),
         }
     }
+    parsed
+}
+
+fn parse_format(name: &str) -> Option<OutputFormat> {
+    match name.to_ascii_lowercase().as_str() {
+        "jpeg" | "jpg" => Some(OutputFormat::default()),
+        "png" => Some(OutputFormat::Png),
+        #[cfg(feature = "webp")]
+        "webp" => Some(OutputFormat::WebP),
+        _ => None,
+    }
+}
+
+fn parse_resize_mode(name: &str) -> Option<ResizeMode> {
+    let name = name.to_ascii_lowercase();
+    RESIZE_MODES
+        .iter()
+        .find(|(mode_name, _)| *mode_name == name)
+        .map(|(_, mode)| *mode)
+}
+
+fn parse_filter(name: &str) -> Option<FilterType> {
+    match name.to_ascii_lowercase().as_str() {
+        "nearest" => Some(FilterType::Nearest),
+        "triangle" => Some(FilterType::Triangle),
+        "catmullrom" => Some(FilterType::CatmullRom),
+        "gaussian" => Some(FilterType::Gaussian),
+        "lanczos3" => Some(FilterType::Lanczos3),
+        _ => None,
+    }
 }
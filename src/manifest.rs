@@ -0,0 +1,85 @@
+// src/manifest.rs
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, File},
+    io::{BufRead, BufReader, Write},
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+pub const MANIFEST_FILENAME: &str = "manifest.jsonl";
+
+/// One saved image, written as a line of `manifest.jsonl`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub url: String,
+    pub filename: String,
+    pub bytes: u64,
+    pub download_ms: u64,
+    pub resize_ms: u64,
+    /// Milliseconds since the Unix epoch
+    pub saved_at_ms: u64,
+}
+
+pub fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Write `entries` to `<output_dir>/manifest.jsonl` via a temp file and rename
+pub fn write_manifest(output_dir: &Path, entries: &[ManifestEntry]) -> Result<()> {
+    let path = output_dir.join(MANIFEST_FILENAME);
+    let tmp_path = path.with_extension("jsonl.tmp");
+
+    let mut file = File::create(&tmp_path)?;
+    for entry in entries {
+        writeln!(file, "{}", serde_json::to_string(entry)?)?;
+    }
+    file.sync_all()?;
+    fs::rename(tmp_path, path)?;
+    Ok(())
+}
+
+pub fn read_manifest(path: &Path) -> Result<Vec<ManifestEntry>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut entries = vec![];
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        entries.push(serde_json::from_str(&line)?);
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_entries() {
+        let dir = Path::new("test_output_manifest");
+        fs::create_dir_all(dir).unwrap();
+
+        let entry = ManifestEntry {
+            url: "https://example.com/1.jpg".to_string(),
+            filename: "abc.jpg".to_string(),
+            bytes: 1024,
+            download_ms: 30,
+            resize_ms: 12,
+            saved_at_ms: now_ms(),
+        };
+        write_manifest(dir, &[entry.clone(), entry.clone()]).unwrap();
+
+        let entries = read_manifest(&dir.join(MANIFEST_FILENAME)).unwrap();
+        assert_eq!(entries, vec![entry.clone(), entry]);
+        assert!(!dir.join("manifest.jsonl.tmp").exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
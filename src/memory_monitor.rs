@@ -1,22 +1,114 @@
 // src/memory_monitor.rs
 
-use sysinfo::{Pid, ProcessesToUpdate, System};
+use std::{
+    collections::VecDeque,
+    fmt,
+    sync::{
+        atomic::{AtomicU32, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use sysinfo::{Pid, ProcessRefreshKind, ProcessesToUpdate, System};
+use tokio::task::JoinHandle;
+use tracing::warn;
 
 pub struct MemoryMonitor {
     system: System,
     pid: Pid,
+    limit_mb: Option<u64>,
+    full_refresh: bool,
+    baseline_mb: u64,
+}
+
+impl Default for MemoryMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl MemoryMonitor {
     pub fn new() -> Self {
         let system = System::new();
         let pid = sysinfo::get_current_pid().unwrap();
-        MemoryMonitor { system, pid }
+        MemoryMonitor {
+            system,
+            pid,
+            limit_mb: None,
+            full_refresh: false,
+            baseline_mb: 0,
+        }
+    }
+
+    /// Like [`new`](Self::new), taking the current usage as the baseline that
+    /// [`baseline_subtracted_mb`](Self::baseline_subtracted_mb) leaves out, so the runtime,
+    /// libraries and anything allocated before this point aren't counted
+    pub fn with_baseline() -> Self {
+        let mut monitor = Self::new();
+        monitor.baseline_mb = monitor.current_usage_mb();
+        monitor
+    }
+
+    /// Usage when the monitor was created by [`with_baseline`](Self::with_baseline), else 0
+    pub fn baseline_mb(&self) -> u64 {
+        self.baseline_mb
+    }
+
+    /// [`current_usage_mb`](Self::current_usage_mb) above the baseline, 0 if usage has
+    /// dropped below it
+    pub fn baseline_subtracted_mb(&mut self) -> u64 {
+        self.current_usage_mb().saturating_sub(self.baseline_mb)
+    }
+
+    /// Refresh every process in [`current_usage_mb`](Self::current_usage_mb) rather than just
+    /// this one, as sysinfo's plain `refresh_processes` does. Only useful for measuring what
+    /// the targeted refresh saves.
+    pub fn set_full_refresh(&mut self, full_refresh: bool) {
+        self.full_refresh = full_refresh;
+    }
+
+    /// Usage above `limit` MB counts as [`threshold_exceeded`](Self::threshold_exceeded)
+    pub fn set_limit_mb(&mut self, limit: u64) {
+        self.limit_mb = Some(limit);
+    }
+
+    /// Whether current usage is over the limit; always false without one
+    pub fn threshold_exceeded(&mut self) -> bool {
+        let usage = self.current_usage_mb();
+        self.exceeds_limit(usage)
+    }
+
+    /// Like [`threshold_exceeded`](Self::threshold_exceeded), for a usage already sampled
+    pub fn exceeds_limit(&self, usage_mb: u64) -> bool {
+        self.limit_mb.is_some_and(|limit| usage_mb > limit)
     }
 
     /// Get current process memory usage in MB
     pub fn current_usage_mb(&mut self) -> u64 {
-        self.system.refresh_processes(ProcessesToUpdate::All, true);
+        if !self.full_refresh {
+            return self.current_usage_mb_fast();
+        }
+        self.system.refresh_processes_specifics(
+            ProcessesToUpdate::All,
+            true,
+            ProcessRefreshKind::new().with_memory(),
+        );
+        self.process_memory_mb()
+    }
+
+    /// [`current_usage_mb`](Self::current_usage_mb) refreshing only this process, whatever
+    /// [`set_full_refresh`](Self::set_full_refresh) says
+    pub fn current_usage_mb_fast(&mut self) -> u64 {
+        self.system.refresh_processes_specifics(
+            ProcessesToUpdate::Some(&[self.pid]),
+            true,
+            ProcessRefreshKind::new().with_memory(),
+        );
+        self.process_memory_mb()
+    }
+
+    fn process_memory_mb(&self) -> u64 {
         if let Some(process) = self.system.process(self.pid) {
             process.memory() / 1_024 / 1_024
         } else {
@@ -24,12 +116,34 @@ impl MemoryMonitor {
         }
     }
 
+    /// CPU used by this process since the previous call, as a percentage of one core, so
+    /// above 100 when several cores are busy. The first call has nothing to compare against
+    /// and returns 0. Refreshing memory in between restarts the measurement, so read CPU
+    /// before memory when sampling both.
+    pub fn current_cpu_percent(&mut self) -> f32 {
+        // sysinfo only works out CPU usage when every process is refreshed
+        self.system.refresh_processes_specifics(
+            ProcessesToUpdate::All,
+            true,
+            ProcessRefreshKind::new().with_cpu(),
+        );
+        self.system
+            .process(self.pid)
+            .map_or(0.0, |process| process.cpu_usage())
+    }
+
     /// Get available memory in MB
     pub fn available_mb(&mut self) -> u64 {
         self.system.refresh_memory();
         self.system.available_memory() / 1_024 / 1_024
     }
 
+    /// Get total system memory in MB
+    pub fn total_mb(&mut self) -> u64 {
+        self.system.refresh_memory();
+        self.system.total_memory() / 1_024 / 1_024
+    }
+
     /// Get memory usage as percentage (0-100)
     pub fn usage_percent(&mut self) -> f32 {
         self.system.refresh_memory();
@@ -38,6 +152,263 @@ impl MemoryMonitor {
 
         (used_mem as f32 / total_mem as f32) * 100.0
     }
+
+    /// Watch RSS across repeated runs, warning when it grows by more than
+    /// `threshold_growth_mb` per run on average over the last `window_runs` runs
+    pub fn start_leak_detector(self, threshold_growth_mb: u64, window_runs: usize) -> LeakDetector {
+        LeakDetector {
+            monitor: self,
+            threshold_growth_mb,
+            window_runs: window_runs.max(1),
+            run_start_mb: None,
+            runs: VecDeque::new(),
+        }
+    }
+}
+
+/// Raise `peak`, an f32 stored as its bits, to `value` unless it is already higher
+pub fn store_max_f32(peak: &AtomicU32, value: f32) {
+    let _ = peak.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+        (value > f32::from_bits(bits)).then_some(value.to_bits())
+    });
+}
+
+/// Highest usage a [`spawn_peak_tracker`] task has seen so far
+#[derive(Debug, Default)]
+pub struct PeakReadings {
+    memory_mb: AtomicU64,
+    cpu_percent: AtomicU32,
+    monitor_us: AtomicU64,
+}
+
+impl PeakReadings {
+    pub fn memory_mb(&self) -> u64 {
+        self.memory_mb.load(Ordering::Relaxed)
+    }
+
+    /// As a percentage of one core, like [`MemoryMonitor::current_cpu_percent`]
+    pub fn cpu_percent(&self) -> f32 {
+        f32::from_bits(self.cpu_percent.load(Ordering::Relaxed))
+    }
+
+    /// Total time spent sampling memory, the cost of measuring the run. CPU sampling refreshes
+    /// every process whatever the memory refresh mode, so it is left out.
+    pub fn monitor_us(&self) -> u64 {
+        self.monitor_us.load(Ordering::Relaxed)
+    }
+
+    /// Sample memory above the monitor's baseline, and CPU if `cpu` is set, returning the
+    /// whole process's memory usage
+    fn sample(&self, monitor: &mut MemoryMonitor, cpu: bool) -> u64 {
+        // CPU first: sampling memory would restart its measurement
+        if cpu {
+            store_max_f32(&self.cpu_percent, monitor.current_cpu_percent());
+        }
+        let sample_start = Instant::now();
+        let usage = monitor.baseline_subtracted_mb();
+        let sample_us = sample_start.elapsed().as_micros() as u64;
+        self.monitor_us.fetch_add(sample_us, Ordering::Relaxed);
+        self.memory_mb.fetch_max(usage, Ordering::Relaxed);
+        usage + monitor.baseline_mb()
+    }
+}
+
+/// Track peak memory and CPU usage with `monitor` on a background task, sampling every
+/// `interval_ms`. Memory is also sampled once before this returns, so work that finishes
+/// before the task first runs still reports usage. Peaks count from the monitor's baseline,
+/// so one from [`MemoryMonitor::with_baseline`] reports only what the work allocated. The
+/// caller must abort the handle once the work is done. Must be called from within a Tokio
+/// runtime.
+pub fn spawn_peak_tracker(
+    interval_ms: u64,
+    monitor: MemoryMonitor,
+) -> (JoinHandle<()>, Arc<PeakReadings>) {
+    spawn_peak_tracker_with(interval_ms, monitor, |_| {})
+}
+
+/// [`spawn_peak_tracker`], also passing every memory sample in MB to `on_sample`, baseline
+/// included
+pub fn spawn_peak_tracker_with(
+    interval_ms: u64,
+    mut monitor: MemoryMonitor,
+    mut on_sample: impl FnMut(u64) + Send + 'static,
+) -> (JoinHandle<()>, Arc<PeakReadings>) {
+    let readings = Arc::new(PeakReadings::default());
+    // Memory only; refreshing CPU means refreshing every process, which is left to the task
+    on_sample(readings.sample(&mut monitor, false));
+    let task_readings = Arc::clone(&readings);
+    let handle = tokio::spawn(async move {
+        loop {
+            on_sample(task_readings.sample(&mut monitor, true));
+            tokio::time::sleep(Duration::from_millis(interval_ms)).await;
+        }
+    });
+    (handle, readings)
+}
+
+/// Process RSS sampled every `interval` on a background task, for memory-over-time charts.
+/// Sampling stops when the timeline is dropped.
+pub struct MemoryTimeline {
+    started: Instant,
+    samples: Arc<Mutex<Vec<(Instant, u64)>>>,
+    sampler: JoinHandle<()>,
+}
+
+impl MemoryTimeline {
+    /// Start sampling; must be called from within a Tokio runtime
+    pub fn new(interval: Duration) -> Self {
+        let samples = Arc::new(Mutex::new(vec![]));
+        let sampler_samples = Arc::clone(&samples);
+        let sampler = tokio::spawn(async move {
+            let mut monitor = MemoryMonitor::new();
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                let usage = monitor.current_usage_mb();
+                sampler_samples
+                    .lock()
+                    .unwrap()
+                    .push((Instant::now(), usage));
+            }
+        });
+        MemoryTimeline {
+            started: Instant::now(),
+            samples,
+            sampler,
+        }
+    }
+
+    /// Every sample as (time since the timeline was created, MB)
+    pub fn timeline(&self) -> Vec<(Duration, u64)> {
+        self.timeline_since(self.started)
+    }
+
+    /// Samples taken at or after `start`, still offset from when the timeline was created
+    pub fn timeline_since(&self, start: Instant) -> Vec<(Duration, u64)> {
+        self.samples
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(at, _)| *at >= start)
+            .map(|(at, usage)| (at.saturating_duration_since(self.started), *usage))
+            .collect()
+    }
+
+    pub fn peak_mb(&self) -> u64 {
+        self.samples
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(_, usage)| *usage)
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+impl Drop for MemoryTimeline {
+    fn drop(&mut self) {
+        self.sampler.abort();
+    }
+}
+
+impl fmt::Debug for MemoryTimeline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MemoryTimeline")
+            .field("samples", &self.samples.lock().unwrap().len())
+            .finish()
+    }
+}
+
+/// RSS before and after one run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct RunSample {
+    start_mb: u64,
+    end_mb: u64,
+}
+
+pub struct LeakDetector {
+    monitor: MemoryMonitor,
+    threshold_growth_mb: u64,
+    window_runs: usize,
+    run_start_mb: Option<u64>,
+    runs: VecDeque<RunSample>,
+}
+
+/// Memory trend over the detector's window
+#[derive(Debug, Clone, PartialEq)]
+pub struct LeakReport {
+    pub runs: usize,
+    /// Mean of (end - start) RSS per run
+    pub avg_growth_mb: f64,
+    /// Least-squares slope of end-of-run RSS against run index
+    pub slope_mb_per_run: f64,
+    pub leak_suspected: bool,
+}
+
+impl LeakDetector {
+    pub fn record_run_start(&mut self) {
+        self.run_start_mb = Some(self.monitor.current_usage_mb());
+    }
+
+    /// Close the current run and warn if the window's average growth exceeds the threshold
+    pub fn record_run_end(&mut self) {
+        let end_mb = self.monitor.current_usage_mb();
+        let start_mb = self.run_start_mb.take().unwrap_or(end_mb);
+        self.runs.push_back(RunSample { start_mb, end_mb });
+        if self.runs.len() > self.window_runs {
+            self.runs.pop_front();
+        }
+
+        let report = self.analyze();
+        if report.leak_suspected {
+            warn!(
+                growth_mb = report.avg_growth_mb,
+                slope_mb_per_run = report.slope_mb_per_run,
+                "possible memory leak detected"
+            );
+        }
+    }
+
+    pub fn analyze(&self) -> LeakReport {
+        let n = self.runs.len();
+        if n == 0 {
+            return LeakReport {
+                runs: 0,
+                avg_growth_mb: 0.0,
+                slope_mb_per_run: 0.0,
+                leak_suspected: false,
+            };
+        }
+
+        let avg_growth_mb = self
+            .runs
+            .iter()
+            .map(|run| run.end_mb as f64 - run.start_mb as f64)
+            .sum::<f64>()
+            / n as f64;
+
+        let mean_x = (n - 1) as f64 / 2.0;
+        let mean_y = self.runs.iter().map(|run| run.end_mb as f64).sum::<f64>() / n as f64;
+        let (mut covariance, mut variance) = (0.0, 0.0);
+        for (i, run) in self.runs.iter().enumerate() {
+            let dx = i as f64 - mean_x;
+            covariance += dx * (run.end_mb as f64 - mean_y);
+            variance += dx * dx;
+        }
+        let slope_mb_per_run = if variance > 0.0 {
+            covariance / variance
+        } else {
+            0.0
+        };
+
+        LeakReport {
+            runs: n,
+            avg_growth_mb,
+            slope_mb_per_run,
+            leak_suspected: n >= self.window_runs
+                && avg_growth_mb > self.threshold_growth_mb as f64,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -50,6 +421,49 @@ mod tests {
         let usage = monitor.current_usage_mb();
         assert!(usage > 0);
         assert!(usage < 1_000_000); // Less than 1TB :)
+
+        assert!(monitor.current_usage_mb_fast() > 0);
+        monitor.set_full_refresh(true);
+        assert!(monitor.current_usage_mb() > 0);
+    }
+
+    #[test]
+    fn subtracts_baseline() {
+        assert_eq!(MemoryMonitor::new().baseline_mb(), 0);
+
+        let mut monitor = MemoryMonitor::with_baseline();
+        assert!(monitor.baseline_mb() > 0);
+        let allocated = std::hint::black_box(vec![1u8; 64 * 1_024 * 1_024]);
+        assert!(monitor.baseline_subtracted_mb() >= 32);
+        assert!(monitor.baseline_subtracted_mb() < monitor.current_usage_mb());
+        drop(allocated);
+    }
+
+    #[test]
+    fn detects_exceeded_limit() {
+        let mut monitor = MemoryMonitor::new();
+        assert!(!monitor.threshold_exceeded());
+        monitor.set_limit_mb(0);
+        assert!(monitor.threshold_exceeded());
+        monitor.set_limit_mb(u64::MAX);
+        assert!(!monitor.threshold_exceeded());
+    }
+
+    #[test]
+    fn reports_cpu() {
+        let mut monitor = MemoryMonitor::new();
+        assert_eq!(monitor.current_cpu_percent(), 0.0);
+        let start = Instant::now();
+        let mut spins = 0u64;
+        while start.elapsed() < Duration::from_millis(300) {
+            spins = std::hint::black_box(spins + 1);
+        }
+        assert!(monitor.current_cpu_percent() > 0.0);
+
+        let peak = AtomicU32::new(0);
+        store_max_f32(&peak, 42.5);
+        store_max_f32(&peak, 7.0);
+        assert_eq!(f32::from_bits(peak.load(Ordering::Relaxed)), 42.5);
     }
 
     #[test]
@@ -59,4 +473,55 @@ mod tests {
         assert!(percent > 0.0);
         assert!(percent <= 100.0);
     }
+
+    #[tokio::test]
+    async fn tracks_peak_in_background() {
+        let samples = Arc::new(AtomicU64::new(0));
+        let seen = Arc::clone(&samples);
+        let (handle, readings) = spawn_peak_tracker_with(10, MemoryMonitor::new(), move |_| {
+            seen.fetch_add(1, Ordering::Relaxed);
+        });
+        // Sampled before the task has run at all
+        assert!(readings.memory_mb() > 0);
+        assert_eq!(samples.load(Ordering::Relaxed), 1);
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        handle.abort();
+        assert!(samples.load(Ordering::Relaxed) > 2);
+        assert!(readings.monitor_us() > 0);
+    }
+
+    #[tokio::test]
+    async fn samples_memory_over_time() {
+        let timeline = MemoryTimeline::new(Duration::from_millis(10));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let samples = timeline.timeline();
+        assert!(samples.len() >= 3);
+        assert!(samples.windows(2).all(|pair| pair[0].0 <= pair[1].0));
+        assert_eq!(
+            timeline.peak_mb(),
+            samples.iter().map(|(_, usage)| *usage).max().unwrap()
+        );
+        assert!(timeline.timeline_since(Instant::now()).is_empty());
+    }
+
+    #[test]
+    fn detects_growing_memory() {
+        let mut detector = MemoryMonitor::new().start_leak_detector(8, 4);
+        let mut retained: Vec<Vec<u8>> = vec![];
+
+        for _ in 0..4 {
+            detector.record_run_start();
+            // Fill with a non-zero byte so the pages are actually touched
+            retained.push(vec![1u8; 16 * 1_024 * 1_024]);
+            detector.record_run_end();
+        }
+
+        let report = detector.analyze();
+        assert_eq!(report.runs, 4);
+        assert!(report.leak_suspected);
+        assert!(report.slope_mb_per_run > 8.0);
+        assert_eq!(retained.len(), 4);
+    }
 }
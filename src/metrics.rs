@@ -1,10 +1,18 @@
 use anyhow::Result;
-use std::fs::File;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
 use std::io::Write;
 use std::path::Path;
-use tabled::{settings::Style, Table, Tabled};
+use tabled::{builder::Builder, settings::Style, Table, Tabled};
 
-#[derive(Debug, Clone, Tabled)]
+use crate::{image_processor::ImageMetrics, manifest::read_manifest};
+
+/// Runs order by throughput. Different runs with the same throughput are incomparable, so
+/// the order agrees with `==`.
+#[derive(Debug, Clone, PartialEq, Tabled, Serialize, Deserialize)]
+#[serde(remote = "Self")]
 pub struct ProcessingRun {
     #[tabled(rename = "Approach")]
     pub approach: String,
@@ -14,18 +22,193 @@ pub struct ProcessingRun {
     pub total_time_ms: u64,
     #[tabled(rename = "Peak Mem (MB)")]
     pub peak_memory_mb: u64,
+    /// Highest CPU usage sampled during the run, as a percentage of one core
+    #[tabled(rename = "Peak CPU (%)", display("display_cpu"))]
+    #[serde(default)]
+    pub peak_cpu_percent: f32,
     #[tabled(rename = "Avg DL (ms)")]
     pub avg_download_ms: u64,
     #[tabled(rename = "Avg Resize (ms)")]
     pub avg_resize_ms: u64,
+    #[tabled(rename = "P50 DL (ms)")]
+    #[serde(default)]
+    pub p50_download_ms: u64,
+    #[tabled(rename = "P95 DL (ms)")]
+    #[serde(default)]
+    pub p95_download_ms: u64,
+    #[tabled(rename = "P99 DL (ms)")]
+    #[serde(default)]
+    pub p99_download_ms: u64,
+    #[tabled(rename = "P50 Resize (ms)")]
+    #[serde(default)]
+    pub p50_resize_ms: u64,
+    #[tabled(rename = "P95 Resize (ms)")]
+    #[serde(default)]
+    pub p95_resize_ms: u64,
+    #[tabled(rename = "P99 Resize (ms)")]
+    #[serde(default)]
+    pub p99_resize_ms: u64,
+    #[tabled(rename = "Stddev DL (ms)", display("display_stddev"))]
+    #[serde(default)]
+    pub stddev_download_ms: f64,
+    #[tabled(rename = "Stddev Resize (ms)", display("display_stddev"))]
+    #[serde(default)]
+    pub stddev_resize_ms: f64,
+    /// Spread of `total_time_ms` across the repeats this run stands for, 0 for a single
+    /// measurement
+    #[tabled(rename = "Stddev Time (ms)", display("display_stddev"))]
+    #[serde(default)]
+    pub stddev_total_time_ms: f64,
+    /// Derived from `image_count` and `total_time_ms`, and recomputed when deserialized
     #[tabled(rename = "Throughput (img/s)", display("display_throughput"))]
+    #[serde(skip_deserializing)]
     pub throughput: f64,
+    /// Every image's download time, for [`MetricsCollector::print_latency_histogram`]
+    #[tabled(skip)]
+    #[serde(default)]
+    pub download_samples: Vec<u64>,
+    /// Extra caller-defined measurements, shown as additional table and CSV columns
+    #[tabled(skip)]
+    #[serde(default)]
+    pub custom_metrics: HashMap<String, f64>,
 }
 
+const CSV_HEADER: &str =
+    "approach,image_count,total_time_ms,peak_memory_mb,avg_download_ms,avg_resize_ms,\
+p50_download_ms,p95_download_ms,p99_download_ms,p50_resize_ms,p95_resize_ms,p99_resize_ms,\
+stddev_download_ms,stddev_resize_ms,stddev_total_time_ms,peak_cpu_percent,throughput";
+
+/// Metric name suffix, help text, and value getter for one exported gauge
+type Gauge = (&'static str, &'static str, fn(&ProcessingRun) -> f64);
+
 fn display_throughput(throughput: &f64) -> String {
     format!("{:.2}", throughput)
 }
 
+fn display_stddev(stddev: &f64) -> String {
+    format!("{:.1}", stddev)
+}
+
+fn display_cpu(percent: &f32) -> String {
+    format!("{:.1}", percent)
+}
+
+/// Sort `samples` and return their p50, p95 and p99 by nearest rank, all 0 if empty
+pub fn percentiles(samples: &mut [u64]) -> [u64; 3] {
+    if samples.is_empty() {
+        return [0; 3];
+    }
+    samples.sort_unstable();
+    let rank = |pct: f64| {
+        let index = (pct / 100.0 * samples.len() as f64).ceil() as usize;
+        samples[index.clamp(1, samples.len()) - 1]
+    };
+    [rank(50.0), rank(95.0), rank(99.0)]
+}
+
+/// Smallest, largest and mean of `samples`, all 0 if empty
+pub fn min_max_avg(samples: &[f64]) -> [f64; 3] {
+    if samples.is_empty() {
+        return [0.0; 3];
+    }
+    let min = samples.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = samples.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    [min, max, samples.iter().sum::<f64>() / samples.len() as f64]
+}
+
+/// Population standard deviation of `samples`, 0 if empty
+pub fn stddev(samples: &[u64]) -> f64 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+    let n = samples.len() as f64;
+    let mean = samples.iter().sum::<u64>() as f64 / n;
+    let variance = samples
+        .iter()
+        .map(|&sample| (sample as f64 - mean).powi(2))
+        .sum::<f64>()
+        / n;
+    variance.sqrt()
+}
+
+/// Inverse of the standard normal CDF for `p` in (0, 1), by Acklam's rational approximation
+/// (relative error below 1.2e-9)
+fn normal_quantile(p: f64) -> f64 {
+    const A: [f64; 6] = [
+        -3.969683028665376e1,
+        2.209460984245205e2,
+        -2.759285104469687e2,
+        1.383_577_518_672_69e2,
+        -3.066479806614716e1,
+        2.506628277459239,
+    ];
+    const B: [f64; 5] = [
+        -5.447609879822406e1,
+        1.615858368580409e2,
+        -1.556989798598866e2,
+        6.680131188771972e1,
+        -1.328068155288572e1,
+    ];
+    const C: [f64; 6] = [
+        -7.784894002430293e-3,
+        -3.223964580411365e-1,
+        -2.400758277161838,
+        -2.549732539343734,
+        4.374664141464968,
+        2.938163982698783,
+    ];
+    const D: [f64; 4] = [
+        7.784695709041462e-3,
+        3.224671290700398e-1,
+        2.445134137142996,
+        3.754408661907416,
+    ];
+    let tail = |q: f64| {
+        (((((C[0] * q + C[1]) * q + C[2]) * q + C[3]) * q + C[4]) * q + C[5])
+            / ((((D[0] * q + D[1]) * q + D[2]) * q + D[3]) * q + 1.0)
+    };
+
+    let low = 0.02425;
+    if p < low {
+        tail((-2.0 * p.ln()).sqrt())
+    } else if p > 1.0 - low {
+        -tail((-2.0 * (1.0 - p).ln()).sqrt())
+    } else {
+        let q = p - 0.5;
+        let r = q * q;
+        (((((A[0] * r + A[1]) * r + A[2]) * r + A[3]) * r + A[4]) * r + A[5]) * q
+            / (((((B[0] * r + B[1]) * r + B[2]) * r + B[3]) * r + B[4]) * r + 1.0)
+    }
+}
+
+fn throughput(image_count: usize, total_time_ms: u64) -> f64 {
+    (image_count as f64 / total_time_ms as f64) * 1000.0
+}
+
+// `remote = "Self"` turns the derives into inherent functions, so these impls can wrap them
+impl Serialize for ProcessingRun {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        ProcessingRun::serialize(self, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for ProcessingRun {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut run = ProcessingRun::deserialize(deserializer)?;
+        run.throughput = throughput(run.image_count, run.total_time_ms);
+        Ok(run)
+    }
+}
+
+impl PartialOrd for ProcessingRun {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match self.throughput.partial_cmp(&other.throughput) {
+            Some(Ordering::Equal) if self != other => None,
+            ordering => ordering,
+        }
+    }
+}
+
 impl ProcessingRun {
     pub fn new(
         approach: &str,
@@ -35,20 +218,237 @@ impl ProcessingRun {
         avg_download_ms: u64,
         avg_resize_ms: u64,
     ) -> Self {
-        let throughput = (image_count as f64 / total_time_ms as f64) * 1000.0;
-
         Self {
             approach: approach.to_string(),
             image_count,
             total_time_ms,
             peak_memory_mb,
+            peak_cpu_percent: 0.0,
             avg_download_ms,
             avg_resize_ms,
-            throughput,
+            p50_download_ms: 0,
+            p95_download_ms: 0,
+            p99_download_ms: 0,
+            p50_resize_ms: 0,
+            p95_resize_ms: 0,
+            p99_resize_ms: 0,
+            stddev_download_ms: 0.0,
+            stddev_resize_ms: 0.0,
+            stddev_total_time_ms: 0.0,
+            throughput: throughput(image_count, total_time_ms),
+            download_samples: vec![],
+            custom_metrics: HashMap::new(),
         }
     }
+
+    /// Attach `[p50, p95, p99]` download and resize latencies, as returned by [`percentiles`]
+    pub fn with_percentiles(mut self, download: [u64; 3], resize: [u64; 3]) -> Self {
+        [
+            self.p50_download_ms,
+            self.p95_download_ms,
+            self.p99_download_ms,
+        ] = download;
+        [self.p50_resize_ms, self.p95_resize_ms, self.p99_resize_ms] = resize;
+        self
+    }
+
+    /// Attach download and resize standard deviations, as returned by [`stddev`]
+    pub fn with_stddev(mut self, download: f64, resize: f64) -> Self {
+        self.stddev_download_ms = download;
+        self.stddev_resize_ms = resize;
+        self
+    }
+
+    /// Attach a stats type's `peak_cpu_percent`
+    pub fn with_peak_cpu(mut self, percent: f32) -> Self {
+        self.peak_cpu_percent = percent;
+        self
+    }
+
+    /// Attach per-image download times, e.g. a stats type's `download_samples`
+    pub fn with_download_samples(mut self, samples: Vec<u64>) -> Self {
+        self.download_samples = samples;
+        self
+    }
+
+    fn csv_row(&self) -> String {
+        format!(
+            "{},{},{},{},{},{},{},{},{},{},{},{},{:.2},{:.2},{:.2},{:.1},{:.2}",
+            self.approach,
+            self.image_count,
+            self.total_time_ms,
+            self.peak_memory_mb,
+            self.avg_download_ms,
+            self.avg_resize_ms,
+            self.p50_download_ms,
+            self.p95_download_ms,
+            self.p99_download_ms,
+            self.p50_resize_ms,
+            self.p95_resize_ms,
+            self.p99_resize_ms,
+            self.stddev_download_ms,
+            self.stddev_resize_ms,
+            self.stddev_total_time_ms,
+            self.peak_cpu_percent,
+            self.throughput
+        )
+    }
+}
+
+pub const PER_IMAGE_FILENAME: &str = "per_image.csv";
+
+/// Timings and output size of one streamed image, kept when `config.per_image` is set
+#[derive(Debug, Clone, PartialEq, Tabled)]
+pub struct PerImageRecord {
+    #[tabled(rename = "URL")]
+    pub url: String,
+    #[tabled(rename = "DL (ms)")]
+    pub download_ms: u64,
+    #[tabled(rename = "Resize (ms)")]
+    pub resize_ms: u64,
+    /// Encoded file size, 0 for images handed to a result sink
+    #[tabled(rename = "Bytes")]
+    pub bytes: u64,
+}
+
+/// Write `records` to `<output_dir>/per_image.csv`
+pub fn write_per_image_csv(output_dir: &Path, records: &[PerImageRecord]) -> Result<()> {
+    let mut file = File::create(output_dir.join(PER_IMAGE_FILENAME))?;
+    writeln!(file, "url,download_ms,resize_ms,bytes")?;
+    for record in records {
+        writeln!(
+            file,
+            "{},{},{},{}",
+            record.url, record.download_ms, record.resize_ms, record.bytes
+        )?;
+    }
+    Ok(())
+}
+
+/// One measurement from a channel capacity benchmark
+#[derive(Debug, Clone, Tabled)]
+pub struct BenchmarkPoint {
+    #[tabled(rename = "Capacity")]
+    pub capacity: usize,
+    #[tabled(rename = "Throughput (msg/s)")]
+    pub throughput: u64,
+}
+
+/// Named set of `(capacity, throughput)` results, printed in the same style as run comparisons
+#[derive(Debug, Clone)]
+pub struct BenchmarkSeries {
+    pub name: String,
+    pub points: Vec<BenchmarkPoint>,
+}
+
+impl BenchmarkSeries {
+    pub fn new(name: &str, results: &[(usize, u64)]) -> Self {
+        Self {
+            name: name.to_string(),
+            points: results
+                .iter()
+                .map(|&(capacity, throughput)| BenchmarkPoint {
+                    capacity,
+                    throughput,
+                })
+                .collect(),
+        }
+    }
+
+    /// The table [`BenchmarkSeries::print_table`] prints, without the heading
+    pub fn render_table(&self) -> String {
+        Table::new(&self.points).with(Style::rounded()).to_string()
+    }
+
+    pub fn print_table(&self) {
+        println!("\n{}\n", self.name);
+        println!("{}\n", self.render_table());
+    }
+}
+
+/// Aggregate figures across every run in a collector
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SummaryStats {
+    pub best_throughput: f64,
+    pub worst_throughput: f64,
+    pub median_throughput: f64,
+    pub total_images_processed: usize,
+    pub total_time_across_runs_ms: u64,
+    /// Highest peak memory of any run; runs execute one after another so peaks don't stack
+    pub combined_peak_memory_mb: u64,
+}
+
+/// Change in one approach's figures from a baseline, as `current - baseline`
+#[derive(Debug, Clone, PartialEq, Tabled)]
+pub struct RunDelta {
+    #[tabled(rename = "Approach")]
+    pub approach: String,
+    #[tabled(rename = "Δ Time (ms)")]
+    pub total_time_ms: i64,
+    #[tabled(rename = "Δ Peak Mem (MB)")]
+    pub peak_memory_mb: i64,
+    #[tabled(rename = "Δ Avg DL (ms)")]
+    pub avg_download_ms: i64,
+    #[tabled(rename = "Δ Avg Resize (ms)")]
+    pub avg_resize_ms: i64,
+    #[tabled(rename = "Δ Throughput (img/s)", display("display_throughput"))]
+    pub throughput: f64,
+    /// Throughput change relative to the baseline; negative means a slowdown
+    #[tabled(rename = "Δ Throughput (%)", display("display_throughput"))]
+    pub throughput_pct: f64,
+}
+
+/// Result of [`MetricsCollector::compare_with`]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ComparisonReport {
+    /// One delta per approach found in both collectors, in the current collector's order
+    pub deltas: Vec<RunDelta>,
+    /// Approaches that appear in only one of the two collectors
+    pub unmatched: Vec<String>,
+}
+
+impl ComparisonReport {
+    pub fn print_table(&self) {
+        println!("\nFlux Image Processor - Change From Baseline\n");
+        println!("{}\n", Table::new(&self.deltas).with(Style::rounded()));
+        if !self.unmatched.is_empty() {
+            println!("Not compared: {}", self.unmatched.join(", "));
+        }
+    }
+}
+
+/// Download time summed over the images of a run that sent a `Server-Timing` header,
+/// alongside the server-side share of it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct ServerTimingSplit {
+    pub images: usize,
+    pub server_ms: f64,
+    pub download_ms: f64,
+}
+
+impl ServerTimingSplit {
+    /// Add one image, ignored when it had no `Server-Timing` header
+    pub fn record(&mut self, metric: &ImageMetrics) {
+        let Some(timing) = &metric.server_timing else {
+            return;
+        };
+        self.images += 1;
+        self.server_ms += timing.values().sum::<f64>();
+        self.download_ms += metric.download_ms as f64;
+    }
+
+    pub fn avg_server_ms(&self) -> Option<f64> {
+        (self.images > 0).then(|| self.server_ms / self.images as f64)
+    }
+
+    /// Average download time not accounted for by the server
+    pub fn avg_client_ms(&self) -> Option<f64> {
+        (self.images > 0)
+            .then(|| ((self.download_ms - self.server_ms) / self.images as f64).max(0.0))
+    }
 }
 
+#[derive(Default)]
 pub struct MetricsCollector {
     runs: Vec<ProcessingRun>,
 }
@@ -62,72 +462,791 @@ impl MetricsCollector {
         self.runs.push(run);
     }
 
+    /// Append every run from `other`, e.g. one collected by a separate process
+    pub fn merge(&mut self, other: MetricsCollector) {
+        self.runs.extend(other.runs);
+    }
+
+    /// Add `run`, or fold it into the most recent run of the same approach. Timings are
+    /// averaged weighted by `image_count` (percentiles and stddev only approximately), image
+    /// counts add up and peak memory is the higher of the two, as for sub-runs that ran side
+    /// by side.
+    pub fn merge_run_by_approach(&mut self, run: ProcessingRun) {
+        let Some(existing) = self
+            .runs
+            .iter_mut()
+            .rev()
+            .find(|existing| existing.approach == run.approach)
+        else {
+            self.runs.push(run);
+            return;
+        };
+
+        let (a, b) = (existing.image_count as f64, run.image_count as f64);
+        let total = (a + b).max(1.0);
+        let weighted = |x: f64, y: f64| (x * a + y * b) / total;
+        let weighted_ms = |x: u64, y: u64| weighted(x as f64, y as f64).round() as u64;
+
+        existing.image_count += run.image_count;
+        existing.total_time_ms = weighted_ms(existing.total_time_ms, run.total_time_ms);
+        existing.peak_memory_mb = existing.peak_memory_mb.max(run.peak_memory_mb);
+        existing.peak_cpu_percent = existing.peak_cpu_percent.max(run.peak_cpu_percent);
+        existing.avg_download_ms = weighted_ms(existing.avg_download_ms, run.avg_download_ms);
+        existing.avg_resize_ms = weighted_ms(existing.avg_resize_ms, run.avg_resize_ms);
+        existing.p50_download_ms = weighted_ms(existing.p50_download_ms, run.p50_download_ms);
+        existing.p95_download_ms = weighted_ms(existing.p95_download_ms, run.p95_download_ms);
+        existing.p99_download_ms = weighted_ms(existing.p99_download_ms, run.p99_download_ms);
+        existing.p50_resize_ms = weighted_ms(existing.p50_resize_ms, run.p50_resize_ms);
+        existing.p95_resize_ms = weighted_ms(existing.p95_resize_ms, run.p95_resize_ms);
+        existing.p99_resize_ms = weighted_ms(existing.p99_resize_ms, run.p99_resize_ms);
+        existing.stddev_download_ms = weighted(existing.stddev_download_ms, run.stddev_download_ms);
+        existing.stddev_resize_ms = weighted(existing.stddev_resize_ms, run.stddev_resize_ms);
+        existing.stddev_total_time_ms =
+            weighted(existing.stddev_total_time_ms, run.stddev_total_time_ms);
+        existing.throughput = throughput(existing.image_count, existing.total_time_ms);
+        existing.download_samples.extend(run.download_samples);
+        for (name, value) in run.custom_metrics {
+            existing
+                .custom_metrics
+                .entry(name)
+                .and_modify(|current| *current = weighted(*current, value))
+                .or_insert(value);
+        }
+    }
+
+    /// Attach a custom metric to the most recent run of `approach`
+    pub fn add_custom_metric(&mut self, approach: &str, name: String, value: f64) -> Result<()> {
+        let run = self
+            .runs
+            .iter_mut()
+            .rev()
+            .find(|run| run.approach == approach)
+            .ok_or_else(|| anyhow::anyhow!("no run for approach {}", approach))?;
+        run.custom_metrics.insert(name, value);
+        Ok(())
+    }
+
+    /// Split the average download time of `approach` into server-side time, as reported by
+    /// `Server-Timing`, and the client-side remainder. Nothing is added when no image had
+    /// the header.
+    pub fn add_server_timing(&mut self, approach: &str, split: &ServerTimingSplit) -> Result<()> {
+        let (Some(server_ms), Some(client_ms)) = (split.avg_server_ms(), split.avg_client_ms())
+        else {
+            return Ok(());
+        };
+        self.add_custom_metric(approach, "server_ms".to_string(), server_ms)?;
+        self.add_custom_metric(approach, "client_ms".to_string(), client_ms)
+    }
+
+    /// Sorted union of custom metric names across all runs
+    fn custom_metric_names(&self) -> Vec<String> {
+        self.runs
+            .iter()
+            .flat_map(|run| run.custom_metrics.keys().cloned())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    }
+
+    fn comparison_table(&self) -> Table {
+        let custom_names = self.custom_metric_names();
+        if custom_names.is_empty() {
+            return Table::new(&self.runs);
+        }
+
+        let mut builder = Builder::default();
+        let mut header: Vec<String> = ProcessingRun::headers()
+            .into_iter()
+            .map(|h| h.into_owned())
+            .collect();
+        header.extend(custom_names.iter().cloned());
+        builder.push_record(header);
+
+        for run in &self.runs {
+            let mut row: Vec<String> = run.fields().into_iter().map(|f| f.into_owned()).collect();
+            row.extend(custom_names.iter().map(|name| {
+                run.custom_metrics
+                    .get(name)
+                    .map(|value| format!("{:.2}", value))
+                    .unwrap_or_default()
+            }));
+            builder.push_record(row);
+        }
+        builder.build()
+    }
+
+    /// Image counts in ascending order, and for each approach in the order it first ran, its
+    /// throughput at each of those counts. `None` when no approach ran at more than one
+    /// count. If an approach ran more than once at a count, the last run wins.
+    #[allow(clippy::type_complexity)]
+    fn scaling_matrix(&self) -> Option<(Vec<usize>, Vec<(&str, Vec<Option<f64>>)>)> {
+        let counts: Vec<usize> = self
+            .runs
+            .iter()
+            .map(|run| run.image_count)
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+
+        let mut rows: Vec<(&str, Vec<Option<f64>>)> = Vec::new();
+        for run in &self.runs {
+            let column = counts.binary_search(&run.image_count).unwrap();
+            let index = match rows.iter().position(|(name, _)| *name == run.approach) {
+                Some(index) => index,
+                None => {
+                    rows.push((&run.approach, vec![None; counts.len()]));
+                    rows.len() - 1
+                }
+            };
+            rows[index].1[column] = Some(run.throughput);
+        }
+
+        let scaled = rows
+            .iter()
+            .any(|(_, cells)| cells.iter().flatten().count() > 1);
+        scaled.then_some((counts, rows))
+    }
+
+    /// Throughput with one row per approach and one column per image count, for runs swept
+    /// across several counts
+    fn scaling_table(&self) -> Option<Table> {
+        let (counts, rows) = self.scaling_matrix()?;
+        let mut builder = Builder::default();
+        let mut header = vec!["Approach".to_string()];
+        header.extend(counts.iter().map(|count| format!("{} images", count)));
+        builder.push_record(header);
+        for (approach, cells) in rows {
+            let mut row = vec![approach.to_string()];
+            row.extend(cells.iter().map(|cell| {
+                cell.map(|throughput| format!("{:.2}", throughput))
+                    .unwrap_or_default()
+            }));
+            builder.push_record(row);
+        }
+        Some(builder.build())
+    }
+
+    /// Rebuild a single approximate run from a `manifest.jsonl`. The approach is named after
+    /// the manifest's directory, time spans the first download to the last save, and peak
+    /// memory is unknown so it is reported as 0.
+    pub fn from_manifest(path: &Path) -> Result<Self> {
+        let entries = read_manifest(path)?;
+        anyhow::ensure!(!entries.is_empty(), "manifest {} is empty", path.display());
+
+        let approach = path
+            .parent()
+            .and_then(|dir| dir.file_name())
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "manifest".to_string());
+        let count = entries.len() as u64;
+        let first_start = entries
+            .iter()
+            .map(|entry| {
+                entry
+                    .saved_at_ms
+                    .saturating_sub(entry.download_ms + entry.resize_ms)
+            })
+            .min()
+            .unwrap_or(0);
+        let last_save = entries
+            .iter()
+            .map(|entry| entry.saved_at_ms)
+            .max()
+            .unwrap_or(0);
+        let total_download_ms: u64 = entries.iter().map(|entry| entry.download_ms).sum();
+        let total_resize_ms: u64 = entries.iter().map(|entry| entry.resize_ms).sum();
+
+        let mut collector = Self::new();
+        collector.add_run(ProcessingRun::new(
+            &approach,
+            entries.len(),
+            (last_save - first_start).max(1),
+            0,
+            total_download_ms / count,
+            total_resize_ms / count,
+        ));
+        Ok(collector)
+    }
+
+    /// Average each `window` consecutive runs to smooth out noisy repeats. Averaged runs are
+    /// named after the window's first run and its index range, e.g. `streaming[3-7]`.
+    /// A window of 1 returns the runs unchanged.
+    pub fn rolling_average(&self, window: usize) -> Vec<ProcessingRun> {
+        if window <= 1 {
+            return self.runs.clone();
+        }
+
+        self.runs
+            .windows(window)
+            .enumerate()
+            .map(|(start, runs)| {
+                let n = runs.len() as u64;
+                let mean =
+                    |value: fn(&ProcessingRun) -> u64| runs.iter().map(value).sum::<u64>() / n;
+                ProcessingRun {
+                    approach: format!("{}[{}-{}]", runs[0].approach, start, start + window - 1),
+                    image_count: (runs.iter().map(|run| run.image_count as u64).sum::<u64>() / n)
+                        as usize,
+                    total_time_ms: mean(|run| run.total_time_ms),
+                    peak_memory_mb: mean(|run| run.peak_memory_mb),
+                    peak_cpu_percent: runs.iter().map(|run| run.peak_cpu_percent).sum::<f32>()
+                        / n as f32,
+                    avg_download_ms: mean(|run| run.avg_download_ms),
+                    avg_resize_ms: mean(|run| run.avg_resize_ms),
+                    p50_download_ms: mean(|run| run.p50_download_ms),
+                    p95_download_ms: mean(|run| run.p95_download_ms),
+                    p99_download_ms: mean(|run| run.p99_download_ms),
+                    p50_resize_ms: mean(|run| run.p50_resize_ms),
+                    p95_resize_ms: mean(|run| run.p95_resize_ms),
+                    p99_resize_ms: mean(|run| run.p99_resize_ms),
+                    stddev_download_ms: runs.iter().map(|run| run.stddev_download_ms).sum::<f64>()
+                        / n as f64,
+                    stddev_resize_ms: runs.iter().map(|run| run.stddev_resize_ms).sum::<f64>()
+                        / n as f64,
+                    stddev_total_time_ms: stddev(
+                        &runs.iter().map(|run| run.total_time_ms).collect::<Vec<_>>(),
+                    ),
+                    throughput: runs.iter().map(|run| run.throughput).sum::<f64>() / n as f64,
+                    download_samples: runs
+                        .iter()
+                        .flat_map(|run| run.download_samples.iter().copied())
+                        .collect(),
+                    custom_metrics: HashMap::new(),
+                }
+            })
+            .collect()
+    }
+
+    /// Run with the highest throughput
+    pub fn best_throughput(&self) -> Option<&ProcessingRun> {
+        self.runs
+            .iter()
+            .max_by(|a, b| a.throughput.total_cmp(&b.throughput))
+    }
+
+    /// Run with the lowest peak memory
+    pub fn best_memory(&self) -> Option<&ProcessingRun> {
+        self.runs.iter().min_by_key(|run| run.peak_memory_mb)
+    }
+
+    /// Run with the lowest average time per image, download plus resize
+    pub fn best_latency(&self) -> Option<&ProcessingRun> {
+        self.runs
+            .iter()
+            .min_by_key(|run| run.avg_download_ms + run.avg_resize_ms)
+    }
+
+    /// Every run, highest throughput first
+    pub fn rank_by_throughput(&self) -> Vec<&ProcessingRun> {
+        let mut ranked: Vec<_> = self.runs.iter().collect();
+        ranked.sort_by(|a, b| b.throughput.total_cmp(&a.throughput));
+        ranked
+    }
+
+    /// e.g. `Winner: streaming (2.3x throughput)`, against the slowest run. `None` with
+    /// fewer than two runs.
+    fn winner_line(&self) -> Option<String> {
+        let ranked = self.rank_by_throughput();
+        let (best, worst) = (ranked.first()?, ranked.last()?);
+        if ranked.len() < 2 || worst.throughput <= 0.0 {
+            return None;
+        }
+        Some(format!(
+            "Winner: {} ({:.1}x throughput)",
+            best.approach,
+            best.throughput / worst.throughput
+        ))
+    }
+
+    /// Summarize all stored runs, all zeros if there are none
+    pub fn summary_statistics(&self) -> SummaryStats {
+        if self.runs.is_empty() {
+            return SummaryStats::default();
+        }
+
+        let mut throughputs: Vec<f64> = self.runs.iter().map(|run| run.throughput).collect();
+        throughputs.sort_by(f64::total_cmp);
+        let mid = throughputs.len() / 2;
+        let median_throughput = if throughputs.len().is_multiple_of(2) {
+            (throughputs[mid - 1] + throughputs[mid]) / 2.0
+        } else {
+            throughputs[mid]
+        };
+
+        SummaryStats {
+            best_throughput: throughputs[throughputs.len() - 1],
+            worst_throughput: throughputs[0],
+            median_throughput,
+            total_images_processed: self.runs.iter().map(|run| run.image_count).sum(),
+            total_time_across_runs_ms: self.runs.iter().map(|run| run.total_time_ms).sum(),
+            combined_peak_memory_mb: self
+                .runs
+                .iter()
+                .map(|run| run.peak_memory_mb)
+                .max()
+                .unwrap_or(0),
+        }
+    }
+
+    /// Append `run` as one CSV row, writing the header first if the file is empty.
+    /// Custom metrics aren't included. Not safe with several processes appending at once.
+    pub fn append_run_to_csv(path: &Path, run: &ProcessingRun) -> Result<()> {
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        if file.metadata()?.len() == 0 {
+            writeln!(file, "{}", CSV_HEADER)?;
+        }
+        writeln!(file, "{}", run.csv_row())?;
+        Ok(())
+    }
+
+    /// Write every run as a JSON array, pretty-printed unless `compact` is set
+    pub fn save_json(&self, path: &Path, compact: bool) -> Result<()> {
+        let file = File::create(path)?;
+        if compact {
+            serde_json::to_writer(file, &self.runs)?;
+        } else {
+            serde_json::to_writer_pretty(file, &self.runs)?;
+        }
+        Ok(())
+    }
+
+    /// Read back runs written by [`MetricsCollector::save_json`]
+    pub fn load_json(path: &Path) -> Result<Self> {
+        let runs = serde_json::from_reader(File::open(path)?)?;
+        Ok(MetricsCollector { runs })
+    }
+
     pub fn save_csv(&self, path: &Path) -> Result<()> {
+        let custom_names = self.custom_metric_names();
         let mut file = File::create(path)?;
-        writeln!(file, "approach,image_count,total_time_ms,peak_memory_mb,avg_download_ms,avg_resize_ms,throughput")?;
+        write!(file, "{}", CSV_HEADER)?;
+        for name in &custom_names {
+            write!(file, ",{}", name)?;
+        }
+        writeln!(file)?;
 
         for run in &self.runs {
-            writeln!(
-                file,
-                "{},{},{},{},{},{},{:.2}",
-                run.approach,
-                run.image_count,
-                run.total_time_ms,
-                run.peak_memory_mb,
-                run.avg_download_ms,
-                run.avg_resize_ms,
-                run.throughput
-            )?;
+            write!(file, "{}", run.csv_row())?;
+            for name in &custom_names {
+                match run.custom_metrics.get(name) {
+                    Some(value) => write!(file, ",{}", value)?,
+                    None => write!(file, ",NA")?,
+                }
+            }
+            writeln!(file)?;
         }
         Ok(())
     }
 
-    pub fn print_comparison(&self) {
-        if self.runs.is_empty() {
-            println!("No runs to compare");
-            return;
+    /// Write the throughput matrix from a count sweep: an `approach` column, then one column
+    /// per image count. Counts an approach did not run at are `NA`. Fails unless some
+    /// approach ran at more than one count.
+    pub fn save_scaling_csv(&self, path: &Path) -> Result<()> {
+        let Some((counts, rows)) = self.scaling_matrix() else {
+            anyhow::bail!("no approach was run at more than one image count");
+        };
+        let mut file = File::create(path)?;
+        write!(file, "approach")?;
+        for count in &counts {
+            write!(file, ",{}", count)?;
         }
+        writeln!(file)?;
 
-        println!("\nFlux Image Processor - Comparison\n");
-        println!("{}\n", Table::new(&self.runs).with(Style::rounded()));
+        for (approach, cells) in rows {
+            write!(file, "{}", approach)?;
+            for cell in cells {
+                match cell {
+                    Some(throughput) => write!(file, ",{}", throughput)?,
+                    None => write!(file, ",NA")?,
+                }
+            }
+            writeln!(file)?;
+        }
+        Ok(())
+    }
 
-        let naive = self.runs.iter().find(|run| run.approach == "naive");
-        let batched = self.runs.iter().find(|run| run.approach == "batched");
-        let streaming = self.runs.iter().find(|run| run.approach == "streaming");
+    /// Read back runs written by [`MetricsCollector::save_csv`]. Columns are matched by
+    /// name, so files from before a column was added still load with it zeroed; columns past
+    /// the standard ones are custom metrics. Throughput is recomputed from the image count
+    /// and total time rather than read from the file.
+    pub fn load_csv(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)?;
+        let mut lines = contents.lines();
+        let header: Vec<&str> = lines
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("{} is empty", path.display()))?
+            .split(',')
+            .collect();
 
-        if let (Some(naive), Some(batched)) = (naive, batched) {
-            let speedup = naive.total_time_ms as f64 / batched.total_time_ms as f64;
-            println!("Batched is {:.2}x faster than naive.", speedup);
+        let known: Vec<&str> = CSV_HEADER.split(',').collect();
+        let mut seen = HashSet::new();
+        if let Some(duplicate) = header.iter().find(|name| !seen.insert(**name)) {
+            anyhow::bail!("duplicate column {}", duplicate);
+        }
+        for required in &known[..6] {
+            anyhow::ensure!(header.contains(required), "missing column {}", required);
         }
 
-        if let (Some(naive), Some(streaming)) = (naive, streaming) {
-            let speedup = naive.total_time_ms as f64 / streaming.total_time_ms as f64;
-            println!("Streaming is {:.2}x faster than naive.", speedup);
+        let mut runs = vec![];
+        for (line_no, line) in lines
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+        {
+            let fields: Vec<&str> = line.split(',').collect();
+            anyhow::ensure!(
+                fields.len() == header.len(),
+                "line {}: expected {} fields, found {}",
+                line_no + 2,
+                header.len(),
+                fields.len()
+            );
+            let row: HashMap<&str, &str> = header.iter().copied().zip(fields).collect();
+            let int = |name: &str| -> Result<u64> {
+                row.get(name).map_or(Ok(0), |value| {
+                    value.parse().map_err(|_| {
+                        anyhow::anyhow!("line {}: invalid {} {:?}", line_no + 2, name, value)
+                    })
+                })
+            };
+            let float = |name: &str| -> Result<f64> {
+                row.get(name).map_or(Ok(0.0), |value| {
+                    value.parse().map_err(|_| {
+                        anyhow::anyhow!("line {}: invalid {} {:?}", line_no + 2, name, value)
+                    })
+                })
+            };
+
+            let mut run = ProcessingRun::new(
+                row["approach"],
+                int("image_count")? as usize,
+                int("total_time_ms")?,
+                int("peak_memory_mb")?,
+                int("avg_download_ms")?,
+                int("avg_resize_ms")?,
+            )
+            .with_percentiles(
+                [
+                    int("p50_download_ms")?,
+                    int("p95_download_ms")?,
+                    int("p99_download_ms")?,
+                ],
+                [
+                    int("p50_resize_ms")?,
+                    int("p95_resize_ms")?,
+                    int("p99_resize_ms")?,
+                ],
+            )
+            .with_stddev(float("stddev_download_ms")?, float("stddev_resize_ms")?)
+            .with_peak_cpu(float("peak_cpu_percent")? as f32);
+            run.stddev_total_time_ms = float("stddev_total_time_ms")?;
+            for name in header.iter().filter(|name| !known.contains(name)) {
+                if row[name] != "NA" {
+                    run.custom_metrics.insert(name.to_string(), float(name)?);
+                }
+            }
+            runs.push(run);
         }
+        Ok(MetricsCollector { runs })
+    }
 
-        if let (Some(batched), Some(streaming)) = (batched, streaming) {
-            let speedup = batched.total_time_ms as f64 / streaming.total_time_ms as f64;
-            println!("Streaming is {:.2}x faster than batched.\n", speedup);
+    /// Align runs with `baseline` by approach, using the latest run of each, and report how
+    /// this collector's figures changed
+    pub fn compare_with(&self, baseline: &MetricsCollector) -> ComparisonReport {
+        let latest = |collector: &MetricsCollector, approach: &str| {
+            collector
+                .runs
+                .iter()
+                .rev()
+                .find(|run| run.approach == approach)
+                .cloned()
+        };
+        let mut approaches: Vec<&str> = vec![];
+        for run in self.runs.iter().chain(&baseline.runs) {
+            if !approaches.contains(&run.approach.as_str()) {
+                approaches.push(&run.approach);
+            }
         }
 
-        if let (Some(naive), Some(batched)) = (naive, batched) {
-            let ratio = batched.throughput / naive.throughput;
-            println!("Batched throughput is {:.2}x higher than naive.", ratio);
+        let mut report = ComparisonReport::default();
+        for approach in approaches {
+            let (Some(current), Some(base)) = (latest(self, approach), latest(baseline, approach))
+            else {
+                report.unmatched.push(approach.to_string());
+                continue;
+            };
+            let delta =
+                |value: fn(&ProcessingRun) -> u64| value(&current) as i64 - value(&base) as i64;
+            report.deltas.push(RunDelta {
+                approach: approach.to_string(),
+                total_time_ms: delta(|run| run.total_time_ms),
+                peak_memory_mb: delta(|run| run.peak_memory_mb),
+                avg_download_ms: delta(|run| run.avg_download_ms),
+                avg_resize_ms: delta(|run| run.avg_resize_ms),
+                throughput: current.throughput - base.throughput,
+                throughput_pct: if base.throughput > 0.0 {
+                    (current.throughput - base.throughput) / base.throughput * 100.0
+                } else {
+                    0.0
+                },
+            });
         }
+        report
+    }
 
-        if let (Some(naive), Some(streaming)) = (naive, streaming) {
-            let ratio = streaming.throughput / naive.throughput;
-            println!("Streaming throughput is {:.2}x higher than naive.", ratio);
+    /// Render every run in Prometheus text format, one gauge per numeric field
+    /// labelled by approach, e.g. `flux_throughput_images_per_second{approach="streaming"} 42.13`
+    pub fn export_prometheus_text(&self, prefix: &str) -> String {
+        let gauges: [Gauge; 16] = [
+            ("images", "Images processed", |run| run.image_count as f64),
+            ("total_time_ms", "Total run time in milliseconds", |run| {
+                run.total_time_ms as f64
+            }),
+            (
+                "peak_memory_mb",
+                "Peak process memory in megabytes",
+                |run| run.peak_memory_mb as f64,
+            ),
+            (
+                "peak_cpu_percent",
+                "Peak CPU usage as a percentage of one core",
+                |run| run.peak_cpu_percent as f64,
+            ),
+            (
+                "avg_download_ms",
+                "Average download time in milliseconds",
+                |run| run.avg_download_ms as f64,
+            ),
+            (
+                "avg_resize_ms",
+                "Average resize time in milliseconds",
+                |run| run.avg_resize_ms as f64,
+            ),
+            (
+                "p50_download_ms",
+                "Median download time in milliseconds",
+                |run| run.p50_download_ms as f64,
+            ),
+            (
+                "p95_download_ms",
+                "95th percentile download time in milliseconds",
+                |run| run.p95_download_ms as f64,
+            ),
+            (
+                "p99_download_ms",
+                "99th percentile download time in milliseconds",
+                |run| run.p99_download_ms as f64,
+            ),
+            (
+                "p50_resize_ms",
+                "Median resize time in milliseconds",
+                |run| run.p50_resize_ms as f64,
+            ),
+            (
+                "p95_resize_ms",
+                "95th percentile resize time in milliseconds",
+                |run| run.p95_resize_ms as f64,
+            ),
+            (
+                "p99_resize_ms",
+                "99th percentile resize time in milliseconds",
+                |run| run.p99_resize_ms as f64,
+            ),
+            (
+                "stddev_download_ms",
+                "Standard deviation of download time in milliseconds",
+                |run| run.stddev_download_ms,
+            ),
+            (
+                "stddev_resize_ms",
+                "Standard deviation of resize time in milliseconds",
+                |run| run.stddev_resize_ms,
+            ),
+            (
+                "stddev_total_time_ms",
+                "Standard deviation of total run time across repeats in milliseconds",
+                |run| run.stddev_total_time_ms,
+            ),
+            (
+                "throughput_images_per_second",
+                "Images processed per second",
+                |run| run.throughput,
+            ),
+        ];
+
+        let mut out = String::new();
+        for (name, help, value) in gauges {
+            let metric = format!("{}_{}", prefix, name);
+            out.push_str(&format!("# HELP {} {}\n", metric, help));
+            out.push_str(&format!("# TYPE {} gauge\n", metric));
+            for run in &self.runs {
+                let approach = run.approach.replace('\\', "\\\\").replace('"', "\\\"");
+                out.push_str(&format!(
+                    "{}{{approach=\"{}\"}} {}\n",
+                    metric,
+                    approach,
+                    value(run)
+                ));
+            }
         }
+        out
+    }
 
-        if let (Some(batched), Some(streaming)) = (batched, streaming) {
-            let ratio = streaming.throughput / batched.throughput;
-            println!(
-                "Streaming throughput is {:.2}x higher than batched.\n",
-                ratio
-            );
+    /// Mean total time of `approach` and its standard error. With several runs the error
+    /// comes from their spread; a lone run's `stddev_total_time_ms` is taken as its error.
+    fn time_estimate(&self, approach: &str) -> Option<(f64, f64)> {
+        let times: Vec<u64> = self
+            .runs
+            .iter()
+            .filter(|run| run.approach == approach)
+            .map(|run| run.total_time_ms)
+            .collect();
+        match times.len() {
+            0 => None,
+            1 => {
+                let run = self.runs.iter().find(|run| run.approach == approach)?;
+                Some((run.total_time_ms as f64, run.stddev_total_time_ms))
+            }
+            n => {
+                let mean = times.iter().sum::<u64>() as f64 / n as f64;
+                // Population stddev over sqrt(n - 1) is the sample stddev over sqrt(n)
+                Some((mean, stddev(&times) / ((n - 1) as f64).sqrt()))
+            }
+        }
+    }
+
+    /// How many times faster `a` is than `b`, by mean total time, and the standard error of
+    /// that ratio by the delta method
+    fn speedup_estimate(&self, a: &str, b: &str) -> Result<(f64, f64)> {
+        let estimate = |approach: &str| {
+            self.time_estimate(approach)
+                .ok_or_else(|| anyhow::anyhow!("no run for approach {}", approach))
+        };
+        let ((time_a, error_a), (time_b, error_b)) = (estimate(a)?, estimate(b)?);
+        let speedup = time_b / time_a;
+        let relative_error = ((error_a / time_a).powi(2) + (error_b / time_b).powi(2)).sqrt();
+        Ok((speedup, speedup * relative_error))
+    }
+
+    /// Confidence interval, at `confidence` (e.g. 0.95), for how many times faster `a` is
+    /// than `b`. Uses mean total times and propagates their standard errors into the ratio
+    /// by the delta method, so it is only realistic with repeated runs or a recorded
+    /// `stddev_total_time_ms`; otherwise the interval collapses to the point estimate.
+    pub fn speedup_ci(&self, a: &str, b: &str, confidence: f64) -> Result<(f64, f64)> {
+        anyhow::ensure!(
+            confidence > 0.0 && confidence < 1.0,
+            "confidence must be between 0 and 1, got {}",
+            confidence
+        );
+        let (speedup, error) = self.speedup_estimate(a, b)?;
+        let margin = normal_quantile(0.5 + confidence / 2.0) * error;
+        Ok(((speedup - margin).max(0.0), speedup + margin))
+    }
+
+    /// e.g. `Streaming is 2.30x faster than naive (95% CI: 1.90x–2.70x).`, leaving out the
+    /// interval when there is no spread to build it from
+    fn speedup_line(&self, a: &str, b: &str) -> Option<String> {
+        let (speedup, error) = self.speedup_estimate(a, b).ok()?;
+        let mut name = a.to_string();
+        if let Some(first) = name.get_mut(..1) {
+            first.make_ascii_uppercase();
+        }
+        if error == 0.0 {
+            return Some(format!("{} is {:.2}x faster than {}.", name, speedup, b));
+        }
+        let (low, high) = self.speedup_ci(a, b, 0.95).ok()?;
+        Some(format!(
+            "{} is {:.2}x faster than {} (95% CI: {:.2}x–{:.2}x).",
+            name, speedup, b, low, high
+        ))
+    }
+
+    /// Print each run's per-image download times as a bar chart over `buckets` equal-width
+    /// ranges shared by every run, so the spread of each approach can be compared and not
+    /// just its average. Bars are scaled to the terminal width.
+    pub fn print_latency_histogram(&self, buckets: usize) {
+        let width = crossterm::terminal::size()
+            .map(|(columns, _)| columns as usize)
+            .unwrap_or(80);
+        print!("{}", self.latency_histogram(buckets, width));
+    }
+
+    fn latency_histogram(&self, buckets: usize, width: usize) -> String {
+        let samples = self.runs.iter().flat_map(|run| &run.download_samples);
+        let (Some(&min), Some(&max)) = (samples.clone().min(), samples.clone().max()) else {
+            return "No download samples to chart\n".to_string();
+        };
+        let buckets = buckets.max(1) as u64;
+        let bucket_ms = (max - min + 1).div_ceil(buckets);
+        let counts: Vec<(&str, Vec<usize>)> = self
+            .runs
+            .iter()
+            .filter(|run| !run.download_samples.is_empty())
+            .map(|run| {
+                let mut counts = vec![0; buckets as usize];
+                for sample in &run.download_samples {
+                    counts[((sample - min) / bucket_ms) as usize] += 1;
+                }
+                (run.approach.as_str(), counts)
+            })
+            .collect();
+        let max_count = counts
+            .iter()
+            .flat_map(|(_, counts)| counts.iter().copied())
+            .max()
+            .unwrap_or(1);
+
+        let digits = (min + buckets * bucket_ms - 1).to_string().len();
+        let count_digits = max_count.to_string().len();
+        // "{from}-{to} ms | " before the bar and " {count}" after it
+        let bar_width = width
+            .saturating_sub(2 * digits + 7 + count_digits + 1)
+            .max(10);
+        let mut out = format!("\nDownload latency, {} samples\n", samples.count());
+        for (approach, counts) in counts {
+            out.push_str(&format!("{}\n", approach));
+            for (bucket, count) in counts.into_iter().enumerate() {
+                let from = min + bucket as u64 * bucket_ms;
+                let bar = (count * bar_width).div_ceil(max_count);
+                out.push_str(&format!(
+                    "{:>digits$}-{:>digits$} ms | {} {}\n",
+                    from,
+                    from + bucket_ms - 1,
+                    "#".repeat(bar),
+                    count
+                ));
+            }
+        }
+        out
+    }
+
+    pub fn print_comparison(&self) {
+        if self.runs.is_empty() {
+            println!("No runs to compare");
+            return;
+        }
+
+        println!("\nFlux Image Processor - Comparison\n");
+        println!("{}\n", self.comparison_table().with(Style::rounded()));
+        if let Some(mut table) = self.scaling_table() {
+            println!("Throughput by image count (img/s)\n");
+            println!("{}\n", table.with(Style::rounded()));
         }
 
+        let naive = self.runs.iter().find(|run| run.approach == "naive");
+        let batched = self.runs.iter().find(|run| run.approach == "batched");
+        let streaming = self.runs.iter().find(|run| run.approach == "streaming");
+
+        for (a, b) in [
+            ("batched", "naive"),
+            ("streaming", "naive"),
+            ("streaming", "batched"),
+        ] {
+            if let Some(line) = self.speedup_line(a, b) {
+                println!("{}", line);
+            }
+        }
+        println!();
+
         if let (Some(naive), Some(batched)) = (naive, batched) {
             let ratio = batched.peak_memory_mb as f64 / naive.peak_memory_mb as f64;
             println!("Batched peak memory is {:.2}x higher than naive.", ratio);
@@ -145,6 +1264,21 @@ impl MetricsCollector {
                 ratio
             );
         }
+
+        let summary = self.summary_statistics();
+        println!(
+            "Throughput best/median/worst: {:.2} / {:.2} / {:.2} img/s",
+            summary.best_throughput, summary.median_throughput, summary.worst_throughput
+        );
+        println!(
+            "Processed {} images in {} ms across all runs, peak memory {} MB.\n",
+            summary.total_images_processed,
+            summary.total_time_across_runs_ms,
+            summary.combined_peak_memory_mb
+        );
+        if let Some(line) = self.winner_line() {
+            println!("{}\n", line);
+        }
     }
 }
 
@@ -170,6 +1304,363 @@ mod tests {
         fs::remove_file(path).unwrap();
     }
 
+    #[test]
+    fn loads_csv() {
+        let mut collector = MetricsCollector::new();
+        collector.add_run(ProcessingRun {
+            stddev_total_time_ms: 420.5,
+            ..ProcessingRun::new("naive", 100, 15000, 450, 230, 290)
+                .with_percentiles([200, 400, 600], [250, 300, 350])
+                .with_stddev(12.5, 3.25)
+                .with_peak_cpu(85.5)
+        });
+        collector.add_run(ProcessingRun::new("streaming", 100, 4000, 120, 210, 280));
+        collector
+            .add_custom_metric("streaming", "first_save_ms".to_string(), 350.0)
+            .unwrap();
+
+        let path = Path::new("test_metrics_load.csv");
+        collector.save_csv(path).unwrap();
+        let loaded = MetricsCollector::load_csv(path).unwrap();
+
+        assert_eq!(loaded.runs.len(), 2);
+        assert_eq!(loaded.runs[0].csv_row(), collector.runs[0].csv_row());
+        assert_eq!(loaded.runs[0].stddev_total_time_ms, 420.5);
+        assert_eq!(loaded.runs[0].peak_cpu_percent, 85.5);
+        assert!(loaded.runs[0].custom_metrics.is_empty());
+        assert_eq!(
+            loaded.runs[1].custom_metrics.get("first_save_ms"),
+            Some(&350.0)
+        );
+
+        // Throughput comes from the image count and time, not the file
+        fs::write(
+            path,
+            "approach,image_count,total_time_ms,peak_memory_mb,avg_download_ms,avg_resize_ms,throughput\n\
+             batched,100,8000,180,220,285,999\n",
+        )
+        .unwrap();
+        let loaded = MetricsCollector::load_csv(path).unwrap();
+        assert_eq!(loaded.runs[0].throughput, 12.5);
+        assert_eq!(loaded.runs[0].p50_download_ms, 0);
+        assert_eq!(loaded.runs[0].stddev_total_time_ms, 0.0);
+
+        fs::write(path, "approach,image_count\nnaive,100\n").unwrap();
+        assert!(MetricsCollector::load_csv(path).is_err());
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn merges_collectors() {
+        let mut collector = MetricsCollector::new();
+        collector.add_run(ProcessingRun::new("naive", 100, 15000, 450, 230, 290));
+        let mut other = MetricsCollector::new();
+        other.add_run(ProcessingRun::new("naive", 50, 8000, 300, 210, 280));
+        other.add_run(ProcessingRun::new("streaming", 100, 4000, 120, 210, 280));
+
+        collector.merge(other);
+        let approaches: Vec<_> = collector
+            .runs
+            .iter()
+            .map(|run| run.approach.as_str())
+            .collect();
+        assert_eq!(approaches, vec!["naive", "naive", "streaming"]);
+    }
+
+    #[test]
+    fn merges_runs_by_approach() {
+        let mut collector = MetricsCollector::new();
+        collector.add_run(ProcessingRun::new("streaming", 300, 4000, 120, 200, 100));
+        collector
+            .add_custom_metric("streaming", "First save (ms)".to_string(), 300.0)
+            .unwrap();
+        let mut run = ProcessingRun::new("streaming", 100, 8000, 150, 400, 300);
+        run.custom_metrics
+            .insert("First save (ms)".to_string(), 700.0);
+
+        collector.merge_run_by_approach(run);
+        collector.merge_run_by_approach(ProcessingRun::new("batched", 10, 1000, 80, 50, 50));
+
+        assert_eq!(collector.runs.len(), 2);
+        let merged = &collector.runs[0];
+        assert_eq!(merged.image_count, 400);
+        assert_eq!(merged.total_time_ms, 5000);
+        assert_eq!(merged.peak_memory_mb, 150);
+        assert_eq!(merged.avg_download_ms, 250);
+        assert_eq!(merged.avg_resize_ms, 150);
+        assert_eq!(merged.throughput, 80.0);
+        assert_eq!(merged.custom_metrics["First save (ms)"], 400.0);
+        assert_eq!(collector.runs[1].approach, "batched");
+    }
+
+    #[test]
+    fn compares_with_baseline() {
+        let mut baseline = MetricsCollector::new();
+        baseline.add_run(ProcessingRun::new("naive", 100, 10000, 400, 230, 290));
+        baseline.add_run(ProcessingRun::new("batched", 100, 8000, 180, 220, 285));
+
+        let mut current = MetricsCollector::new();
+        current.add_run(ProcessingRun::new("naive", 100, 20000, 450, 250, 290));
+        current.add_run(ProcessingRun::new("streaming", 100, 4000, 120, 210, 280));
+
+        let report = current.compare_with(&baseline);
+        assert_eq!(report.deltas.len(), 1);
+        let naive = &report.deltas[0];
+        assert_eq!(naive.approach, "naive");
+        assert_eq!(naive.total_time_ms, 10000);
+        assert_eq!(naive.peak_memory_mb, 50);
+        assert_eq!(naive.throughput, -5.0);
+        assert_eq!(naive.throughput_pct, -50.0);
+        assert_eq!(report.unmatched, vec!["streaming", "batched"]);
+    }
+
+    #[test]
+    fn round_trips_json() {
+        let mut collector = MetricsCollector::new();
+        collector.add_run(ProcessingRun::new("naive", 100, 15000, 450, 230, 290));
+        collector.add_run(ProcessingRun::new("streaming", 100, 4000, 120, 210, 280));
+        collector
+            .add_custom_metric("streaming", "First save (ms)".to_string(), 350.0)
+            .unwrap();
+
+        for (path, compact) in [
+            ("test_metrics.json", false),
+            ("test_metrics_compact.json", true),
+        ] {
+            let path = Path::new(path);
+            collector.save_json(path, compact).unwrap();
+            assert_eq!(fs::read_to_string(path).unwrap().contains('\n'), !compact);
+
+            let loaded = MetricsCollector::load_json(path).unwrap();
+            assert_eq!(loaded.runs.len(), 2);
+            assert_eq!(loaded.runs[0].approach, "naive");
+            assert_eq!(loaded.runs[0].total_time_ms, 15000);
+            assert_eq!(
+                loaded.runs[1].custom_metrics.get("First save (ms)"),
+                Some(&350.0)
+            );
+
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn recomputes_throughput_when_deserialized() {
+        let run = ProcessingRun::new("batched", 100, 5000, 200, 220, 260).with_stddev(1.5, 2.5);
+        let mut value = serde_json::to_value(&run).unwrap();
+        value["throughput"] = serde_json::json!(0.0);
+
+        let loaded: ProcessingRun = serde_json::from_value(value).unwrap();
+        assert_eq!(loaded.throughput, run.throughput);
+        assert_eq!(loaded.stddev_resize_ms, 2.5);
+    }
+
+    #[test]
+    fn computes_percentiles() {
+        let mut samples: Vec<u64> = (1..=100).rev().collect();
+        assert_eq!(percentiles(&mut samples), [50, 95, 99]);
+        assert_eq!(percentiles(&mut [7]), [7, 7, 7]);
+        assert_eq!(percentiles(&mut []), [0, 0, 0]);
+        assert_eq!(stddev(&[2, 4, 4, 4, 5, 5, 7, 9]), 2.0);
+        assert_eq!(stddev(&[]), 0.0);
+
+        let run = ProcessingRun::new("naive", 100, 15000, 450, 230, 290)
+            .with_percentiles([200, 400, 600], [250, 300, 350])
+            .with_stddev(12.5, 3.25);
+        assert_eq!(run.p99_download_ms, 600);
+        assert_eq!(
+            run.csv_row(),
+            "naive,100,15000,450,230,290,200,400,600,250,300,350,12.50,3.25,0.00,0.0,6.67"
+        );
+    }
+
+    #[test]
+    fn appends_runs_to_csv() {
+        let path = Path::new("test_metrics_append.csv");
+        let _ = fs::remove_file(path);
+
+        for i in 0..5 {
+            let run = ProcessingRun::new("streaming", 100, 1000 + i, 120, 200, 280);
+            MetricsCollector::append_run_to_csv(path, &run).unwrap();
+        }
+
+        let contents = fs::read_to_string(path).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines.len(), 6);
+        assert_eq!(lines[0], CSV_HEADER);
+        assert!(lines[1..].iter().all(|line| line.starts_with("streaming,")));
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn smooths_with_rolling_average() {
+        let mut collector = MetricsCollector::new();
+        for i in 0..5 {
+            collector.add_run(ProcessingRun::new(
+                "streaming",
+                100,
+                1000 * (i + 1),
+                100 + i,
+                200,
+                280,
+            ));
+        }
+
+        let unchanged = collector.rolling_average(1);
+        assert_eq!(unchanged.len(), 5);
+        assert_eq!(unchanged[2].approach, "streaming");
+        assert_eq!(unchanged[2].total_time_ms, 3000);
+
+        let smoothed = collector.rolling_average(3);
+        assert_eq!(smoothed.len(), 3);
+        assert_eq!(smoothed[0].approach, "streaming[0-2]");
+        assert_eq!(smoothed[1].total_time_ms, 3000);
+        assert_eq!(smoothed[1].peak_memory_mb, 102);
+        let expected = (100.0 / 2.0 + 100.0 / 3.0 + 100.0 / 4.0) / 3.0;
+        assert!((smoothed[1].throughput - expected).abs() < 1e-9);
+    }
+
+    #[test]
+    fn saves_custom_metrics() {
+        let mut collector = MetricsCollector::new();
+
+        collector.add_run(ProcessingRun::new("naive", 100, 15000, 450, 230, 290));
+        collector.add_run(ProcessingRun::new("batched", 100, 8000, 180, 220, 285));
+        collector
+            .add_custom_metric("batched", "retries".to_string(), 3.0)
+            .unwrap();
+        assert!(collector
+            .add_custom_metric("streaming", "retries".to_string(), 1.0)
+            .is_err());
+
+        let path = Path::new("test_metrics_custom.csv");
+        collector.save_csv(path).unwrap();
+
+        let contents = fs::read_to_string(path).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert!(lines[0].ends_with(",retries"));
+        assert!(lines[1].ends_with(",NA"));
+        assert!(lines[2].ends_with(",3"));
+
+        collector.print_comparison();
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn splits_server_and_client_timing() {
+        let mut collector = MetricsCollector::new();
+        collector.add_run(ProcessingRun::new("naive", 2, 1000, 100, 100, 20));
+
+        let metric = |download_ms, timing: Option<Vec<(&str, f64)>>| {
+            ImageMetrics::builder()
+                .with_url("https://example.com/1.jpg")
+                .with_download(download_ms, 1024)
+                .with_server_timing(timing.map(|timing| {
+                    timing
+                        .into_iter()
+                        .map(|(name, dur)| (name.to_string(), dur))
+                        .collect()
+                }))
+                .build()
+                .unwrap()
+        };
+        let mut split = ServerTimingSplit::default();
+        collector.add_server_timing("naive", &split).unwrap();
+        assert!(collector.runs[0].custom_metrics.is_empty());
+
+        split.record(&metric(100, Some(vec![("cdn", 10.0), ("origin", 30.0)])));
+        split.record(&metric(80, Some(vec![("cdn", 20.0)])));
+        split.record(&metric(500, None));
+        assert_eq!(split.images, 2);
+        collector.add_server_timing("naive", &split).unwrap();
+
+        let run = &collector.runs[0];
+        assert_eq!(run.custom_metrics["server_ms"], 30.0);
+        assert_eq!(run.custom_metrics["client_ms"], 60.0);
+    }
+
+    #[test]
+    fn computes_speedup_ci() {
+        assert!((normal_quantile(0.975) - 1.959964).abs() < 1e-6);
+        assert!((normal_quantile(0.005) + 2.575829).abs() < 1e-6);
+
+        let mut collector = MetricsCollector::new();
+        for (naive_ms, streaming_ms) in [(10000, 4000), (11000, 5000), (12000, 6000)] {
+            collector.add_run(ProcessingRun::new("naive", 100, naive_ms, 450, 230, 290));
+            collector.add_run(ProcessingRun::new(
+                "streaming",
+                100,
+                streaming_ms,
+                120,
+                215,
+                280,
+            ));
+        }
+        collector.print_comparison();
+
+        let (low, high) = collector.speedup_ci("streaming", "naive", 0.95).unwrap();
+        assert!((low - 1.653).abs() < 1e-3);
+        assert!((high - 2.747).abs() < 1e-3);
+        let (narrow_low, narrow_high) = collector.speedup_ci("streaming", "naive", 0.5).unwrap();
+        assert!(low < narrow_low && narrow_high < high);
+
+        // A single run without a recorded spread has no uncertainty
+        let mut single = MetricsCollector::new();
+        single.add_run(ProcessingRun::new("naive", 100, 9000, 450, 230, 290));
+        single.add_run(ProcessingRun::new("batched", 100, 6000, 180, 220, 285));
+        assert_eq!(
+            single.speedup_ci("batched", "naive", 0.95).unwrap(),
+            (1.5, 1.5)
+        );
+        assert_eq!(
+            single.speedup_line("batched", "naive").unwrap(),
+            "Batched is 1.50x faster than naive."
+        );
+        assert!(collector
+            .speedup_line("streaming", "naive")
+            .unwrap()
+            .ends_with("(95% CI: 1.65x–2.75x)."));
+
+        assert!(single.speedup_ci("streaming", "naive", 0.95).is_err());
+        assert!(single.speedup_ci("batched", "naive", 1.0).is_err());
+    }
+
+    #[test]
+    fn charts_download_latencies() {
+        let mut collector = MetricsCollector::new();
+        assert_eq!(
+            collector.latency_histogram(4, 80),
+            "No download samples to chart\n"
+        );
+
+        collector.add_run(
+            ProcessingRun::new("naive", 4, 1000, 450, 250, 290)
+                .with_download_samples(vec![100, 150, 250, 400]),
+        );
+        collector.add_run(
+            ProcessingRun::new("streaming", 4, 400, 120, 110, 280)
+                .with_download_samples(vec![100, 110, 120, 130]),
+        );
+        collector.add_run(ProcessingRun::new("batched", 4, 600, 180, 220, 285));
+        collector.print_latency_histogram(4);
+
+        let chart = collector.latency_histogram(4, 60);
+        let lines: Vec<&str> = chart.lines().collect();
+        // Blank line and title, then a name and 4 buckets for each run with samples
+        assert_eq!(lines.len(), 2 + 2 * 5);
+        assert_eq!(lines[1], "Download latency, 8 samples");
+        assert_eq!(lines[2], "naive");
+        assert!(lines[3].starts_with("100-175 ms | #"));
+        assert!(lines[6].starts_with("328-403 ms | #"));
+        assert_eq!(lines[7], "streaming");
+        // Streaming's 4 samples all land in the first bucket, the largest bar
+        assert!(lines[8].ends_with(" 4"));
+        assert_eq!(lines[8].len(), 60);
+        assert!(lines[9].ends_with("|  0"));
+    }
+
     #[test]
     fn prints_comparison() {
         let mut collector = MetricsCollector::new();
@@ -180,4 +1671,131 @@ mod tests {
 
         collector.print_comparison();
     }
+
+    #[test]
+    fn exports_prometheus_text() {
+        let mut collector = MetricsCollector::new();
+
+        collector.add_run(ProcessingRun::new("naive", 100, 15000, 450, 230, 290));
+        collector.add_run(ProcessingRun::new("streaming", 100, 5000, 120, 215, 280));
+
+        let text = collector.export_prometheus_text("flux");
+        let scrape =
+            prometheus_parse::Scrape::parse(text.lines().map(|line| Ok(line.to_string()))).unwrap();
+
+        // One gauge for every numeric field, and nothing else
+        let expected = [
+            "flux_images",
+            "flux_total_time_ms",
+            "flux_peak_memory_mb",
+            "flux_peak_cpu_percent",
+            "flux_avg_download_ms",
+            "flux_avg_resize_ms",
+            "flux_p50_download_ms",
+            "flux_p95_download_ms",
+            "flux_p99_download_ms",
+            "flux_p50_resize_ms",
+            "flux_p95_resize_ms",
+            "flux_p99_resize_ms",
+            "flux_stddev_download_ms",
+            "flux_stddev_resize_ms",
+            "flux_stddev_total_time_ms",
+            "flux_throughput_images_per_second",
+        ];
+        let exported: BTreeSet<&str> = scrape
+            .samples
+            .iter()
+            .map(|sample| sample.metric.as_str())
+            .collect();
+        assert_eq!(exported, BTreeSet::from(expected));
+        for name in expected {
+            let samples: Vec<_> = scrape
+                .samples
+                .iter()
+                .filter(|sample| sample.metric == name)
+                .collect();
+            assert_eq!(samples.len(), 2, "{}", name);
+        }
+
+        let streaming = scrape
+            .samples
+            .iter()
+            .find(|sample| {
+                sample.metric == "flux_throughput_images_per_second"
+                    && sample.labels.get("approach") == Some("streaming")
+            })
+            .unwrap();
+        assert_eq!(streaming.value, prometheus_parse::Value::Gauge(20.0));
+    }
+
+    #[test]
+    fn summarizes_runs() {
+        let mut collector = MetricsCollector::new();
+
+        collector.add_run(ProcessingRun::new("naive", 100, 15000, 450, 230, 290));
+        collector.add_run(ProcessingRun::new("batched", 100, 8000, 180, 220, 285));
+        collector.add_run(ProcessingRun::new("streaming", 100, 5000, 120, 215, 280));
+        collector.add_run(ProcessingRun::new("naive", 50, 7000, 400, 230, 290));
+        collector.add_run(ProcessingRun::new("streaming", 50, 2000, 110, 215, 280));
+
+        let summary = collector.summary_statistics();
+        assert!(summary.best_throughput >= summary.median_throughput);
+        assert!(summary.median_throughput >= summary.worst_throughput);
+        assert_eq!(summary.total_images_processed, 400);
+        assert_eq!(summary.total_time_across_runs_ms, 37000);
+        assert_eq!(summary.combined_peak_memory_mb, 450);
+    }
+
+    #[test]
+    fn picks_best_approach() {
+        let mut collector = MetricsCollector::new();
+        assert!(collector.best_throughput().is_none());
+        assert!(collector.winner_line().is_none());
+
+        collector.add_run(ProcessingRun::new("naive", 100, 15000, 450, 230, 290));
+        collector.add_run(ProcessingRun::new("batched", 100, 8000, 120, 220, 285));
+        collector.add_run(ProcessingRun::new("streaming", 100, 5000, 180, 215, 280));
+
+        assert_eq!(collector.best_throughput().unwrap().approach, "streaming");
+        assert_eq!(collector.best_memory().unwrap().approach, "batched");
+        assert_eq!(collector.best_latency().unwrap().approach, "streaming");
+        let ranked: Vec<_> = collector
+            .rank_by_throughput()
+            .iter()
+            .map(|run| run.approach.as_str())
+            .collect();
+        assert_eq!(ranked, ["streaming", "batched", "naive"]);
+        assert_eq!(
+            collector.winner_line().unwrap(),
+            "Winner: streaming (3.0x throughput)"
+        );
+
+        let (naive, streaming) = (&collector.runs[0], &collector.runs[2]);
+        assert!(streaming > naive);
+        let same_throughput = ProcessingRun::new("other", 100, 5000, 90, 215, 280);
+        assert_eq!(streaming.partial_cmp(&same_throughput), None);
+        assert_eq!(streaming.partial_cmp(streaming), Some(Ordering::Equal));
+    }
+
+    #[test]
+    fn pivots_throughput_by_count() {
+        let mut collector = MetricsCollector::new();
+        collector.add_run(ProcessingRun::new("naive", 10, 1000, 100, 50, 20));
+        collector.add_run(ProcessingRun::new("batched", 10, 500, 100, 50, 20));
+        assert!(collector.scaling_table().is_none());
+
+        collector.add_run(ProcessingRun::new("naive", 50, 5000, 100, 50, 20));
+        collector.add_run(ProcessingRun::new("streaming", 50, 1000, 100, 50, 20));
+        let table = collector.scaling_table().unwrap().to_string();
+        assert!(table.contains("50 images"));
+
+        let path = Path::new("test_scaling.csv");
+        collector.save_scaling_csv(path).unwrap();
+        let contents = fs::read_to_string(path).unwrap();
+        fs::remove_file(path).unwrap();
+        assert_eq!(
+            contents,
+            "approach,10,50\nnaive,10,10\nbatched,20,NA\nstreaming,NA,50\n"
+        );
+    }
 }
@@ -1,82 +1,909 @@
 use crate::{
-    image_processor::process_single_image,
-    url_generator::UrlGenerator,
+    checkpoint::{read_checkpoint, CheckpointEntry, CheckpointWriter},
+    config::{ErrorPolicy, ProcessorConfig},
+    image_processor::{
+        download, downloaded_image_metrics, process_and_save_to, process_single_image,
+        skip_existing, Download, ImageMetrics,
+    },
+    memory_monitor::{spawn_peak_tracker, MemoryMonitor},
+    metrics::{min_max_avg, percentiles, stddev, ProcessingRun, ServerTimingSplit},
+    output_sink::SinkWriter,
+    progress::progress_bar,
+    url_generator::{ImageSource, UrlGenerator},
+    validation::validate_count,
+    warmup::warm_up,
 };
 use anyhow::Result;
-use std::{cmp::max, path::Path};
-use tokio::time::Instant;
-use tracing::info;
+use futures::{stream::FuturesUnordered, FutureExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::{cmp::max, future::Future, path::Path, sync::Arc};
+use tokio::{
+    spawn,
+    sync::Semaphore,
+    task::{AbortHandle, JoinHandle},
+    time::Instant,
+};
+use tracing::{info, warn};
 
+#[derive(Default, Serialize, Deserialize)]
 pub struct ProcessingStats {
     pub total_images: usize,
     pub total_time_ms: u64,
     pub peak_memory_mb: u64,
+    /// Highest CPU usage sampled during the run, as a percentage of one core
+    pub peak_cpu_percent: f32,
+    /// Total time the memory monitor spent sampling memory, the cost of measuring the run
+    pub monitor_overhead_us: u64,
     pub avg_download_ms: u64,
     pub avg_resize_ms: u64,
+    pub p50_download_ms: u64,
+    pub p95_download_ms: u64,
+    pub p99_download_ms: u64,
+    pub p50_resize_ms: u64,
+    pub p95_resize_ms: u64,
+    pub p99_resize_ms: u64,
+    pub stddev_download_ms: f64,
+    pub stddev_resize_ms: f64,
+    /// Every image's download time, sorted
+    pub download_samples: Vec<u64>,
+    /// Saved over downloaded size across images, see [`ImageMetrics::compression_ratio`]
+    pub min_compression_ratio: f64,
+    pub max_compression_ratio: f64,
+    pub avg_compression_ratio: f64,
+    /// Server-side share of the download time, from images that sent `Server-Timing`
+    pub server_timing: ServerTimingSplit,
+    /// Only a random subset of images was processed; `total_time_ms` is extrapolated
+    pub sampled: bool,
+    pub sample_rate: f64,
+    /// An existing checkpoint was found and its images were skipped
+    pub resumed_from_checkpoint: bool,
+    /// Images already in the checkpoint, not counted in `total_images`
+    pub skipped_by_checkpoint: usize,
+    /// Images skipped because their output already existed, not counted in `total_images`
+    pub skipped_count: usize,
+    /// (URL, error) for every image that failed under [`ErrorPolicy::CollectAndContinue`].
+    /// These aren't counted in `total_images` or any of the timings.
+    pub errors: Vec<(String, String)>,
 }
 
-pub async fn process_naive(count: usize, output_dir: &Path) -> Result<ProcessingStats> {
-    info!(count, "starting naive processing");
+impl ProcessingStats {
+    /// This run's row in a [`MetricsCollector`](crate::metrics::MetricsCollector) comparison
+    pub fn to_run(&self, approach: &str) -> ProcessingRun {
+        ProcessingRun::new(
+            approach,
+            self.total_images,
+            self.total_time_ms,
+            self.peak_memory_mb,
+            self.avg_download_ms,
+            self.avg_resize_ms,
+        )
+        .with_percentiles(
+            [
+                self.p50_download_ms,
+                self.p95_download_ms,
+                self.p99_download_ms,
+            ],
+            [self.p50_resize_ms, self.p95_resize_ms, self.p99_resize_ms],
+        )
+        .with_stddev(self.stddev_download_ms, self.stddev_resize_ms)
+        .with_peak_cpu(self.peak_cpu_percent)
+        .with_download_samples(self.download_samples.clone())
+    }
+}
 
-    let url_generator = UrlGenerator::new(count);
-    let urls = url_generator.generate();
-    let mut total_download_time: u64 = 0;
-    let mut total_resize_time: u64 = 0;
-    let total_time: u64;
-    let mut peak_memory_usage: u64 = 0;
+pub async fn process_naive(
+    count: usize,
+    output_dir: &Path,
+    config: &ProcessorConfig,
+) -> Result<ProcessingStats> {
+    validate_count(count)?;
+    let urls = UrlGenerator::for_config(count, config).urls();
+    if config.semi_async_naive {
+        return process_naive_pipelined(urls, output_dir, config).await;
+    }
+    process_naive_urls(urls, output_dir, config).await
+}
+
+async fn process_naive_urls(
+    urls: Vec<String>,
+    output_dir: &Path,
+    config: &ProcessorConfig,
+) -> Result<ProcessingStats> {
+    let config = &config.with_shared_client()?;
+    let (urls, skipped_count) = skip_existing(urls, output_dir, config);
+    let count = urls.len();
+    warm_up(&urls, config).await;
+    info!(count, "starting naive processing");
 
+    let (mut checkpoint, urls) = Resume::open(urls, config)?;
+    let progress = progress_bar(urls.len(), config.progress);
     let start_time = Instant::now();
-    for (index, u) in urls.iter().enumerate() {
-        info!(index = index + 1, total = count, url = %u, "processing image");
+    let totals = track_run_cpu(async {
+        let mut totals = NaiveTotals::default();
+        for (index, u) in urls.iter().enumerate() {
+            info!(index = index + 1, total = count, url = %u, "processing image");
 
-        let metric = process_single_image(&u, output_dir).await.unwrap();
-        peak_memory_usage = max(metric.peak_memory_mb, peak_memory_usage);
-        total_download_time += metric.download_ms;
-        total_resize_time += metric.resize_ms;
+            let metric = match process_single_image(u, output_dir, config).await {
+                Ok(metric) => metric,
+                Err(e) => {
+                    totals.collect_error(u, e, config)?;
+                    progress.inc(1);
+                    continue;
+                }
+            };
+            checkpoint.record(&metric, output_dir)?;
+            totals.record_image(&metric);
+            if let Some(metrics) = &config.live_metrics {
+                metrics.record_image("naive", metric.download_ms, metric.resize_ms);
+            }
 
-        info!(
-            download_ms = metric.download_ms,
-            resize_ms = metric.resize_ms,
-            memory_mb = metric.peak_memory_mb,
-            "image processed"
-        );
-    }
+            info!(
+                download_ms = metric.download_ms,
+                resize_ms = metric.resize_ms,
+                memory_mb = metric.peak_memory_mb,
+                "image processed"
+            );
+            progress.inc(1);
+        }
+        Ok(totals)
+    })
+    .await?;
     let end_time = Instant::now();
+    progress.finish();
 
-    total_time = (end_time - start_time).as_millis() as u64;
-
+    let total_time = (end_time - start_time).as_millis() as u64;
+    let stats = totals.into_stats(total_time, skipped_count, config);
     info!(
         total_time_ms = total_time,
-        peak_memory_mb = peak_memory_usage,
-        avg_download_ms = total_download_time / count as u64,
-        avg_resize_ms = total_resize_time / count as u64,
+        peak_memory_mb = stats.peak_memory_mb,
+        avg_download_ms = stats.avg_download_ms,
+        avg_resize_ms = stats.avg_resize_ms,
         "naive processing complete"
     );
+    checkpoint.finish(stats)
+}
+
+/// Naive processing with up to `max_concurrent` images in flight. Every URL is its own task
+/// that downloads, resizes and saves independently, so unlike `process_batched` a slow image
+/// never holds back the ones after it.
+pub async fn process_naive_concurrent(
+    count: usize,
+    max_concurrent: usize,
+    output_dir: &Path,
+    config: &ProcessorConfig,
+) -> Result<ProcessingStats> {
+    validate_count(count)?;
+    let urls = UrlGenerator::for_config(count, config).urls();
+    process_naive_concurrent_urls(urls, max_concurrent, output_dir, config).await
+}
+
+async fn process_naive_concurrent_urls(
+    urls: Vec<String>,
+    max_concurrent: usize,
+    output_dir: &Path,
+    config: &ProcessorConfig,
+) -> Result<ProcessingStats> {
+    let config = &config.with_shared_client()?;
+    let (urls, skipped_count) = skip_existing(urls, output_dir, config);
+    let count = urls.len();
+    warm_up(&urls, config).await;
+    info!(
+        count,
+        max_concurrent, "starting concurrent naive processing"
+    );
+
+    let sem = Arc::new(Semaphore::new(max_concurrent.max(1)));
+    let start_time = Instant::now();
+    let handles: Vec<_> = urls
+        .iter()
+        .cloned()
+        .map(|url| {
+            let sem = Arc::clone(&sem);
+            let output_dir = output_dir.to_path_buf();
+            let config = config.clone();
+            spawn(async move {
+                let _permit = sem.acquire_owned().await.unwrap();
+                let metric = process_single_image(&url, &output_dir, &config).await?;
+                if let Some(metrics) = &config.live_metrics {
+                    metrics.record_image("naive-concurrent", metric.download_ms, metric.resize_ms);
+                }
+                anyhow::Ok(metric)
+            })
+        })
+        .collect();
+    let tasks: Vec<AbortHandle> = handles.iter().map(JoinHandle::abort_handle).collect();
+    // Taken as they finish, so under FailFast the first failure stops the rest at once
+    let mut results: FuturesUnordered<_> = urls
+        .iter()
+        .zip(handles)
+        .map(|(url, handle)| handle.map(move |res| (url, res)))
+        .collect();
 
-    Ok(ProcessingStats {
-        total_images: count,
-        total_time_ms: total_time,
-        peak_memory_mb: peak_memory_usage,
-        avg_download_ms: total_download_time / count as u64,
-        avg_resize_ms: total_resize_time / count as u64,
+    let totals = track_run_cpu(async {
+        let mut totals = NaiveTotals::default();
+        while let Some((url, res)) = results.next().await {
+            match res.map_err(anyhow::Error::from).and_then(|metric| metric) {
+                Ok(metric) => totals.record_image(&metric),
+                Err(e) => {
+                    if let Err(e) = totals.collect_error(url, e, config) {
+                        tasks.iter().for_each(AbortHandle::abort);
+                        return Err(e);
+                    }
+                }
+            }
+        }
+        Ok(totals)
     })
+    .await?;
+    let total_time = start_time.elapsed().as_millis() as u64;
+    let stats = totals.into_stats(total_time, skipped_count, config);
+    info!(
+        total_time_ms = total_time,
+        peak_memory_mb = stats.peak_memory_mb,
+        avg_download_ms = stats.avg_download_ms,
+        avg_resize_ms = stats.avg_resize_ms,
+        "concurrent naive processing complete"
+    );
+    Ok(stats)
+}
+
+/// Naive processing with one download in flight ahead of the image being processed.
+/// Images are still decoded, resized and saved one at a time in URL order.
+async fn process_naive_pipelined(
+    urls: Vec<String>,
+    output_dir: &Path,
+    config: &ProcessorConfig,
+) -> Result<ProcessingStats> {
+    let config = &config.with_shared_client()?;
+    let (urls, skipped_count) = skip_existing(urls, output_dir, config);
+    let count = urls.len();
+    warm_up(&urls, config).await;
+    info!(count, "starting pipelined naive processing");
+    let (mut checkpoint, urls) = Resume::open(urls, config)?;
+
+    let mut memory_monitor = MemoryMonitor::with_baseline();
+    memory_monitor.set_full_refresh(config.full_memory_refresh);
+    let (monitor_handle, peak) = spawn_peak_tracker(100, memory_monitor);
+
+    let start_time = Instant::now();
+
+    let progress = progress_bar(urls.len(), config.progress);
+    // Capacity 1: the downloader blocks once it is a single image ahead, so memory stays flat
+    let (tx, rx) = async_channel::bounded::<(String, Result<Download>)>(1);
+    let download_config = config.clone();
+    let downloader = spawn(async move {
+        for url in urls {
+            let downloaded = download(&url, &download_config).await;
+            if tx.send((url, downloaded)).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let output = SinkWriter::directory(output_dir);
+    let totals = async {
+        let mut totals = NaiveTotals::default();
+        let mut index = 0;
+        while let Ok((url, downloaded)) = rx.recv().await {
+            index += 1;
+            info!(index, total = count, url = %url, "processing image");
+
+            let metric = match downloaded {
+                Ok(downloaded) => process_and_save_to(&url, &downloaded.bytes, &output, config)
+                    .await
+                    .and_then(|saved| downloaded_image_metrics(&url, downloaded, saved).build()),
+                Err(e) => Err(e),
+            };
+            let metric = match metric {
+                Ok(metric) => metric,
+                Err(e) => {
+                    totals.collect_error(&url, e, config)?;
+                    progress.inc(1);
+                    continue;
+                }
+            };
+            checkpoint.record(&metric, output_dir)?;
+            totals.record_image(&metric);
+            if let Some(metrics) = &config.live_metrics {
+                metrics.record_image("naive-pipelined", metric.download_ms, metric.resize_ms);
+            }
+
+            info!(
+                download_ms = metric.download_ms,
+                resize_ms = metric.resize_ms,
+                "image processed"
+            );
+            progress.inc(1);
+        }
+        downloader.await?;
+        anyhow::Ok(totals)
+    }
+    .await;
+
+    monitor_handle.abort();
+    progress.finish();
+    let mut totals = totals?;
+    // Decoding happens on this task rather than per image, so the whole run is tracked
+    totals.peak_memory_mb = peak.memory_mb();
+    totals.peak_cpu_percent = peak.cpu_percent();
+    totals.monitor_overhead_us = peak.monitor_us();
+    let total_time = start_time.elapsed().as_millis() as u64;
+    let stats = totals.into_stats(total_time, skipped_count, config);
+    info!(
+        total_time_ms = total_time,
+        peak_memory_mb = stats.peak_memory_mb,
+        avg_download_ms = stats.avg_download_ms,
+        avg_resize_ms = stats.avg_resize_ms,
+        "pipelined naive processing complete"
+    );
+    checkpoint.finish(stats)
+}
+
+/// `config.checkpoint` for a naive run: images it already records are left out, and every
+/// image saved is added to it
+struct Resume {
+    writer: Option<CheckpointWriter>,
+    skipped_by_checkpoint: usize,
+}
+
+impl Resume {
+    /// Open `config.checkpoint`, if set, returning `urls` without the ones it records
+    fn open(urls: Vec<String>, config: &ProcessorConfig) -> Result<(Self, Vec<String>)> {
+        let Some(checkpoint) = &config.checkpoint else {
+            let resume = Resume {
+                writer: None,
+                skipped_by_checkpoint: 0,
+            };
+            return Ok((resume, urls));
+        };
+        let done = read_checkpoint(&checkpoint.path)?;
+        let count = urls.len();
+        let urls: Vec<String> = urls.into_iter().filter(|u| !done.contains(u)).collect();
+        let skipped_by_checkpoint = count - urls.len();
+        if !done.is_empty() {
+            info!(skipped = skipped_by_checkpoint, "resuming from checkpoint");
+        }
+        let resume = Resume {
+            writer: Some(CheckpointWriter::open(checkpoint)?),
+            skipped_by_checkpoint,
+        };
+        Ok((resume, urls))
+    }
+
+    /// Add `metric`'s image, saved under `output_dir`
+    fn record(&mut self, metric: &ImageMetrics, output_dir: &Path) -> Result<()> {
+        let Some(writer) = &mut self.writer else {
+            return Ok(());
+        };
+        writer.record(&CheckpointEntry {
+            url: metric.url.clone(),
+            filename: metric
+                .output_path
+                .strip_prefix(output_dir)?
+                .display()
+                .to_string(),
+        })
+    }
+
+    /// Flush the checkpoint and note in `stats` what it skipped
+    fn finish(mut self, stats: ProcessingStats) -> Result<ProcessingStats> {
+        let Some(writer) = &mut self.writer else {
+            return Ok(stats);
+        };
+        writer.sync()?;
+        Ok(ProcessingStats {
+            resumed_from_checkpoint: self.skipped_by_checkpoint > 0,
+            skipped_by_checkpoint: self.skipped_by_checkpoint,
+            ..stats
+        })
+    }
+}
+
+/// Run `work` under a run-level tracker and keep the highest CPU usage it or any image saw.
+/// Per-image trackers take their first CPU reading, which is always 0, as the image starts,
+/// so images that finish within one sampling interval would otherwise report no CPU at all.
+pub(crate) async fn track_run_cpu(
+    work: impl Future<Output = Result<NaiveTotals>>,
+) -> Result<NaiveTotals> {
+    let (monitor_handle, peak) = spawn_peak_tracker(100, MemoryMonitor::new());
+    let totals = work.await;
+    monitor_handle.abort();
+    let mut totals = totals?;
+    totals.peak_cpu_percent = totals.peak_cpu_percent.max(peak.cpu_percent());
+    Ok(totals)
+}
+
+/// Per-image figures gathered while a naive or sampled run goes, turned into
+/// [`ProcessingStats`] at the end
+#[derive(Default)]
+pub(crate) struct NaiveTotals {
+    pub peak_memory_mb: u64,
+    pub peak_cpu_percent: f32,
+    pub monitor_overhead_us: u64,
+    download_samples: Vec<u64>,
+    resize_samples: Vec<u64>,
+    compression_ratios: Vec<f64>,
+    server_timing: ServerTimingSplit,
+    errors: Vec<(String, String)>,
+}
+
+impl NaiveTotals {
+    /// Add one processed image's timings, memory and CPU
+    pub fn record_image(&mut self, metric: &ImageMetrics) {
+        self.peak_memory_mb = max(metric.peak_memory_mb, self.peak_memory_mb);
+        self.peak_cpu_percent = self.peak_cpu_percent.max(metric.peak_cpu_percent);
+        self.monitor_overhead_us += metric.monitor_overhead_us;
+        self.server_timing.record(metric);
+        self.download_samples.push(metric.download_ms);
+        self.resize_samples.push(metric.resize_ms);
+        self.compression_ratios.push(metric.compression_ratio);
+    }
+
+    /// [`collect_error`] into this run's errors
+    pub fn collect_error(
+        &mut self,
+        url: &str,
+        error: anyhow::Error,
+        config: &ProcessorConfig,
+    ) -> Result<()> {
+        collect_error(url, error, &mut self.errors, config)
+    }
+
+    /// Stats for a run that took `total_time_ms`, counting only the images recorded.
+    /// Also reports the peak memory to `config.live_metrics`.
+    pub fn into_stats(
+        mut self,
+        total_time_ms: u64,
+        skipped_count: usize,
+        config: &ProcessorConfig,
+    ) -> ProcessingStats {
+        if let Some(metrics) = &config.live_metrics {
+            metrics.record_peak_memory_mb(self.peak_memory_mb);
+        }
+        let total_images = self.download_samples.len();
+        let processed = total_images.max(1) as u64;
+        let avg_download_ms = self.download_samples.iter().sum::<u64>() / processed;
+        let avg_resize_ms = self.resize_samples.iter().sum::<u64>() / processed;
+        let [p50_download_ms, p95_download_ms, p99_download_ms] =
+            percentiles(&mut self.download_samples);
+        let [p50_resize_ms, p95_resize_ms, p99_resize_ms] = percentiles(&mut self.resize_samples);
+        let [min_compression_ratio, max_compression_ratio, avg_compression_ratio] =
+            min_max_avg(&self.compression_ratios);
+
+        ProcessingStats {
+            total_images,
+            total_time_ms,
+            peak_memory_mb: self.peak_memory_mb,
+            peak_cpu_percent: self.peak_cpu_percent,
+            monitor_overhead_us: self.monitor_overhead_us,
+            avg_download_ms,
+            avg_resize_ms,
+            p50_download_ms,
+            p95_download_ms,
+            p99_download_ms,
+            p50_resize_ms,
+            p95_resize_ms,
+            p99_resize_ms,
+            stddev_download_ms: stddev(&self.download_samples),
+            stddev_resize_ms: stddev(&self.resize_samples),
+            download_samples: self.download_samples,
+            min_compression_ratio,
+            max_compression_ratio,
+            avg_compression_ratio,
+            server_timing: self.server_timing,
+            sampled: false,
+            sample_rate: 1.0,
+            resumed_from_checkpoint: false,
+            skipped_by_checkpoint: 0,
+            skipped_count,
+            errors: self.errors,
+        }
+    }
+}
+
+/// Under [`ErrorPolicy::CollectAndContinue`] record `error` against `url` so the run can
+/// carry on; otherwise hand it back to abort the run
+pub(crate) fn collect_error(
+    url: &str,
+    error: anyhow::Error,
+    errors: &mut Vec<(String, String)>,
+    config: &ProcessorConfig,
+) -> Result<()> {
+    if config.error_policy == ErrorPolicy::FailFast {
+        return Err(error);
+    }
+    warn!(url, error = %error, "image failed, continuing");
+    errors.push((url.to_string(), error.to_string()));
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::fs;
+    use crate::{config::CheckpointConfig, test_support::jpeg_bytes, url_generator::UrlTemplate};
+    use std::{
+        fs,
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+    use wiremock::{
+        matchers::{any, path, path_regex},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    #[test]
+    fn round_trips_stats() {
+        let stats = ProcessingStats {
+            total_images: 40,
+            total_time_ms: 1200,
+            stddev_download_ms: 3.5,
+            sampled: true,
+            sample_rate: 0.25,
+            ..Default::default()
+        };
+        let json = serde_json::to_string(&stats).unwrap();
+        let loaded: ProcessingStats = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.total_images, 40);
+        assert_eq!(loaded.total_time_ms, 1200);
+        assert_eq!(loaded.stddev_download_ms, 3.5);
+        assert!(loaded.sampled);
+        assert_eq!(loaded.sample_rate, 0.25);
+    }
+
+    #[test]
+    fn converts_stats_to_run() {
+        let stats = ProcessingStats {
+            total_images: 4,
+            total_time_ms: 200,
+            p95_download_ms: 30,
+            p99_resize_ms: 12,
+            stddev_resize_ms: 1.5,
+            peak_cpu_percent: 80.0,
+            download_samples: vec![10, 20, 30, 40],
+            ..Default::default()
+        };
+        let run = stats.to_run("naive");
+        assert_eq!(run.approach, "naive");
+        assert_eq!(run.image_count, 4);
+        assert_eq!(run.total_time_ms, 200);
+        assert_eq!(run.p95_download_ms, 30);
+        assert_eq!(run.p99_resize_ms, 12);
+        assert_eq!(run.stddev_resize_ms, 1.5);
+        assert_eq!(run.peak_cpu_percent, 80.0);
+        assert_eq!(run.download_samples, stats.download_samples);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn tracks_cpu_over_the_run() {
+        // Short images report 0 on their own; the run-level tracker still sees the work
+        let totals = track_run_cpu(async {
+            tokio::task::spawn_blocking(|| {
+                let start = std::time::Instant::now();
+                let mut spins = 0u64;
+                while start.elapsed() < Duration::from_millis(400) {
+                    spins = std::hint::black_box(spins + 1);
+                }
+            })
+            .await?;
+            Ok(NaiveTotals::default())
+        })
+        .await
+        .unwrap();
+        assert!(totals.peak_cpu_percent > 0.0);
+    }
 
     #[tokio::test]
     async fn processes_images_sequentially() {
+        let server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(jpeg_bytes(400, 300)))
+            .mount(&server)
+            .await;
+
         let output = Path::new("test_output_naive");
         fs::create_dir_all(output).unwrap();
 
-        let stats = process_naive(5, output).await.unwrap();
+        let config = ProcessorConfig {
+            url_template: Some(UrlTemplate::Custom(format!("{}/{{seed}}", server.uri()))),
+            ..Default::default()
+        };
+        let stats = process_naive(5, output, &config).await.unwrap();
 
         assert_eq!(stats.total_images, 5);
         assert!(stats.total_time_ms > 0);
-        assert!(stats.peak_memory_mb > 0);
+        assert!(stats.monitor_overhead_us > 0);
+
+        fs::remove_dir_all(output).unwrap();
+    }
+
+    #[tokio::test]
+    async fn pipelines_downloads() {
+        let server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(jpeg_bytes(16, 16)))
+            .mount(&server)
+            .await;
+
+        let output = Path::new("test_output_naive_pipelined");
+        fs::create_dir_all(output).unwrap();
+
+        let urls = (0..5).map(|i| format!("{}/{}", server.uri(), i)).collect();
+        let config = ProcessorConfig {
+            semi_async_naive: true,
+            ..Default::default()
+        };
+        let stats = process_naive_pipelined(urls, output, &config)
+            .await
+            .unwrap();
+
+        assert_eq!(stats.total_images, 5);
+        assert_eq!(fs::read_dir(output).unwrap().count(), 5);
+
+        fs::remove_dir_all(output).unwrap();
+    }
+
+    #[tokio::test]
+    async fn pipelines_like_sequential_downloads() {
+        let server = MockServer::start().await;
+        // A JPEG body, so only the status marks it as failed
+        Mock::given(path("/missing"))
+            .respond_with(ResponseTemplate::new(404).set_body_bytes(jpeg_bytes(16, 16)))
+            .mount(&server)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(jpeg_bytes(16, 16)))
+            .mount(&server)
+            .await;
+
+        let output = Path::new("test_output_naive_pipelined_checkpoint");
+        fs::create_dir_all(output).unwrap();
+        let urls: Vec<String> = ["1", "missing", "2", "3"]
+            .iter()
+            .map(|path| format!("{}/{}", server.uri(), path))
+            .collect();
+        let config = ProcessorConfig {
+            semi_async_naive: true,
+            error_policy: ErrorPolicy::CollectAndContinue,
+            checkpoint: Some(CheckpointConfig {
+                path: output.join("checkpoint.jsonl"),
+                sync_interval: 1,
+            }),
+            ..Default::default()
+        };
+
+        let first = process_naive_pipelined(urls[..2].to_vec(), output, &config)
+            .await
+            .unwrap();
+        assert_eq!(first.total_images, 1);
+        assert_eq!(first.errors.len(), 1);
+        assert_eq!(first.errors[0].0, urls[1]);
+
+        let resumed = process_naive_pipelined(urls, output, &config)
+            .await
+            .unwrap();
+        assert!(resumed.resumed_from_checkpoint);
+        assert_eq!(resumed.skipped_by_checkpoint, 1);
+        assert_eq!(resumed.total_images, 2);
+        assert_eq!(resumed.errors.len(), 1);
+
+        fs::remove_dir_all(output).unwrap();
+    }
+
+    #[tokio::test]
+    async fn processes_images_concurrently() {
+        let server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(jpeg_bytes(16, 16)))
+            .mount(&server)
+            .await;
+
+        let output = Path::new("test_output_naive_concurrent");
+        fs::create_dir_all(output).unwrap();
+
+        let urls = (0..6).map(|i| format!("{}/{}", server.uri(), i)).collect();
+        let stats = process_naive_concurrent_urls(urls, 3, output, &ProcessorConfig::default())
+            .await
+            .unwrap();
+
+        assert_eq!(stats.total_images, 6);
+        assert_eq!(fs::read_dir(output).unwrap().count(), 6);
+
+        fs::remove_dir_all(output).unwrap();
+    }
+
+    #[tokio::test]
+    async fn skips_existing_images() {
+        let server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(jpeg_bytes(16, 16)))
+            .mount(&server)
+            .await;
+
+        let output = Path::new("test_output_naive_skip_existing");
+        fs::create_dir_all(output).unwrap();
+        let config = ProcessorConfig {
+            skip_existing: true,
+            ..Default::default()
+        };
+        let urls: Vec<String> = (0..4).map(|i| format!("{}/{}", server.uri(), i)).collect();
+
+        process_naive_urls(urls[..2].to_vec(), output, &config)
+            .await
+            .unwrap();
+        let stats = process_naive_urls(urls, output, &config).await.unwrap();
+
+        assert_eq!(stats.skipped_count, 2);
+        assert_eq!(stats.total_images, 2);
+        assert_eq!(server.received_requests().await.unwrap().len(), 4);
+        assert_eq!(fs::read_dir(output).unwrap().count(), 4);
+
+        fs::remove_dir_all(output).unwrap();
+    }
+
+    #[tokio::test]
+    async fn resumes_from_checkpoint() {
+        let server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(jpeg_bytes(16, 16)))
+            .mount(&server)
+            .await;
+
+        let output = Path::new("test_output_naive_checkpoint");
+        fs::create_dir_all(output).unwrap();
+        let config = ProcessorConfig {
+            checkpoint: Some(CheckpointConfig {
+                path: output.join("checkpoint.jsonl"),
+                sync_interval: 2,
+            }),
+            ..Default::default()
+        };
+        let urls: Vec<String> = (0..5).map(|i| format!("{}/{}", server.uri(), i)).collect();
+
+        // First run stops after three images
+        let first = process_naive_urls(urls[..3].to_vec(), output, &config)
+            .await
+            .unwrap();
+        assert!(!first.resumed_from_checkpoint);
+
+        let resumed = process_naive_urls(urls, output, &config).await.unwrap();
+        assert!(resumed.resumed_from_checkpoint);
+        assert_eq!(resumed.skipped_by_checkpoint, 3);
+        assert_eq!(resumed.total_images, 2);
+        assert_eq!(server.received_requests().await.unwrap().len(), 5);
+
+        fs::remove_dir_all(output).unwrap();
+    }
+
+    #[tokio::test]
+    async fn collects_errors_and_continues() {
+        let server = MockServer::start().await;
+        Mock::given(path_regex("^/good/"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(jpeg_bytes(16, 16)))
+            .mount(&server)
+            .await;
+        Mock::given(path("/bad"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("not an image"))
+            .mount(&server)
+            .await;
+
+        let output = Path::new("test_output_naive_collect_errors");
+        fs::create_dir_all(output).unwrap();
+        let urls: Vec<String> = ["good/1", "good/2", "bad", "good/3", "good/4"]
+            .iter()
+            .map(|path| format!("{}/{}", server.uri(), path))
+            .collect();
+
+        let fail_fast = process_naive_urls(urls.clone(), output, &ProcessorConfig::default()).await;
+        assert!(fail_fast.is_err());
+
+        let config = ProcessorConfig {
+            error_policy: ErrorPolicy::CollectAndContinue,
+            ..Default::default()
+        };
+        let stats = process_naive_urls(urls.clone(), output, &config)
+            .await
+            .unwrap();
+        assert_eq!(stats.total_images, 4);
+        assert_eq!(stats.download_samples.len(), 4);
+        assert_eq!(stats.errors.len(), 1);
+        assert_eq!(stats.errors[0].0, urls[2]);
+        let stats = process_naive_concurrent_urls(urls, 2, output, &config)
+            .await
+            .unwrap();
+        assert_eq!(stats.total_images, 4);
+        assert_eq!(stats.errors.len(), 1);
+        assert_eq!(fs::read_dir(output).unwrap().count(), 4);
+
+        fs::remove_dir_all(output).unwrap();
+    }
+
+    #[tokio::test]
+    async fn concurrent_fails_fast() {
+        let server = MockServer::start().await;
+        Mock::given(path("/bad"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("not an image"))
+            .mount(&server)
+            .await;
+        Mock::given(any())
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_bytes(jpeg_bytes(16, 16))
+                    .set_delay(Duration::from_millis(200)),
+            )
+            .mount(&server)
+            .await;
+
+        let output = Path::new("test_output_naive_concurrent_fail_fast");
+        fs::create_dir_all(output).unwrap();
+        let urls: Vec<String> = std::iter::once("bad".to_string())
+            .chain((0..8).map(|i| format!("good/{}", i)))
+            .map(|path| format!("{}/{}", server.uri(), path))
+            .collect();
+
+        let result =
+            process_naive_concurrent_urls(urls, 2, output, &ProcessorConfig::default()).await;
+        assert!(result.is_err());
+        // The failure aborted the queued images before they were requested
+        assert!(server.received_requests().await.unwrap().len() < 9);
+
+        fs::remove_dir_all(output).unwrap();
+    }
+
+    /// Keep-alive HTTP server answering every request with `body`, counting the connections
+    /// it accepts
+    async fn counting_server(body: Vec<u8>) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let connections = Arc::new(AtomicUsize::new(0));
+        let accepted = Arc::clone(&connections);
+        spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                accepted.fetch_add(1, Ordering::Relaxed);
+                let body = body.clone();
+                spawn(async move {
+                    let head = format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n",
+                        body.len()
+                    );
+                    // These GETs are small enough to arrive in a single read each
+                    let mut request = [0u8; 4096];
+                    while matches!(socket.read(&mut request).await, Ok(n) if n > 0) {
+                        if socket.write_all(head.as_bytes()).await.is_err()
+                            || socket.write_all(&body).await.is_err()
+                        {
+                            break;
+                        }
+                    }
+                });
+            }
+        });
+        (url, connections)
+    }
+
+    #[tokio::test]
+    async fn reuses_connections() {
+        let (server, connections) = counting_server(jpeg_bytes(16, 16)).await;
+        let output = Path::new("test_output_naive_reuse_connections");
+        fs::create_dir_all(output).unwrap();
+        let urls: Vec<String> = (0..4).map(|i| format!("{}/{}", server, i)).collect();
+
+        let stats = process_naive_urls(urls.clone(), output, &ProcessorConfig::default())
+            .await
+            .unwrap();
+        assert_eq!(stats.total_images, 4);
+        assert_eq!(connections.load(Ordering::Relaxed), 1);
+
+        let config = ProcessorConfig {
+            reuse_connections: false,
+            ..Default::default()
+        };
+        process_naive_urls(urls, output, &config).await.unwrap();
+        assert_eq!(connections.load(Ordering::Relaxed), 5);
 
         fs::remove_dir_all(output).unwrap();
     }
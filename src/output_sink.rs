@@ -0,0 +1,264 @@
+// src/output_sink.rs
+
+use anyhow::Result;
+use image::DynamicImage;
+use serde::Serialize;
+use std::{
+    fs::File,
+    io::{self, BufWriter, Cursor, Stdout, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+use crate::{
+    config::ProcessorConfig,
+    image_processor::{
+        encode, output_name, output_path, save_atomic, save_bytes_atomic, skip_existing,
+    },
+};
+
+/// Where a run writes its processed images
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OutputSink {
+    /// One file per image under this directory
+    Directory(PathBuf),
+    /// Every image as an entry of a single ZIP file at this path, avoiding the filesystem
+    /// overhead of thousands of small files
+    ZipArchive(PathBuf),
+    /// No images saved, just one [`ImageRecord`] per image written to stdout so runs can be
+    /// piped into other tools. Only the streaming pipeline supports it.
+    Stdout(RecordFormat),
+}
+
+/// How [`OutputSink::Stdout`] writes each [`ImageRecord`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordFormat {
+    /// One JSON object per line
+    Ndjson,
+    /// A header row, then one comma-separated row per image
+    Csv,
+}
+
+/// What [`OutputSink::Stdout`] writes for each processed image
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ImageRecord {
+    pub url: String,
+    pub width: u32,
+    pub height: u32,
+    pub download_ms: u64,
+    pub resize_ms: u64,
+}
+
+impl RecordFormat {
+    fn header(self) -> Option<&'static str> {
+        match self {
+            RecordFormat::Ndjson => None,
+            RecordFormat::Csv => Some("url,width,height,download_ms,resize_ms"),
+        }
+    }
+
+    /// `record` as a single line, without the trailing newline
+    pub fn format(self, record: &ImageRecord) -> Result<String> {
+        Ok(match self {
+            RecordFormat::Ndjson => serde_json::to_string(record)?,
+            RecordFormat::Csv => format!(
+                "{},{},{},{},{}",
+                record.url, record.width, record.height, record.download_ms, record.resize_ms
+            ),
+        })
+    }
+}
+
+impl OutputSink {
+    /// Directory for run-level files such as the manifest: the output directory itself, the
+    /// one holding the archive, or the current directory for stdout
+    pub fn dir(&self) -> &Path {
+        match self {
+            OutputSink::Directory(dir) => dir,
+            OutputSink::ZipArchive(path) => path.parent().unwrap_or(Path::new("")),
+            OutputSink::Stdout(_) => Path::new(""),
+        }
+    }
+
+    /// [`skip_existing`] for directories. Archives and stdout are written from scratch on
+    /// every run, so nothing is skipped for them.
+    pub fn skip_existing(
+        &self,
+        urls: Vec<String>,
+        config: &ProcessorConfig,
+    ) -> (Vec<String>, usize) {
+        match self {
+            OutputSink::Directory(dir) => skip_existing(urls, dir, config),
+            OutputSink::ZipArchive(_) | OutputSink::Stdout(_) => (urls, 0),
+        }
+    }
+
+    /// Whether [`OutputSink::skip_existing`] would skip `url`, for URLs checked one at a time
+    pub fn is_saved(&self, url: &str, config: &ProcessorConfig) -> bool {
+        match self {
+            OutputSink::Directory(dir) => {
+                config.skip_existing && dir.join(output_name(url, config)).exists()
+            }
+            OutputSink::ZipArchive(_) | OutputSink::Stdout(_) => false,
+        }
+    }
+
+    /// Create the archive, if any, ready for workers to write into. For stdout the CSV
+    /// header is written straight away.
+    pub(crate) fn open(&self) -> Result<SinkWriter> {
+        let mut writer = SinkWriter::directory(self.dir());
+        match self {
+            OutputSink::Directory(_) => {}
+            OutputSink::ZipArchive(path) => {
+                writer.archive = Some(Arc::new(Mutex::new(ZipWriter::new(File::create(path)?))));
+            }
+            OutputSink::Stdout(format) => {
+                let mut stdout = BufWriter::new(io::stdout());
+                if let Some(header) = format.header() {
+                    writeln!(stdout, "{}", header)?;
+                }
+                writer.records = Some((*format, Arc::new(Mutex::new(stdout))));
+            }
+        }
+        Ok(writer)
+    }
+}
+
+/// An opened [`OutputSink`]. Clones share the same archive or stdout writer.
+#[derive(Clone)]
+pub(crate) struct SinkWriter {
+    dir: PathBuf,
+    archive: Option<Arc<Mutex<ZipWriter<File>>>>,
+    records: Option<(RecordFormat, Arc<Mutex<BufWriter<Stdout>>>)>,
+}
+
+impl SinkWriter {
+    pub fn directory(dir: &Path) -> Self {
+        SinkWriter {
+            dir: dir.to_path_buf(),
+            archive: None,
+            records: None,
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Whether images are reported with [`SinkWriter::write_record`] instead of saved
+    pub fn writes_records(&self) -> bool {
+        self.records.is_some()
+    }
+
+    /// Write `record` as one line to stdout
+    pub fn write_record(&self, record: &ImageRecord) -> Result<()> {
+        let Some((format, stdout)) = &self.records else {
+            anyhow::bail!("output sink does not write records");
+        };
+        let line = format.format(record)?;
+        writeln!(stdout.lock().unwrap(), "{}", line)?;
+        Ok(())
+    }
+
+    /// Save `img` under the SHA256-based name for `url`. Returns the saved file's path, or the
+    /// entry name inside the archive, and the encoded size.
+    pub fn save(
+        &self,
+        url: &str,
+        img: &DynamicImage,
+        config: &ProcessorConfig,
+    ) -> Result<(PathBuf, u64)> {
+        self.ensure_saves()?;
+        let save = config.save.unwrap_or_default();
+        let Some(archive) = &self.archive else {
+            let path = output_path(url, &self.dir, config)?;
+            let bytes = save_atomic(img, &path, &save)?;
+            return Ok((path, bytes));
+        };
+
+        // Encode before taking the lock so workers only contend on the write itself
+        let mut encoded = Vec::new();
+        encode(img, &mut Cursor::new(&mut encoded), save.format)?;
+        let name = output_name(url, config);
+        write_entry(archive, &name, &encoded)?;
+        Ok((name, encoded.len() as u64))
+    }
+
+    /// Like [`SinkWriter::save`], for bytes an image processor backend already encoded
+    pub fn save_encoded(
+        &self,
+        url: &str,
+        bytes: &[u8],
+        config: &ProcessorConfig,
+    ) -> Result<(PathBuf, u64)> {
+        self.ensure_saves()?;
+        let Some(archive) = &self.archive else {
+            let path = output_path(url, &self.dir, config)?;
+            let bytes = save_bytes_atomic(bytes, &path)?;
+            return Ok((path, bytes));
+        };
+        let name = output_name(url, config);
+        write_entry(archive, &name, bytes)?;
+        Ok((name, bytes.len() as u64))
+    }
+
+    fn ensure_saves(&self) -> Result<()> {
+        anyhow::ensure!(
+            !self.writes_records(),
+            "stdout output is only supported by the streaming pipeline"
+        );
+        Ok(())
+    }
+
+    /// Write the archive's central directory, or flush stdout. Every other clone must have
+    /// been dropped.
+    pub fn finish(self) -> Result<()> {
+        if let Some((_, stdout)) = self.records {
+            stdout.lock().unwrap().flush()?;
+        }
+        if let Some(archive) = self.archive {
+            let archive = Arc::try_unwrap(archive)
+                .map_err(|_| anyhow::anyhow!("zip archive is still being written"))?;
+            archive.into_inner().unwrap().finish()?;
+        }
+        Ok(())
+    }
+}
+
+fn write_entry(archive: &Mutex<ZipWriter<File>>, name: &Path, bytes: &[u8]) -> Result<()> {
+    // Image data is already compressed, so entries are stored as-is
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    let mut archive = archive.lock().unwrap();
+    archive.start_file(name.to_string_lossy(), options)?;
+    archive.write_all(bytes)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_records() {
+        let record = ImageRecord {
+            url: "https://example.com/1".to_string(),
+            width: 256,
+            height: 256,
+            download_ms: 120,
+            resize_ms: 30,
+        };
+        assert_eq!(
+            RecordFormat::Ndjson.format(&record).unwrap(),
+            r#"{"url":"https://example.com/1","width":256,"height":256,"download_ms":120,"resize_ms":30}"#
+        );
+        assert_eq!(
+            RecordFormat::Csv.format(&record).unwrap(),
+            "https://example.com/1,256,256,120,30"
+        );
+        assert_eq!(
+            RecordFormat::Csv.header(),
+            Some("url,width,height,download_ms,resize_ms")
+        );
+    }
+}
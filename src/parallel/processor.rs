@@ -0,0 +1,229 @@
+use crate::{
+    config::ProcessorConfig,
+    image_processor::{resize_and_save, skip_existing},
+    memory_monitor::{spawn_peak_tracker, MemoryMonitor},
+    metrics::{percentiles, stddev, ProcessingRun},
+    streaming::download::fetch_image,
+    url_generator::{ImageSource, UrlGenerator},
+    validation::validate_count,
+    warmup::warm_up,
+};
+use anyhow::Result;
+use futures::future::join_all;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tokio::{spawn, sync::Semaphore, task::spawn_blocking, time::Instant};
+use tracing::info;
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct ParallelStats {
+    pub total_images: usize,
+    /// Threads in Rayon's global pool that shared the resize work
+    pub threads: usize,
+    pub total_time_ms: u64,
+    pub peak_memory_mb: u64,
+    /// Highest CPU usage sampled during the run, as a percentage of one core
+    pub peak_cpu_percent: f32,
+    pub avg_download_ms: u64,
+    pub avg_resize_ms: u64,
+    pub p50_download_ms: u64,
+    pub p95_download_ms: u64,
+    pub p99_download_ms: u64,
+    pub p50_resize_ms: u64,
+    pub p95_resize_ms: u64,
+    pub p99_resize_ms: u64,
+    pub stddev_download_ms: f64,
+    pub stddev_resize_ms: f64,
+    /// Every image's download time, sorted
+    pub download_samples: Vec<u64>,
+    /// Images skipped because their output already existed, not counted in `total_images`
+    pub skipped_count: usize,
+    pub saved_paths: Vec<PathBuf>,
+    /// Time spent downloading before any resizing started
+    pub download_phase_ms: u64,
+}
+
+impl ParallelStats {
+    /// This run's row in a [`MetricsCollector`](crate::metrics::MetricsCollector) comparison
+    pub fn to_run(&self, approach: &str) -> ProcessingRun {
+        ProcessingRun::new(
+            approach,
+            self.total_images,
+            self.total_time_ms,
+            self.peak_memory_mb,
+            self.avg_download_ms,
+            self.avg_resize_ms,
+        )
+        .with_percentiles(
+            [
+                self.p50_download_ms,
+                self.p95_download_ms,
+                self.p99_download_ms,
+            ],
+            [self.p50_resize_ms, self.p95_resize_ms, self.p99_resize_ms],
+        )
+        .with_stddev(self.stddev_download_ms, self.stddev_resize_ms)
+        .with_peak_cpu(self.peak_cpu_percent)
+        .with_download_samples(self.download_samples.clone())
+    }
+}
+
+/// Download every image with up to `config.download_concurrency` requests in flight, then
+/// decode, resize and save them all across Rayon's global thread pool
+pub async fn process_parallel(
+    count: usize,
+    output_dir: &Path,
+    config: &ProcessorConfig,
+) -> Result<ParallelStats> {
+    validate_count(count)?;
+    let urls = UrlGenerator::for_config(count, config).urls();
+    process_parallel_urls(urls, output_dir, config).await
+}
+
+async fn process_parallel_urls(
+    urls: Vec<String>,
+    output_dir: &Path,
+    config: &ProcessorConfig,
+) -> Result<ParallelStats> {
+    let config = &config.with_shared_client()?;
+    let (urls, skipped_count) = skip_existing(urls, output_dir, config);
+    let count = urls.len();
+    warm_up(&urls, config).await;
+    let threads = rayon::current_num_threads();
+    info!(count, threads, "starting parallel processing");
+
+    let (monitor_handle, peak) = spawn_peak_tracker(100, MemoryMonitor::with_baseline());
+
+    let start_time = Instant::now();
+    let sem = Arc::new(Semaphore::new(config.download_concurrency));
+    let downloads = join_all(
+        urls.into_iter()
+            .map(|url| spawn(fetch_image(url, Arc::clone(&sem), config.clone()))),
+    )
+    .await;
+    let images = downloads
+        .into_iter()
+        .map(|res| Ok(res??))
+        .collect::<Result<Vec<_>>>();
+    let images = match images {
+        Ok(images) => images,
+        Err(e) => {
+            monitor_handle.abort();
+            return Err(e);
+        }
+    };
+    let download_phase_ms = start_time.elapsed().as_millis() as u64;
+    info!(download_phase_ms, "downloads complete");
+
+    let mut download_samples: Vec<u64> = images.iter().map(|img| img.download_ms as u64).collect();
+    let output_dir = output_dir.to_path_buf();
+    let resize_config = config.clone();
+    let saved = spawn_blocking(move || {
+        images
+            .par_iter()
+            .map(|img| {
+                let saved = resize_and_save(&img.url, &img.bytes, &output_dir, &resize_config)?;
+                if let Some(metrics) = &resize_config.live_metrics {
+                    metrics.record_image("parallel", img.download_ms as u64, saved.resize_ms);
+                }
+                Ok(saved)
+            })
+            .collect::<Result<Vec<_>>>()
+    })
+    .await?;
+    let total_time_ms = start_time.elapsed().as_millis() as u64;
+
+    monitor_handle.abort();
+    let saved = saved?;
+    let (peak_memory_mb, peak_cpu_percent) = (peak.memory_mb(), peak.cpu_percent());
+    if let Some(metrics) = &config.live_metrics {
+        metrics.record_peak_memory_mb(peak_memory_mb);
+    }
+
+    let mut resize_samples: Vec<u64> = saved.iter().map(|img| img.resize_ms).collect();
+    let processed = count.max(1) as u64;
+    let total_download_time: u64 = download_samples.iter().sum();
+    let total_resize_time: u64 = resize_samples.iter().sum();
+    let [p50_download_ms, p95_download_ms, p99_download_ms] = percentiles(&mut download_samples);
+    let [p50_resize_ms, p95_resize_ms, p99_resize_ms] = percentiles(&mut resize_samples);
+
+    info!(
+        total_time_ms,
+        peak_memory_mb,
+        avg_download_ms = total_download_time / processed,
+        avg_resize_ms = total_resize_time / processed,
+        "parallel processing complete"
+    );
+
+    Ok(ParallelStats {
+        total_images: count,
+        threads,
+        total_time_ms,
+        peak_memory_mb,
+        peak_cpu_percent,
+        avg_download_ms: total_download_time / processed,
+        avg_resize_ms: total_resize_time / processed,
+        p50_download_ms,
+        p95_download_ms,
+        p99_download_ms,
+        p50_resize_ms,
+        p95_resize_ms,
+        p99_resize_ms,
+        stddev_download_ms: stddev(&download_samples),
+        stddev_resize_ms: stddev(&resize_samples),
+        download_samples,
+        skipped_count,
+        saved_paths: saved.into_iter().map(|img| img.output_path).collect(),
+        download_phase_ms,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::jpeg_bytes;
+    use std::fs;
+    use wiremock::{matchers::any, Mock, MockServer, ResponseTemplate};
+
+    #[test]
+    fn round_trips_stats() {
+        let stats = ParallelStats {
+            total_images: 2,
+            threads: 8,
+            saved_paths: vec![PathBuf::from("a.jpg"), PathBuf::from("b.jpg")],
+            ..Default::default()
+        };
+        let json = serde_json::to_string(&stats).unwrap();
+        let loaded: ParallelStats = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.threads, 8);
+        assert_eq!(loaded.saved_paths, stats.saved_paths);
+    }
+
+    #[tokio::test]
+    async fn resizes_across_threads() {
+        let server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(jpeg_bytes(16, 16)))
+            .mount(&server)
+            .await;
+
+        let output = Path::new("test_output_parallel");
+        fs::create_dir_all(output).unwrap();
+
+        let urls = (0..8).map(|i| format!("{}/{}", server.uri(), i)).collect();
+        let stats = process_parallel_urls(urls, output, &ProcessorConfig::default())
+            .await
+            .unwrap();
+
+        assert_eq!(stats.total_images, 8);
+        assert_eq!(stats.saved_paths.len(), 8);
+        assert!(stats.saved_paths.iter().all(|path| path.exists()));
+        assert!(stats.download_phase_ms <= stats.total_time_ms);
+
+        fs::remove_dir_all(output).unwrap();
+    }
+}
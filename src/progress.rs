@@ -0,0 +1,23 @@
+// src/progress.rs
+
+use std::io::{stdout, IsTerminal};
+
+use indicatif::{ProgressBar, ProgressStyle};
+
+/// Progress bar over `count` images showing throughput, elapsed time and ETA. Hidden
+/// unless `enabled` is set and stdout is a terminal, so callers can advance it regardless.
+pub fn progress_bar(count: usize, enabled: bool) -> ProgressBar {
+    if !enabled || !stdout().is_terminal() {
+        return ProgressBar::hidden();
+    }
+
+    let bar = ProgressBar::new(count as u64);
+    bar.set_style(
+        ProgressStyle::with_template(
+            "{elapsed_precise} [{bar:40}] {pos}/{len} images, {per_sec}, ETA {eta}",
+        )
+        .unwrap()
+        .progress_chars("=> "),
+    );
+    bar
+}
@@ -0,0 +1,104 @@
+// src/run_config.rs
+
+use anyhow::Result;
+use serde::Serialize;
+use std::{fs, path::Path};
+
+use crate::{config::ProcessorConfig, manifest::now_ms};
+
+pub const RUN_CONFIG_FILENAME: &str = "run_config.json";
+
+/// The parameters a processor ran with, saved next to its output so results can be
+/// interpreted and reproduced later
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RunConfig {
+    pub approach: String,
+    pub count: usize,
+    /// Batched only
+    pub batch_size: Option<usize>,
+    pub download_concurrency: usize,
+    pub process_concurrency: usize,
+    pub download_channel_capacity: usize,
+    pub process_channel_capacity: usize,
+    pub resize_width: u32,
+    pub resize_height: u32,
+    pub resize_filter: String,
+    pub resize_mode: String,
+    /// Extension of the saved thumbnails
+    pub output_format: String,
+    /// Milliseconds since the Unix epoch
+    pub timestamp_ms: u64,
+    /// Commit the binary was built from, `None` when it wasn't built from a git checkout
+    pub git_sha: Option<String>,
+}
+
+impl RunConfig {
+    /// Parameters for running `approach` on `count` images with `config`, timestamped now
+    pub fn new(approach: &str, count: usize, config: &ProcessorConfig) -> Self {
+        let resize = config.resize.unwrap_or_default();
+        let format = config.save.unwrap_or_default().format;
+        RunConfig {
+            approach: approach.to_string(),
+            count,
+            batch_size: None,
+            download_concurrency: config.download_concurrency,
+            process_concurrency: config.process_concurrency,
+            download_channel_capacity: config.download_channel_capacity,
+            process_channel_capacity: config.process_channel_capacity,
+            resize_width: resize.width,
+            resize_height: resize.height,
+            resize_filter: format!("{:?}", resize.filter),
+            resize_mode: format!("{:?}", config.resize_mode),
+            output_format: format.extension().to_string(),
+            timestamp_ms: now_ms(),
+            git_sha: option_env!("FLUX_GIT_SHA").map(str::to_string),
+        }
+    }
+
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = Some(batch_size);
+        self
+    }
+
+    /// Write to `<dir>/run_config.json`, replacing the previous run's
+    pub fn save(&self, dir: &Path) -> Result<()> {
+        fs::create_dir_all(dir)?;
+        fs::write(
+            dir.join(RUN_CONFIG_FILENAME),
+            serde_json::to_string_pretty(self)?,
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saves_parameters() {
+        let dir = Path::new("test_output_run_config");
+        let config = ProcessorConfig {
+            download_concurrency: 4,
+            ..Default::default()
+        };
+
+        RunConfig::new("batched", 20, &config)
+            .with_batch_size(5)
+            .save(dir)
+            .unwrap();
+
+        let saved: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(dir.join(RUN_CONFIG_FILENAME)).unwrap())
+                .unwrap();
+        assert_eq!(saved["approach"], "batched");
+        assert_eq!(saved["count"], 20);
+        assert_eq!(saved["batch_size"], 5);
+        assert_eq!(saved["download_concurrency"], 4);
+        assert_eq!(saved["resize_width"], 256);
+        assert_eq!(saved["output_format"], "jpg");
+        assert!(saved["timestamp_ms"].as_u64().unwrap() > 0);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
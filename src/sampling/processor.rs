@@ -0,0 +1,122 @@
+use crate::{
+    config::ProcessorConfig,
+    image_processor::{process_single_image, skip_existing},
+    naive::processor::{track_run_cpu, NaiveTotals, ProcessingStats},
+    url_generator::ImageSource,
+    warmup::warm_up,
+};
+use anyhow::Result;
+use rand::Rng;
+use std::path::Path;
+use tokio::time::Instant;
+use tracing::info;
+
+/// Process each image with probability `sample_rate` for a quick sanity check.
+/// `total_time_ms` is extrapolated to the full source as `actual_time / sample_rate`.
+pub async fn process_sampled(
+    source: impl ImageSource,
+    sample_rate: f64,
+    output_dir: &Path,
+    config: &ProcessorConfig,
+) -> Result<ProcessingStats> {
+    anyhow::ensure!(
+        sample_rate > 0.0 && sample_rate <= 1.0,
+        "sample_rate must be in (0, 1], got {}",
+        sample_rate
+    );
+
+    let urls = sample(source.urls(), sample_rate, &mut rand::rng());
+    process_sampled_urls(urls, sample_rate, output_dir, config).await
+}
+
+/// Keep each of `urls` with probability `sample_rate`, drawing from `rng`
+fn sample(urls: Vec<String>, sample_rate: f64, rng: &mut impl Rng) -> Vec<String> {
+    urls.into_iter()
+        .filter(|_| rng.random_bool(sample_rate))
+        .collect()
+}
+
+/// [`process_sampled`] for `urls` already drawn at `sample_rate`
+async fn process_sampled_urls(
+    urls: Vec<String>,
+    sample_rate: f64,
+    output_dir: &Path,
+    config: &ProcessorConfig,
+) -> Result<ProcessingStats> {
+    let config = &config.with_shared_client()?;
+    let (urls, skipped_count) = skip_existing(urls, output_dir, config);
+    let count = urls.len();
+    warm_up(&urls, config).await;
+    info!(count, sample_rate, "starting sampled processing");
+
+    let start_time = Instant::now();
+    let totals = track_run_cpu(async {
+        let mut totals = NaiveTotals::default();
+        for (index, u) in urls.iter().enumerate() {
+            info!(index = index + 1, total = count, url = %u, "processing sampled image");
+
+            let metric = match process_single_image(u, output_dir, config).await {
+                Ok(metric) => metric,
+                Err(e) => {
+                    totals.collect_error(u, e, config)?;
+                    continue;
+                }
+            };
+            totals.record_image(&metric);
+            if let Some(metrics) = &config.live_metrics {
+                metrics.record_image("sampled", metric.download_ms, metric.resize_ms);
+            }
+        }
+        Ok(totals)
+    })
+    .await?;
+    let actual_time = start_time.elapsed().as_millis() as u64;
+    let total_time = (actual_time as f64 / sample_rate) as u64;
+    let stats = totals.into_stats(total_time, skipped_count, config);
+
+    info!(
+        actual_time_ms = actual_time,
+        extrapolated_time_ms = total_time,
+        peak_memory_mb = stats.peak_memory_mb,
+        "sampled processing complete"
+    );
+
+    Ok(ProcessingStats {
+        sampled: true,
+        sample_rate,
+        ..stats
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::jpeg_bytes;
+    use rand::{rngs::StdRng, SeedableRng};
+    use std::fs;
+    use wiremock::{matchers::any, Mock, MockServer, ResponseTemplate};
+
+    #[tokio::test]
+    async fn processes_half() {
+        let server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(jpeg_bytes(16, 16)))
+            .mount(&server)
+            .await;
+
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path();
+
+        let urls: Vec<String> = (0..20).map(|i| format!("{}/{}", server.uri(), i)).collect();
+        // Seeded, so the same 10 of the 20 are drawn every run
+        let urls = sample(urls, 0.5, &mut StdRng::seed_from_u64(42));
+        let stats = process_sampled_urls(urls, 0.5, output, &ProcessorConfig::default())
+            .await
+            .unwrap();
+
+        assert!(stats.sampled);
+        assert_eq!(stats.sample_rate, 0.5);
+        assert_eq!(stats.total_images, 10);
+        assert_eq!(fs::read_dir(output).unwrap().count(), stats.total_images);
+    }
+}
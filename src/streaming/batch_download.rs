@@ -0,0 +1,208 @@
+// src/streaming/batch_download.rs
+
+use reqwest::{multipart::Form, StatusCode};
+use tracing::debug;
+
+use crate::error::ProcessingError;
+
+/// Body of one image from a batch response, keyed by the URL it was requested as
+pub struct BatchPart {
+    pub url: String,
+    pub bytes: Vec<u8>,
+}
+
+/// POST `urls` to `endpoint` as a multipart form (one `url` field per image) and split the
+/// multipart response into its parts, matched to URLs as [`match_parts`] does. Returns `Ok(None)` when the endpoint answers 404 or 405,
+/// meaning the server doesn't support batching and callers should GET each URL instead.
+pub async fn fetch_batch(
+    client: &reqwest::Client,
+    endpoint: &str,
+    urls: &[String],
+) -> Result<Option<Vec<BatchPart>>, ProcessingError> {
+    let form = urls
+        .iter()
+        .fold(Form::new(), |form, url| form.text("url", url.clone()));
+    let response = client
+        .post(endpoint)
+        .multipart(form)
+        .send()
+        .await
+        .map_err(|e| ProcessingError::download(endpoint, e))?;
+
+    if matches!(
+        response.status(),
+        StatusCode::NOT_FOUND | StatusCode::METHOD_NOT_ALLOWED
+    ) {
+        debug!(endpoint, status = %response.status(), "batch downloads unsupported");
+        return Ok(None);
+    }
+    let response = response
+        .error_for_status()
+        .map_err(|e| ProcessingError::download(endpoint, e))?;
+
+    let boundary = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(multipart_boundary)
+        .ok_or_else(|| ProcessingError::download(endpoint, "response is not multipart"))?;
+    let body = response
+        .bytes()
+        .await
+        .map_err(|e| ProcessingError::download(endpoint, e))?;
+
+    Ok(Some(match_parts(parse_multipart(&body, &boundary), urls)))
+}
+
+/// Pair each part with the requested URL it answers: the one its `Content-Location` names,
+/// or failing that (relative, rewritten or missing locations) the one at the same position
+/// in the request. Parts left without a URL are dropped, so every returned part has one of
+/// `urls` and no URL is answered twice; URLs without a part are left for the caller.
+fn match_parts(parts: Vec<(Option<String>, Vec<u8>)>, urls: &[String]) -> Vec<BatchPart> {
+    let mut taken = vec![false; urls.len()];
+    let mut matched: Vec<Option<usize>> = vec![None; parts.len()];
+    for (index, (location, _)) in parts.iter().enumerate() {
+        let found = urls
+            .iter()
+            .enumerate()
+            .position(|(i, url)| !taken[i] && location.as_ref() == Some(url));
+        if let Some(i) = found {
+            taken[i] = true;
+            matched[index] = Some(i);
+        }
+    }
+    for (index, slot) in matched.iter_mut().enumerate() {
+        if slot.is_none() && taken.get(index) == Some(&false) {
+            taken[index] = true;
+            *slot = Some(index);
+        }
+    }
+
+    parts
+        .into_iter()
+        .zip(matched)
+        .filter_map(|((location, bytes), matched)| match matched {
+            Some(i) => Some(BatchPart {
+                url: urls[i].clone(),
+                bytes,
+            }),
+            None => {
+                debug!(?location, "batch part matches no requested URL");
+                None
+            }
+        })
+        .collect()
+}
+
+/// `boundary` parameter of a `multipart/*` content type
+fn multipart_boundary(content_type: &str) -> Option<String> {
+    let mut params = content_type.split(';').map(str::trim);
+    if !params.next()?.starts_with("multipart/") {
+        return None;
+    }
+    params.find_map(|param| {
+        let (key, value) = param.split_once('=')?;
+        key.eq_ignore_ascii_case("boundary")
+            .then(|| value.trim_matches('"').to_string())
+    })
+}
+
+/// Split a multipart body into `(Content-Location, body)` pairs
+fn parse_multipart(body: &[u8], boundary: &str) -> Vec<(Option<String>, Vec<u8>)> {
+    let delimiter = format!("--{}", boundary).into_bytes();
+    let mut parts = vec![];
+
+    // Everything before the first delimiter is preamble
+    let Some(start) = find(body, &delimiter) else {
+        return parts;
+    };
+    let mut rest = &body[start + delimiter.len()..];
+    while !rest.starts_with(b"--") {
+        let end = find(rest, &delimiter).unwrap_or(rest.len());
+        let section = &rest[..end];
+        let section = section.strip_prefix(b"\r\n").unwrap_or(section);
+        if let Some(header_end) = find(section, b"\r\n\r\n") {
+            let headers = String::from_utf8_lossy(&section[..header_end]);
+            let content = &section[header_end + 4..];
+            let content = content.strip_suffix(b"\r\n").unwrap_or(content);
+            let location = headers.lines().find_map(|line| {
+                let (name, value) = line.split_once(':')?;
+                name.trim()
+                    .eq_ignore_ascii_case("content-location")
+                    .then(|| value.trim().to_string())
+            });
+            parts.push((location, content.to_vec()));
+        }
+
+        if end == rest.len() {
+            break;
+        }
+        rest = &rest[end + delimiter.len()..];
+    }
+    parts
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_multipart_body() {
+        let body = b"preamble\r\n--xyz\r\nContent-Type: image/jpeg\r\nContent-Location: https://example.com/1.jpg\r\n\r\nfirst\r\n--xyz\r\nContent-Type: image/jpeg\r\n\r\nsecond\r\n--xyz--\r\n";
+
+        let parts = parse_multipart(body, "xyz");
+
+        assert_eq!(
+            parts,
+            vec![
+                (
+                    Some("https://example.com/1.jpg".to_string()),
+                    b"first".to_vec()
+                ),
+                (None, b"second".to_vec()),
+            ]
+        );
+        assert_eq!(
+            multipart_boundary("multipart/mixed; boundary=\"xyz\""),
+            Some("xyz".to_string())
+        );
+    }
+
+    #[test]
+    fn matches_parts_to_requested_urls() {
+        let urls: Vec<String> = [
+            "https://example.com/a",
+            "https://example.com/b",
+            "https://example.com/c",
+        ]
+        .map(str::to_string)
+        .to_vec();
+        let parts = vec![
+            (Some(urls[2].clone()), b"c".to_vec()),
+            (Some("/b".to_string()), b"b".to_vec()),
+            (None, b"a".to_vec()),
+            (Some("https://elsewhere.com/d".to_string()), b"d".to_vec()),
+        ];
+
+        let matched: Vec<(String, Vec<u8>)> = match_parts(parts, &urls)
+            .into_iter()
+            .map(|part| (part.url, part.bytes))
+            .collect();
+
+        // The relative location takes its position; the stray part past the end is dropped
+        // and "a" has no free position left, leaving its URL unanswered
+        assert_eq!(
+            matched,
+            vec![
+                (urls[2].clone(), b"c".to_vec()),
+                (urls[1].clone(), b"b".to_vec()),
+            ]
+        );
+    }
+}
@@ -3,7 +3,11 @@
 use std::time::Duration;
 
 use anyhow::Result;
-use tokio::{sync::mpsc, time::sleep};
+use tokio::{
+    spawn,
+    sync::mpsc,
+    time::{sleep, Instant},
+};
 use tracing::info;
 
 pub async fn channel_demo() -> Result<()> {
@@ -51,9 +55,42 @@ pub async fn backpressure_demo() -> Result<()> {
     Ok(())
 }
 
+/// Push `message_count` messages through a bounded channel of each capacity and
+/// report `(capacity, throughput_msgs_per_sec)`. Smaller buffers make the producer
+/// wait on the consumer more often, which shows up as lower throughput.
+pub async fn bounded_backpressure_benchmark(
+    capacities: &[usize],
+    message_count: usize,
+) -> Vec<(usize, u64)> {
+    let mut results = vec![];
+    for &capacity in capacities {
+        let (tx, mut rx) = mpsc::channel::<usize>(capacity.max(1));
+        let start_time = Instant::now();
+
+        let producer = spawn(async move {
+            for i in 0..message_count {
+                if tx.send(i).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut received = 0;
+        while rx.recv().await.is_some() {
+            received += 1;
+        }
+        producer.await.unwrap();
+
+        let elapsed = start_time.elapsed().as_secs_f64().max(f64::EPSILON);
+        results.push((capacity, (received as f64 / elapsed) as u64));
+    }
+    results
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::metrics::BenchmarkSeries;
 
     #[tokio::test]
     async fn basic_channel_works() {
@@ -64,4 +101,23 @@ mod tests {
     async fn backpressure_works() {
         backpressure_demo().await.unwrap();
     }
+
+    #[tokio::test]
+    async fn benchmarks_each_capacity() {
+        let results = bounded_backpressure_benchmark(&[1, 16, 256], 2_000).await;
+
+        let capacities: Vec<usize> = results.iter().map(|(capacity, _)| *capacity).collect();
+        assert_eq!(capacities, vec![1, 16, 256]);
+        assert!(results.iter().all(|(_, throughput)| *throughput > 0));
+
+        let table = BenchmarkSeries::new("bounded mpsc", &results).render_table();
+        assert!(table.contains("Throughput (msg/s)"));
+        for capacity in ["1", "16", "256"] {
+            let row = format!("│ {capacity} ");
+            assert_eq!(
+                table.lines().filter(|line| line.starts_with(&row)).count(),
+                1
+            );
+        }
+    }
 }
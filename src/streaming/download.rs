@@ -1,69 +1,668 @@
 use anyhow::Result;
-use futures::future::join_all;
-use std::sync::Arc;
+use futures::{
+    future::{self, join_all},
+    stream::FuturesUnordered,
+    Stream, StreamExt,
+};
+use rand::Rng;
+use reqwest::{
+    header::{HeaderMap, ETAG, IF_NONE_MATCH},
+    StatusCode,
+};
+use std::{collections::HashMap, path::Path, pin::pin, sync::Arc, time::Duration};
 use tokio::{
     spawn,
-    sync::{mpsc, Semaphore},
-    time::Instant,
+    sync::{mpsc, Semaphore, SemaphorePermit},
+    task::JoinHandle,
+    time::{sleep, Instant},
+};
+use tracing::{debug, info, info_span, instrument, warn, Instrument};
+
+use crate::{
+    compression::{compress, ChannelCompression},
+    config::ProcessorConfig,
+    error::{DeadLetterQueue, ProcessingError},
+    http_client::{download_with_retry, server_timing},
+    streaming::{
+        batch_download::fetch_batch,
+        in_flight::{InFlightLimiter, InFlightPermit},
+    },
+    url_generator::InputSource,
 };
-use tracing::{debug, info};
 
 pub struct ImageData {
     pub url: String,
     pub bytes: Vec<u8>,
     pub download_ms: u128,
+    /// From sending the request to the first body chunk arriving, mostly network latency;
+    /// 0 for local files and batch parts
+    pub time_to_first_byte_ms: u128,
+    /// From sending the request to the last body chunk arriving; the gap after
+    /// `time_to_first_byte_ms` is down to bandwidth. 0 for local files and batch parts
+    pub time_to_last_byte_ms: u128,
+    pub connection_retries: u32,
+    pub jitter_applied_ms: u64,
+    /// Server-side durations (ms) from the `Server-Timing` response header
+    pub server_timing: Option<HashMap<String, f64>>,
+    /// Slot under `config.max_in_flight`, held until the image is saved
+    pub in_flight: Option<InFlightPermit>,
+    /// Set when `bytes` were zstd-compressed for the channel by `config.compress_channel`
+    pub compression: Option<ChannelCompression>,
+    /// MIME type from the body's signature, or failing that an `image/*` `Content-Type`
+    pub content_type: Option<String>,
+    /// Position of the URL in the download stage's input, for restoring input order later
+    pub sequence: usize,
+    /// Response headers, e.g. `ETag` or `Content-Length`; empty for local files and batch parts
+    pub headers: HeaderMap,
+    /// `bytes` came from `config.disk_cache` after the server answered 304 Not Modified
+    pub from_cache: bool,
+}
+
+impl ImageData {
+    /// Compress `bytes` in place if `config.compress_channel` is set
+    fn compress_for_channel(mut self, config: &ProcessorConfig) -> Result<Self, ProcessingError> {
+        if config.compress_channel {
+            let (bytes, stats) =
+                compress(&self.bytes).map_err(|e| ProcessingError::download(&self.url, e))?;
+            self.bytes = bytes;
+            self.compression = Some(stats);
+        }
+        Ok(self)
+    }
+}
+
+/// What a download stage did besides sending images downstream
+#[derive(Default)]
+pub struct DownloadSummary {
+    /// Multipart batch requests the server accepted
+    pub batch_download_requests: usize,
+    /// URLs requested through those batch requests
+    pub batched_urls: usize,
+    /// Downloads answered from `config.disk_cache`
+    pub cache_hits: u64,
+    /// Downloads that had to fetch the body despite `config.disk_cache` being set
+    pub cache_misses: u64,
+}
+
+/// GET `url` with `client`, opening a fresh connection up to `max_retries` times when
+/// connecting fails. With `etag`, the request is conditional and a server whose copy still
+/// matches answers 304 Not Modified. Returns the response along with the number of
+/// reconnects it took.
+pub async fn get_with_reconnect(
+    client: &reqwest::Client,
+    url: &str,
+    etag: Option<&str>,
+    max_retries: u32,
+    delay: Duration,
+) -> reqwest::Result<(reqwest::Response, u32)> {
+    let mut retries = 0;
+    loop {
+        let mut request = client.get(url);
+        if let Some(etag) = etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        match request.send().await {
+            Err(e) if e.is_connect() && retries < max_retries => {
+                retries += 1;
+                warn!(url, retries, error = %e, "connection failed, reconnecting");
+                sleep(delay).await;
+            }
+            res => return res.map(|response| (response, retries)),
+        }
+    }
 }
 
+/// Response body read chunk by chunk, with when the first and last chunks arrived
+pub struct TimedBody {
+    pub bytes: Vec<u8>,
+    pub time_to_first_byte_ms: u128,
+    pub time_to_last_byte_ms: u128,
+}
+
+/// Most of a declared `Content-Length` reserved before the body arrives, so a bogus header
+/// can't make us allocate more than this up front
+const MAX_BODY_PREALLOCATION: u64 = 1024 * 1024;
+
+/// Stream the response body, giving up as soon as it grows past `max_bytes`, or before
+/// reading anything if `Content-Length` already says it will. Chunk arrival times are
+/// measured from `request_start`; an empty body's first byte counts as its last.
+pub async fn read_body(
+    response: reqwest::Response,
+    url: &str,
+    max_bytes: Option<usize>,
+    request_start: Instant,
+) -> Result<TimedBody, ProcessingError> {
+    let content_length = response.content_length();
+    if let (Some(len), Some(max)) = (content_length, max_bytes) {
+        if len > max as u64 {
+            return Err(ProcessingError::ImageTooLarge {
+                url: url.to_string(),
+                bytes_received: 0,
+            });
+        }
+    }
+    let mut body = Vec::with_capacity(
+        content_length.map_or(0, |len| len.min(MAX_BODY_PREALLOCATION)) as usize,
+    );
+    let mut time_to_first_byte_ms = None;
+    let mut chunks = response.bytes_stream();
+    while let Some(chunk) = chunks.next().await {
+        let chunk = chunk.map_err(|e| ProcessingError::download(url, e))?;
+        time_to_first_byte_ms.get_or_insert_with(|| request_start.elapsed().as_millis());
+        body.extend_from_slice(&chunk);
+        if let Some(max) = max_bytes {
+            if body.len() > max {
+                return Err(ProcessingError::ImageTooLarge {
+                    url: url.to_string(),
+                    bytes_received: body.len(),
+                });
+            }
+        }
+    }
+    let time_to_last_byte_ms = request_start.elapsed().as_millis();
+    Ok(TimedBody {
+        bytes: body,
+        time_to_first_byte_ms: time_to_first_byte_ms.unwrap_or(time_to_last_byte_ms),
+        time_to_last_byte_ms,
+    })
+}
+
+/// Fetch a single image, applying the configured start jitter, reconnects and size cap.
+/// Downloads sharing `sem` are limited to its number of permits. Local paths are read from
+/// disk instead.
+pub async fn fetch_image(
+    url: String,
+    sem: Arc<Semaphore>,
+    config: ProcessorConfig,
+) -> Result<ImageData, ProcessingError> {
+    let jitter_applied_ms = if config.jitter_ms > 0 {
+        rand::rng().random_range(0..config.jitter_ms)
+    } else {
+        0
+    };
+    sleep(Duration::from_millis(jitter_applied_ms)).await;
+
+    let _permit = match &config.cancellation {
+        Some(token) => tokio::select! {
+            permit = acquire_permit(&sem, &config) => permit,
+            _ = token.cancelled() => return Err(ProcessingError::Cancelled { url }),
+        },
+        None => acquire_permit(&sem, &config).await,
+    };
+    if let InputSource::LocalFile(path) = InputSource::parse(&url) {
+        return read_local_file(url, &path, jitter_applied_ms, &config).await;
+    }
+    // Taken while holding the permit so request starts stay spaced out
+    if let Some(limiter) = &config.rate_limiter {
+        limiter.acquire().await;
+    }
+    let client = config
+        .client()
+        .map_err(|e| ProcessingError::download(&url, e))?;
+    let cached = config.disk_cache.as_ref().and_then(|cache| cache.get(&url));
+    debug!(url = %url, cached = cached.is_some(), "downloading");
+    let start_time = Instant::now();
+    let ((response, connection_retries), _) = download_with_retry(
+        &url,
+        config.download_retries,
+        config.download_retry_base_delay_ms,
+        || async {
+            let (response, reconnects) = get_with_reconnect(
+                &client,
+                &url,
+                cached.as_ref().map(|(_, etag)| etag.as_str()),
+                config.connect_retries,
+                config.connect_retry_delay,
+            )
+            .await?;
+            Ok((response.error_for_status()?, reconnects))
+        },
+    )
+    .await
+    .map_err(|e| ProcessingError::download(&url, e))?;
+    let server_timing = server_timing(&response);
+    let headers = response.headers().clone();
+    if let Some((bytes, _)) = cached.filter(|_| response.status() == StatusCode::NOT_MODIFIED) {
+        debug!(url = %url, "not modified, using cached copy");
+        let content_type = verify_magic_bytes(&url, &bytes, &config)?;
+        let elapsed_ms = start_time.elapsed().as_millis();
+        return Ok(ImageData {
+            download_ms: elapsed_ms,
+            time_to_first_byte_ms: elapsed_ms,
+            time_to_last_byte_ms: elapsed_ms,
+            url,
+            bytes,
+            connection_retries,
+            jitter_applied_ms,
+            server_timing,
+            in_flight: None,
+            compression: None,
+            content_type,
+            sequence: 0,
+            headers,
+            from_cache: true,
+        });
+    }
+    let header = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    let header_type = header
+        .as_deref()
+        .and_then(|value| value.split(';').next())
+        .map(|mime| mime.trim().to_ascii_lowercase())
+        .filter(|mime| mime.starts_with("image/"));
+    if config.verify_content_type && header_type.is_none() {
+        return Err(ProcessingError::NotAnImage {
+            reason: format!("content type {}", header.as_deref().unwrap_or("missing")),
+            url,
+        });
+    }
+    let body = read_body(response, &url, config.max_image_bytes, start_time).await?;
+    let content_type = verify_magic_bytes(&url, &body.bytes, &config)?.or(header_type);
+    let etag = headers.get(ETAG).and_then(|value| value.to_str().ok());
+    if let (Some(cache), Some(etag)) = (&config.disk_cache, etag) {
+        if let Err(e) = cache.put(&url, &body.bytes, etag) {
+            warn!(url = %url, error = %e, "failed to cache download");
+        }
+    }
+
+    Ok(ImageData {
+        download_ms: start_time.elapsed().as_millis(),
+        time_to_first_byte_ms: body.time_to_first_byte_ms,
+        time_to_last_byte_ms: body.time_to_last_byte_ms,
+        url,
+        bytes: body.bytes,
+        connection_retries,
+        jitter_applied_ms,
+        server_timing,
+        in_flight: None,
+        compression: None,
+        content_type,
+        sequence: 0,
+        headers,
+        from_cache: false,
+    })
+}
+
+/// [`fetch_image`] for a file on disk, with the same size cap and signature check
+async fn read_local_file(
+    url: String,
+    path: &Path,
+    jitter_applied_ms: u64,
+    config: &ProcessorConfig,
+) -> Result<ImageData, ProcessingError> {
+    let start_time = Instant::now();
+    let bytes = tokio::fs::read(path)
+        .await
+        .map_err(|e| ProcessingError::download(&url, e))?;
+    if config.max_image_bytes.is_some_and(|max| bytes.len() > max) {
+        return Err(ProcessingError::ImageTooLarge {
+            bytes_received: bytes.len(),
+            url,
+        });
+    }
+    // With no Content-Type to go on, the signature has to stand in for it
+    let content_type = verify_magic_bytes(&url, &bytes, config)?;
+    if config.verify_content_type && content_type.is_none() {
+        return Err(ProcessingError::NotAnImage {
+            reason: "no JPEG or PNG signature".to_string(),
+            url,
+        });
+    }
+
+    Ok(ImageData {
+        download_ms: start_time.elapsed().as_millis(),
+        time_to_first_byte_ms: 0,
+        time_to_last_byte_ms: 0,
+        url,
+        bytes,
+        connection_retries: 0,
+        jitter_applied_ms,
+        server_timing: None,
+        in_flight: None,
+        compression: None,
+        content_type,
+        sequence: 0,
+        headers: HeaderMap::new(),
+        from_cache: false,
+    })
+}
+
+/// Wait out any `config.memory_pause`, then take a download permit from `sem`
+async fn acquire_permit<'a>(sem: &'a Semaphore, config: &ProcessorConfig) -> SemaphorePermit<'a> {
+    if let Some(pause) = &config.memory_pause {
+        // Only errs once the sender is gone, after which nothing can pause downloads
+        let _ = pause.clone().wait_for(|paused| !paused).await;
+    }
+    sem.acquire().await.unwrap()
+}
+
+/// MIME type of a JPEG or PNG body, judged by its leading bytes
+fn sniff_image_type(bytes: &[u8]) -> Option<&'static str> {
+    if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some("image/jpeg")
+    } else if bytes.starts_with(&[0x89, 0x50, 0x4E, 0x47]) {
+        Some("image/png")
+    } else {
+        None
+    }
+}
+
+/// MIME type sniffed from `bytes`, rejecting unrecognised bodies when
+/// `config.verify_magic_bytes` is set
+fn verify_magic_bytes(
+    url: &str,
+    bytes: &[u8],
+    config: &ProcessorConfig,
+) -> Result<Option<String>, ProcessingError> {
+    match sniff_image_type(bytes) {
+        Some(mime) => Ok(Some(mime.to_string())),
+        None if config.verify_magic_bytes => Err(ProcessingError::NotAnImage {
+            url: url.to_string(),
+            reason: "no JPEG or PNG signature".to_string(),
+        }),
+        None => Ok(None),
+    }
+}
+
+/// Download every URL into `output`. Images that can't be fetched are pushed to
+/// `dead_letters` rather than failing the stage. Nothing downstream limits images in flight.
+/// A `Vec<String>` can be passed through [`futures::stream::iter`].
 pub async fn download_stage(
-    urls: Vec<String>,
+    urls: impl Stream<Item = String>,
     output: mpsc::Sender<ImageData>,
     concurrency: usize,
-) -> Result<()> {
-    let total = urls.len();
+    config: &ProcessorConfig,
+    dead_letters: &DeadLetterQueue,
+) -> Result<DownloadSummary> {
+    download_stage_with_limit(
+        urls,
+        output,
+        concurrency,
+        config,
+        dead_letters,
+        &InFlightLimiter::default(),
+    )
+    .await
+}
+
+/// Like [`download_stage`], but every image sent to `output` first takes a permit from
+/// `in_flight`, waiting while the pipeline is full.
+///
+/// `urls` is pulled a window at a time, `concurrency` URLs or `concurrency` batch requests'
+/// worth, and only topped up as downloads finish, so a long stream is never held at once.
+///
+/// With `config.batch_download` set, each window is first requested in multipart batches;
+/// anything the server doesn't return, or every URL if it doesn't support batching, is
+/// fetched per URL.
+///
+/// Each URL's download gets its own span under the stage's.
+#[instrument(name = "download_stage", skip_all, fields(concurrency))]
+pub async fn download_stage_with_limit(
+    urls: impl Stream<Item = String>,
+    output: mpsc::Sender<ImageData>,
+    concurrency: usize,
+    config: &ProcessorConfig,
+    dead_letters: &DeadLetterQueue,
+    in_flight: &InFlightLimiter,
+) -> Result<DownloadSummary> {
+    let config = &config.with_shared_client()?;
+    let window = match &config.batch_download {
+        Some(batch) => concurrency.max(1) * batch.max_urls_per_request.max(1),
+        None => concurrency.max(1),
+    };
+    // Numbered before anything is filtered out, so sequences always match input positions
+    let urls = urls
+        .enumerate()
+        .filter(|(_, url)| {
+            let allowed = match &config.preflight_check {
+                Some(check) if !check.allows(url) => {
+                    debug!(url = %url, "rejected by preflight check");
+                    dead_letters.push(ProcessingError::PreflightRejected { url: url.clone() });
+                    false
+                }
+                _ => true,
+            };
+            future::ready(allowed)
+        })
+        .chunks(window);
+    let mut urls = pin!(urls);
     let sem = Arc::new(Semaphore::new(concurrency));
-    let mut handles = vec![];
+    let mut summary = DownloadSummary::default();
+    let mut downloads = FuturesUnordered::new();
+    let mut exhausted = false;
+    let mut total = 0;
 
-    info!(total, concurrency, "download stage started");
+    info!(concurrency, "download stage started");
 
-    for u in urls {
-        let sem_clone = Arc::clone(&sem);
-        let output_clone = output.clone();
+    loop {
+        // Once cancelled, or once nothing is receiving, URLs still in the stream are never
+        // pulled, let alone downloaded
+        if let Some(token) = &config.cancellation {
+            exhausted |= token.is_cancelled();
+        }
+        exhausted |= output.is_closed();
+        while !exhausted && downloads.len() < window {
+            let Some(chunk) = urls.next().await else {
+                exhausted = true;
+                break;
+            };
+            let chunk = download_batches(
+                chunk,
+                config,
+                &sem,
+                &output,
+                dead_letters,
+                in_flight,
+                &mut summary,
+            )
+            .await;
+            total += chunk.len();
+            downloads.extend(chunk.into_iter().map(|(sequence, url)| {
+                spawn_download(
+                    sequence,
+                    url,
+                    &sem,
+                    &output,
+                    config,
+                    dead_letters,
+                    in_flight,
+                )
+            }));
+        }
 
-        let handle = spawn(async move {
-            let _permit = sem_clone.acquire().await.unwrap();
-            debug!(url = %u, "downloading");
-            let start_time = Instant::now();
-            let img_bytes = reqwest::get(&u)
-                .await
-                .unwrap()
-                .bytes()
-                .await
-                .unwrap()
-                .to_vec();
-            let download_time = start_time.elapsed().as_millis();
-
-            output_clone
-                .send(ImageData {
-                    url: u,
-                    bytes: img_bytes,
-                    download_ms: download_time,
-                })
+        let Some(download) = downloads.next().await else {
+            break;
+        };
+        match download? {
+            Some(true) => summary.cache_hits += 1,
+            Some(false) if config.disk_cache.is_some() => summary.cache_misses += 1,
+            _ => {}
+        }
+    }
+    info!(total, "download stage complete");
+    Ok(summary)
+}
+
+/// Fetch `url` in its own task and send it to `output` tagged with `sequence`. Resolves to
+/// whether it came from `config.disk_cache`, or `None` if it was cancelled, rejected or
+/// `output` has closed.
+fn spawn_download(
+    sequence: usize,
+    url: String,
+    sem: &Arc<Semaphore>,
+    output: &mpsc::Sender<ImageData>,
+    config: &ProcessorConfig,
+    dead_letters: &DeadLetterQueue,
+    in_flight: &InFlightLimiter,
+) -> JoinHandle<Option<bool>> {
+    let sem = Arc::clone(sem);
+    let output = output.clone();
+    let config = config.clone();
+    let dead_letters = dead_letters.clone();
+    let in_flight = in_flight.clone();
+    let span = info_span!("download_image", url = %url);
+
+    spawn(
+        async move {
+            let res = fetch_image(url, sem, config.clone())
                 .await
-                .unwrap();
-        });
+                .and_then(|data| data.compress_for_channel(&config));
+            match res {
+                Ok(mut data) => {
+                    debug!(download_ms = data.download_ms as u64, "downloaded");
+                    let from_cache = data.from_cache;
+                    data.sequence = sequence;
+                    data.in_flight = Some(in_flight.acquire().await);
+                    if output.send(data).await.is_err() {
+                        debug!("output closed, dropping download");
+                        return None;
+                    }
+                    Some(from_cache)
+                }
+                Err(ProcessingError::Cancelled { url }) => {
+                    debug!(url = %url, "download cancelled");
+                    None
+                }
+                Err(e) => {
+                    warn!(error = %e, "download rejected");
+                    dead_letters.push(e);
+                    None
+                }
+            }
+        }
+        .instrument(span),
+    )
+}
 
-        handles.push(handle);
-    }
+/// Request `urls` through `config.batch_download`, sending every returned image to `output`.
+/// Returns the URLs that still need an individual GET, which is all of them when batching
+/// isn't configured, along with any local paths.
+async fn download_batches(
+    urls: Vec<(usize, String)>,
+    config: &ProcessorConfig,
+    sem: &Semaphore,
+    output: &mpsc::Sender<ImageData>,
+    dead_letters: &DeadLetterQueue,
+    in_flight: &InFlightLimiter,
+    summary: &mut DownloadSummary,
+) -> Vec<(usize, String)> {
+    let Some(batch) = &config.batch_download else {
+        return urls;
+    };
+    let (urls, local): (Vec<_>, Vec<_>) = urls
+        .into_iter()
+        .partition(|(_, url)| matches!(InputSource::parse(url), InputSource::Http(_)));
+    let client = match config.client() {
+        Ok(client) => client,
+        Err(e) => {
+            warn!(error = %e, "failed to build client, downloading per URL");
+            return [urls, local].concat();
+        }
+    };
+    let requests = urls.chunks(batch.max_urls_per_request.max(1)).map(|chunk| {
+        let client = &client;
+        async move {
+            let _permit = acquire_permit(sem, config).await;
+            let start_time = Instant::now();
+            let chunk_urls: Vec<String> = chunk.iter().map(|(_, url)| url.clone()).collect();
+            let res = fetch_batch(client, &batch.endpoint, &chunk_urls).await;
+            (chunk, res, start_time.elapsed().as_millis())
+        }
+    });
 
-    join_all(handles).await;
-    info!(total, "download stage complete");
+    let mut fallback = vec![];
+    for (chunk, res, download_ms) in join_all(requests).await {
+        let parts = match res {
+            Ok(Some(parts)) => parts,
+            Ok(None) => {
+                fallback.extend_from_slice(chunk);
+                continue;
+            }
+            Err(e) => {
+                warn!(error = %e, urls = chunk.len(), "batch download failed");
+                for (_, url) in chunk {
+                    dead_letters.push(ProcessingError::download(url, &e));
+                }
+                continue;
+            }
+        };
+
+        summary.batch_download_requests += 1;
+        summary.batched_urls += chunk.len();
+        let mut missing: Vec<&(usize, String)> = chunk.iter().collect();
+        for part in parts {
+            // fetch_batch only returns parts for URLs in the chunk, each at most once
+            let Some(index) = missing.iter().position(|(_, url)| *url == part.url) else {
+                continue;
+            };
+            let (sequence, _) = *missing.remove(index);
+            // Parts carry no Content-Type of their own, so only the signature is checked
+            let content_type = match verify_magic_bytes(&part.url, &part.bytes, config) {
+                Ok(content_type) => content_type,
+                Err(e) => {
+                    dead_letters.push(e);
+                    continue;
+                }
+            };
+            let data = ImageData {
+                url: part.url,
+                bytes: part.bytes,
+                download_ms,
+                time_to_first_byte_ms: 0,
+                time_to_last_byte_ms: 0,
+                connection_retries: 0,
+                jitter_applied_ms: 0,
+                server_timing: None,
+                in_flight: None,
+                compression: None,
+                content_type,
+                sequence,
+                headers: HeaderMap::new(),
+                from_cache: false,
+            };
+            match data.compress_for_channel(config) {
+                Ok(mut data) => {
+                    data.in_flight = Some(in_flight.acquire().await);
+                    if output.send(data).await.is_err() {
+                        debug!("output closed, skipping the remaining batches");
+                        return vec![];
+                    }
+                }
+                Err(e) => dead_letters.push(e),
+            }
+        }
+        fallback.extend(missing.into_iter().cloned());
+    }
 
-    Ok(())
+    if !fallback.is_empty() {
+        info!(urls = fallback.len(), "falling back to per-URL downloads");
+    }
+    fallback.extend(local);
+    fallback
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{
+        cache::DiskCache,
+        config::{BatchDownloadConfig, PreflightCheck},
+        test_support::jpeg_bytes,
+    };
+    use futures::stream;
+    use std::sync::Mutex;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        sync::watch,
+    };
+    use tokio_util::sync::CancellationToken;
+    use wiremock::{
+        matchers::{any, header, method, path},
+        Mock, MockServer, Request, ResponseTemplate,
+    };
 
     #[tokio::test]
     async fn downloads_images() {
@@ -75,15 +674,673 @@ mod tests {
         let (tx, mut rx) = mpsc::channel(10);
 
         tokio::spawn(async move {
-            download_stage(urls, tx, 2).await.unwrap();
+            download_stage(
+                stream::iter(urls),
+                tx,
+                2,
+                &ProcessorConfig::default(),
+                &DeadLetterQueue::new(),
+            )
+            .await
+            .unwrap();
         });
 
         let mut count = 0;
         while let Some(data) = rx.recv().await {
-            assert!(data.bytes.len() > 0);
+            assert!(!data.bytes.is_empty());
             count += 1;
         }
 
         assert_eq!(count, 2);
     }
+
+    #[tokio::test]
+    async fn staggers_download_starts() {
+        // When each request actually reaches the server, not the jitter the stage reports
+        let arrivals = Arc::new(Mutex::new(vec![]));
+        let server = MockServer::start().await;
+        let recorded = Arc::clone(&arrivals);
+        Mock::given(any())
+            .respond_with(move |_: &Request| {
+                recorded.lock().unwrap().push(std::time::Instant::now());
+                ResponseTemplate::new(200).set_body_bytes(vec![0u8; 16])
+            })
+            .mount(&server)
+            .await;
+
+        let urls: Vec<String> = (0..50).map(|i| format!("{}/{}", server.uri(), i)).collect();
+        let config = ProcessorConfig {
+            jitter_ms: 100,
+            ..Default::default()
+        };
+        let (tx, mut rx) = mpsc::channel(50);
+        download_stage(stream::iter(urls), tx, 50, &config, &DeadLetterQueue::new())
+            .await
+            .unwrap();
+        while let Some(data) = rx.recv().await {
+            assert!(data.jitter_applied_ms < 100);
+        }
+
+        let arrivals = arrivals.lock().unwrap();
+        assert_eq!(arrivals.len(), 50);
+        let first = *arrivals.iter().min().unwrap();
+        let offsets: Vec<f64> = arrivals
+            .iter()
+            .map(|arrival| (*arrival - first).as_secs_f64() * 1000.0)
+            .collect();
+        // Unstaggered, all 50 arrive within a few milliseconds of each other
+        let spread = offsets.iter().cloned().fold(0.0, f64::max);
+        assert!(spread >= 50.0, "arrivals spread over {:.1}ms", spread);
+        let mean = offsets.iter().sum::<f64>() / offsets.len() as f64;
+        let variance =
+            offsets.iter().map(|o| (o - mean).powi(2)).sum::<f64>() / offsets.len() as f64;
+        assert!(variance.sqrt() >= 15.0);
+    }
+
+    #[tokio::test]
+    async fn reads_local_files() {
+        let path = Path::new("test_fetch_local.jpg");
+        std::fs::write(path, jpeg_bytes(8, 8)).unwrap();
+
+        let sem = Arc::new(Semaphore::new(1));
+        let url = path.to_string_lossy().into_owned();
+        let data = fetch_image(url.clone(), Arc::clone(&sem), ProcessorConfig::default())
+            .await
+            .unwrap();
+        assert_eq!(data.url, url);
+        assert_eq!(data.bytes, jpeg_bytes(8, 8));
+        assert_eq!(data.content_type.as_deref(), Some("image/jpeg"));
+
+        let capped = ProcessorConfig {
+            max_image_bytes: Some(16),
+            ..Default::default()
+        };
+        let too_large = fetch_image(format!("file://{}", url), Arc::clone(&sem), capped).await;
+        assert!(matches!(
+            too_large,
+            Err(ProcessingError::ImageTooLarge { .. })
+        ));
+
+        std::fs::remove_file(path).unwrap();
+        let missing = fetch_image(url, sem, ProcessorConfig::default()).await;
+        assert!(matches!(missing, Err(ProcessingError::Download { .. })));
+    }
+
+    #[tokio::test]
+    async fn records_server_timing() {
+        let server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("Server-Timing", "cdn;dur=10.5, origin;dur=45.2")
+                    .set_body_bytes(vec![0u8; 16]),
+            )
+            .mount(&server)
+            .await;
+
+        let data = fetch_image(
+            server.uri(),
+            Arc::new(Semaphore::new(1)),
+            ProcessorConfig::default(),
+        )
+        .await
+        .unwrap();
+
+        let timing = data.server_timing.unwrap();
+        assert_eq!(timing["cdn"], 10.5);
+        assert_eq!(timing["origin"], 45.2);
+    }
+
+    #[tokio::test]
+    async fn times_first_and_last_byte() {
+        // Half the body straight after the headers, the rest once the delay is up
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = [0; 1024];
+            let _ = socket.read(&mut request).await;
+            let head = b"HTTP/1.1 200 OK\r\nContent-Length: 4\r\n\r\nab";
+            socket.write_all(head).await.unwrap();
+            socket.flush().await.unwrap();
+            sleep(Duration::from_millis(200)).await;
+            socket.write_all(b"cd").await.unwrap();
+        });
+
+        let data = fetch_image(url, Arc::new(Semaphore::new(1)), ProcessorConfig::default())
+            .await
+            .unwrap();
+
+        assert_eq!(data.bytes, b"abcd");
+        assert!(data.time_to_first_byte_ms < 150);
+        assert!(data.time_to_last_byte_ms >= 200);
+        assert!(data.time_to_last_byte_ms <= data.download_ms);
+    }
+
+    #[tokio::test]
+    async fn distrusts_content_length() {
+        // Claims a petabyte-sized body, then sends two bytes and hangs up
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            for _ in 0..2 {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = [0; 1024];
+                let _ = socket.read(&mut request).await;
+                let head = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\nab",
+                    1u64 << 50
+                );
+                socket.write_all(head.as_bytes()).await.unwrap();
+            }
+        });
+
+        let sem = Arc::new(Semaphore::new(1));
+        let unlimited = fetch_image(url.clone(), Arc::clone(&sem), ProcessorConfig::default());
+        assert!(matches!(
+            unlimited.await,
+            Err(ProcessingError::Download { .. })
+        ));
+
+        let capped = ProcessorConfig {
+            max_image_bytes: Some(1_000),
+            ..Default::default()
+        };
+        assert!(matches!(
+            fetch_image(url, sem, capped).await,
+            Err(ProcessingError::ImageTooLarge {
+                bytes_received: 0,
+                ..
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn revalidates_cached_downloads() {
+        let server = MockServer::start().await;
+        Mock::given(header("If-None-Match", "\"v1\""))
+            .respond_with(ResponseTemplate::new(304).insert_header("ETag", "\"v1\""))
+            .mount(&server)
+            .await;
+        Mock::given(any())
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("ETag", "\"v1\"")
+                    .set_body_bytes(b"image".to_vec()),
+            )
+            .mount(&server)
+            .await;
+
+        let dir = tempfile::tempdir().unwrap();
+        let cache = DiskCache::new(dir.path().join("cache"));
+        let config = ProcessorConfig {
+            disk_cache: Some(cache.clone()),
+            ..Default::default()
+        };
+        let run = || async {
+            let (tx, mut rx) = mpsc::channel(1);
+            let summary = download_stage(
+                stream::iter([server.uri()]),
+                tx,
+                1,
+                &config,
+                &DeadLetterQueue::new(),
+            )
+            .await
+            .unwrap();
+            let data = rx.recv().await.unwrap();
+            (summary, data)
+        };
+
+        let (summary, data) = run().await;
+        assert_eq!((summary.cache_hits, summary.cache_misses), (0, 1));
+        assert!(!data.from_cache);
+        assert_eq!(cache.get(&server.uri()).unwrap().1, "\"v1\"");
+
+        let (summary, data) = run().await;
+        assert_eq!((summary.cache_hits, summary.cache_misses), (1, 0));
+        assert!(data.from_cache);
+        assert_eq!(data.bytes, b"image");
+    }
+
+    #[tokio::test]
+    async fn keeps_response_headers() {
+        let server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("ETag", "\"abc123\"")
+                    .insert_header("X-Image-ID", "42")
+                    .set_body_bytes(vec![0u8; 16]),
+            )
+            .mount(&server)
+            .await;
+
+        let data = fetch_image(
+            server.uri(),
+            Arc::new(Semaphore::new(1)),
+            ProcessorConfig::default(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(data.headers[reqwest::header::ETAG], "\"abc123\"");
+        assert_eq!(data.headers["x-image-id"], "42");
+    }
+
+    #[tokio::test]
+    async fn rejects_urls_failing_preflight() {
+        let server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(vec![0u8; 16]))
+            .mount(&server)
+            .await;
+
+        let urls = vec![
+            format!("{}/1", server.uri()),
+            "http://bad.example.com/2".to_string(),
+            "http://cdn.bad-host.test/3".to_string(),
+        ];
+        let config = ProcessorConfig {
+            preflight_check: Some(PreflightCheck::new(|url| {
+                reqwest::Url::parse(url)
+                    .ok()
+                    .and_then(|url| url.host_str().map(|host| !host.contains("bad")))
+                    .unwrap_or(false)
+            })),
+            ..Default::default()
+        };
+        let dead_letters = DeadLetterQueue::new();
+        let (tx, mut rx) = mpsc::channel(3);
+        download_stage(stream::iter(urls), tx, 2, &config, &dead_letters)
+            .await
+            .unwrap();
+
+        assert!(rx.recv().await.is_some());
+        assert!(rx.recv().await.is_none());
+        assert_eq!(
+            dead_letters.count(|e| matches!(e, ProcessingError::PreflightRejected { .. })),
+            2
+        );
+        assert_eq!(server.received_requests().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn downloads_in_multipart_batches() {
+        let server = MockServer::start().await;
+        let urls: Vec<String> = (0..3)
+            .map(|i| format!("{}/img/{}", server.uri(), i))
+            .collect();
+        let body = format!(
+            "--b\r\nContent-Location: {}\r\n\r\nfirst\r\n--b\r\nContent-Location: {}\r\n\r\nsecond\r\n--b--\r\n",
+            urls[0], urls[1]
+        );
+        Mock::given(method("POST"))
+            .and(path("/batch"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_raw(body, "multipart/mixed; boundary=b"),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(vec![0u8; 16]))
+            .mount(&server)
+            .await;
+
+        let config = ProcessorConfig {
+            batch_download: Some(BatchDownloadConfig {
+                endpoint: format!("{}/batch", server.uri()),
+                max_urls_per_request: 3,
+            }),
+            ..Default::default()
+        };
+        let (tx, mut rx) = mpsc::channel(3);
+        let summary = download_stage(stream::iter(urls), tx, 2, &config, &DeadLetterQueue::new())
+            .await
+            .unwrap();
+
+        let mut received = vec![];
+        while let Some(data) = rx.recv().await {
+            received.push(data.bytes);
+        }
+        assert_eq!(received.len(), 3);
+        assert!(received.contains(&b"first".to_vec()));
+        assert_eq!(summary.batch_download_requests, 1);
+        assert_eq!(summary.batched_urls, 3);
+        // The URL missing from the batch response is fetched on its own
+        let requests = server.received_requests().await.unwrap();
+        assert_eq!(
+            requests
+                .iter()
+                .filter(|r| r.method.as_str() == "GET")
+                .count(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn falls_back_when_batching_unsupported() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(405))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(vec![0u8; 16]))
+            .mount(&server)
+            .await;
+
+        let urls: Vec<String> = (0..4).map(|i| format!("{}/{}", server.uri(), i)).collect();
+        let config = ProcessorConfig {
+            batch_download: Some(BatchDownloadConfig {
+                endpoint: format!("{}/batch", server.uri()),
+                max_urls_per_request: 2,
+            }),
+            ..Default::default()
+        };
+        let (tx, mut rx) = mpsc::channel(4);
+        let summary = download_stage(stream::iter(urls), tx, 2, &config, &DeadLetterQueue::new())
+            .await
+            .unwrap();
+
+        let mut sent = 0;
+        while rx.recv().await.is_some() {
+            sent += 1;
+        }
+        assert_eq!(sent, 4);
+        assert_eq!(summary.batch_download_requests, 0);
+    }
+
+    #[tokio::test]
+    async fn limits_images_in_flight() {
+        let server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(vec![0u8; 16]))
+            .mount(&server)
+            .await;
+
+        let urls: Vec<String> = (0..6).map(|i| format!("{}/{}", server.uri(), i)).collect();
+        let in_flight = InFlightLimiter::new(Some(2));
+        let (tx, mut rx) = mpsc::channel(6);
+        let consumer = spawn(async move {
+            let mut received = 0;
+            while let Some(data) = rx.recv().await {
+                // Hold the image as a slow save stage would
+                sleep(Duration::from_millis(20)).await;
+                drop(data);
+                received += 1;
+            }
+            received
+        });
+
+        download_stage_with_limit(
+            stream::iter(urls),
+            tx,
+            6,
+            &ProcessorConfig::default(),
+            &DeadLetterQueue::new(),
+            &in_flight,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(consumer.await.unwrap(), 6);
+        assert_eq!(in_flight.max_observed(), 2);
+    }
+
+    #[tokio::test]
+    async fn stops_downloading_when_cancelled() {
+        let server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_millis(200)))
+            .mount(&server)
+            .await;
+
+        let token = CancellationToken::new();
+        let config = ProcessorConfig {
+            cancellation: Some(token.clone()),
+            ..Default::default()
+        };
+        let urls: Vec<String> = (0..5).map(|i| format!("{}/{}", server.uri(), i)).collect();
+        let dead_letters = DeadLetterQueue::new();
+        let (tx, mut rx) = mpsc::channel(5);
+        let cancel = tokio::spawn(async move {
+            sleep(Duration::from_millis(50)).await;
+            token.cancel();
+        });
+        download_stage(stream::iter(urls), tx, 1, &config, &dead_letters)
+            .await
+            .unwrap();
+        cancel.await.unwrap();
+
+        // Only the download already in flight when cancelled finishes
+        let mut received = 0;
+        while rx.recv().await.is_some() {
+            received += 1;
+        }
+        assert_eq!(received, 1);
+        assert!(dead_letters.errors().is_empty());
+    }
+
+    #[tokio::test]
+    async fn stops_downloading_when_output_closes() {
+        let server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(jpeg_bytes(8, 8)))
+            .mount(&server)
+            .await;
+
+        let urls: Vec<String> = (0..5).map(|i| format!("{}/{}", server.uri(), i)).collect();
+        let dead_letters = DeadLetterQueue::new();
+        let (tx, mut rx) = mpsc::channel(1);
+        // Takes one image, then goes away like a failed process stage
+        let receiver = tokio::spawn(async move { rx.recv().await.is_some() });
+        download_stage(
+            stream::iter(urls),
+            tx,
+            1,
+            &ProcessorConfig::default(),
+            &dead_letters,
+        )
+        .await
+        .unwrap();
+
+        assert!(receiver.await.unwrap());
+        assert!(dead_letters.is_empty());
+        assert!(server.received_requests().await.unwrap().len() < 5);
+    }
+
+    #[tokio::test]
+    async fn compresses_channel_payloads() {
+        let server = MockServer::start().await;
+        let body = vec![7u8; 10_000];
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(body.clone()))
+            .mount(&server)
+            .await;
+
+        let config = ProcessorConfig {
+            compress_channel: true,
+            ..Default::default()
+        };
+        let (tx, mut rx) = mpsc::channel(1);
+        download_stage(
+            stream::iter([server.uri()]),
+            tx,
+            1,
+            &config,
+            &DeadLetterQueue::new(),
+        )
+        .await
+        .unwrap();
+
+        let data = rx.recv().await.unwrap();
+        let stats = data.compression.unwrap();
+        assert_eq!(stats.original_len, body.len());
+        assert!(data.bytes.len() < body.len());
+        assert_eq!(
+            crate::compression::decompress(&data.bytes, &stats).unwrap(),
+            body
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_oversized_images() {
+        let server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(vec![0u8; 10_000]))
+            .mount(&server)
+            .await;
+
+        let config = ProcessorConfig {
+            max_image_bytes: Some(1_000),
+            ..Default::default()
+        };
+        let dead_letters = DeadLetterQueue::new();
+        let (tx, mut rx) = mpsc::channel(1);
+        download_stage(stream::iter([server.uri()]), tx, 1, &config, &dead_letters)
+            .await
+            .unwrap();
+
+        assert!(rx.recv().await.is_none());
+        match dead_letters.errors().as_slice() {
+            // Turned away on its Content-Length, before any of the body was read
+            [ProcessingError::ImageTooLarge { bytes_received, .. }] => {
+                assert_eq!(*bytes_received, 0)
+            }
+            other => panic!("unexpected dead letters: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn rate_limits_downloads() {
+        let server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(jpeg_bytes(8, 8)))
+            .mount(&server)
+            .await;
+
+        let config = ProcessorConfig {
+            rate_limit: Some(20.0),
+            ..Default::default()
+        };
+        let urls: Vec<String> = (0..4).map(|i| format!("{}/{}", server.uri(), i)).collect();
+        let (tx, mut rx) = mpsc::channel(4);
+        let start = Instant::now();
+        download_stage(stream::iter(urls), tx, 4, &config, &DeadLetterQueue::new())
+            .await
+            .unwrap();
+
+        // The first request starts immediately, each later one 50ms after the last
+        assert!(start.elapsed() >= Duration::from_millis(150));
+        let mut received = 0;
+        while rx.recv().await.is_some() {
+            received += 1;
+        }
+        assert_eq!(received, 4);
+    }
+
+    #[tokio::test]
+    async fn pauses_while_memory_is_over_limit() {
+        let server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(jpeg_bytes(8, 8)))
+            .mount(&server)
+            .await;
+
+        let (pause_tx, pause_rx) = watch::channel(true);
+        let config = ProcessorConfig {
+            memory_pause: Some(pause_rx),
+            ..Default::default()
+        };
+        let urls: Vec<String> = (0..3).map(|i| format!("{}/{}", server.uri(), i)).collect();
+        let (tx, mut rx) = mpsc::channel(3);
+        let stage = tokio::spawn(async move {
+            download_stage(stream::iter(urls), tx, 3, &config, &DeadLetterQueue::new()).await
+        });
+
+        sleep(Duration::from_millis(100)).await;
+        assert!(rx.try_recv().is_err());
+        assert!(server.received_requests().await.unwrap().is_empty());
+
+        pause_tx.send(false).unwrap();
+        stage.await.unwrap().unwrap();
+        let mut received = 0;
+        while rx.recv().await.is_some() {
+            received += 1;
+        }
+        assert_eq!(received, 3);
+    }
+
+    #[tokio::test]
+    async fn rejects_non_image_content() {
+        let server = MockServer::start().await;
+        Mock::given(path("/page"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw("<html></html>", "text/html"))
+            .mount(&server)
+            .await;
+        Mock::given(path("/fake.jpg"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw("not a jpeg", "image/jpeg"))
+            .mount(&server)
+            .await;
+        Mock::given(path("/real.jpg"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(jpeg_bytes(8, 8), "image/jpeg"))
+            .mount(&server)
+            .await;
+
+        let config = ProcessorConfig {
+            verify_content_type: true,
+            verify_magic_bytes: true,
+            ..Default::default()
+        };
+        let dead_letters = DeadLetterQueue::new();
+        let (tx, mut rx) = mpsc::channel(3);
+        let urls: Vec<String> = ["page", "fake.jpg", "real.jpg"]
+            .iter()
+            .map(|name| format!("{}/{}", server.uri(), name))
+            .collect();
+        download_stage(stream::iter(urls), tx, 3, &config, &dead_letters)
+            .await
+            .unwrap();
+
+        let data = rx.recv().await.unwrap();
+        assert!(data.url.ends_with("/real.jpg"));
+        assert_eq!(data.content_type.as_deref(), Some("image/jpeg"));
+        assert!(rx.recv().await.is_none());
+        assert_eq!(
+            dead_letters.count(|e| matches!(e, ProcessingError::NotAnImage { .. })),
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn reconnects_after_connect_error() {
+        // Reserve a port, then leave it closed so the first attempt is refused
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+
+        let server = spawn(async move {
+            sleep(Duration::from_millis(150)).await;
+            let listener = std::net::TcpListener::bind(addr).unwrap();
+            let server = MockServer::builder().listener(listener).start().await;
+            Mock::given(any())
+                .respond_with(ResponseTemplate::new(200))
+                .mount(&server)
+                .await;
+            server
+        });
+
+        let url = format!("http://{}/image.jpg", addr);
+        let client = reqwest::Client::new();
+        let (response, retries) =
+            get_with_reconnect(&client, &url, None, 20, Duration::from_millis(50))
+                .await
+                .unwrap();
+
+        assert!(response.status().is_success());
+        assert!(retries >= 1);
+        drop(server.await.unwrap());
+    }
 }
@@ -0,0 +1,58 @@
+// src/streaming/in_flight.rs
+
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// End-to-end cap on images between download and save. A permit is taken when an image
+/// leaves the download stage and travels with it until it is dropped after saving.
+/// Clones share the same limit.
+#[derive(Clone, Default)]
+pub struct InFlightLimiter {
+    sem: Option<Arc<Semaphore>>,
+    in_flight: Arc<AtomicUsize>,
+    max_observed: Arc<AtomicUsize>,
+}
+
+impl InFlightLimiter {
+    /// `None` leaves the pipeline unbounded but still tracks the peak
+    pub fn new(limit: Option<usize>) -> Self {
+        Self {
+            sem: limit.map(|limit| Arc::new(Semaphore::new(limit.max(1)))),
+            ..Default::default()
+        }
+    }
+
+    pub async fn acquire(&self) -> InFlightPermit {
+        let permit = match &self.sem {
+            Some(sem) => Some(Arc::clone(sem).acquire_owned().await.unwrap()),
+            None => None,
+        };
+        let in_flight = self.in_flight.fetch_add(1, Ordering::Relaxed) + 1;
+        self.max_observed.fetch_max(in_flight, Ordering::Relaxed);
+        InFlightPermit {
+            _permit: permit,
+            in_flight: Arc::clone(&self.in_flight),
+        }
+    }
+
+    /// Most images that were in flight at once
+    pub fn max_observed(&self) -> usize {
+        self.max_observed.load(Ordering::Relaxed)
+    }
+}
+
+/// Held by an image while it is in flight; releases its slot on drop
+pub struct InFlightPermit {
+    _permit: Option<OwnedSemaphorePermit>,
+    in_flight: Arc<AtomicUsize>,
+}
+
+impl Drop for InFlightPermit {
+    fn drop(&mut self) {
+        self.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
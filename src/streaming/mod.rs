@@ -1,4 +1,8 @@
+pub mod batch_download;
 pub mod channel_demo;
 pub mod download;
+pub mod in_flight;
 pub mod process;
 pub mod pipeline;
+pub mod rate_limit;
+pub mod sweep;
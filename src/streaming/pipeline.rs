@@ -1,110 +1,891 @@
 use anyhow::Result;
-use sha2::{Digest, Sha256};
+use indicatif::ProgressBar;
+use serde::{Deserialize, Serialize};
 use std::{
-    cmp::max,
-    path::Path,
+    any::Any,
+    cmp::{max, Ordering as CmpOrdering, Reverse},
+    collections::BinaryHeap,
+    future::Future,
+    panic::AssertUnwindSafe,
+    path::{Path, PathBuf},
     sync::{
-        atomic::{AtomicU64, Ordering},
+        atomic::{AtomicU64, AtomicUsize, Ordering},
         Arc,
     },
     time::Duration,
 };
+use futures::{
+    future::{self, join_all},
+    stream, FutureExt, Stream, StreamExt,
+};
 use tokio::{
     spawn,
-    sync::mpsc,
-    time::{sleep, Instant},
-    try_join,
+    sync::{mpsc, watch, Mutex},
+    task::JoinHandle,
+    time::Instant,
 };
-use tracing::info;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, instrument, warn, Instrument};
 
 use crate::{
-    memory_monitor::MemoryMonitor,
+    config::{ErrorPolicy, ProcessorConfig},
+    error::{DeadLetterQueue, PipelineError, ProcessingError},
+    image_processor::{ImageProcessor, ImageResult, OutputFormat, ResizeConfig, SaveConfig},
+    manifest::{now_ms, write_manifest, ManifestEntry},
+    memory_monitor::{spawn_peak_tracker_with, MemoryMonitor},
+    metrics::{percentiles, stddev, write_per_image_csv, PerImageRecord, ProcessingRun},
+    output_sink::{ImageRecord, OutputSink, SinkWriter},
+    progress::progress_bar,
     streaming::{
-        download::{download_stage, ImageData},
-        process::{process_stage, ProcessedImage},
+        download::{download_stage_with_limit, ImageData},
+        in_flight::InFlightLimiter,
+        process::{process_stage, ProcessedImage, ProcessedOutput, RejectedImage},
     },
     url_generator::UrlGenerator,
+    validation::validate_count,
+    warmup::warm_up,
 };
 
+#[derive(Default, Serialize, Deserialize)]
 pub struct StreamingStats {
     pub total_images: usize,
+    /// Decode + resize jobs the process stage was allowed to run at once
+    pub streaming_concurrency: usize,
+    /// Save workers writing files at once
+    pub save_concurrency: usize,
     pub total_time_ms: u64,
+    /// Most memory the run allocated, above the process's usage when it started
     pub peak_memory_mb: u64,
+    /// Highest CPU usage sampled during the run, as a percentage of one core
+    pub peak_cpu_percent: f32,
+    /// Times downloads paused because memory went over `config.memory_limit_mb`
+    pub memory_pause_count: u64,
     pub avg_download_ms: u64,
     pub avg_resize_ms: u64,
+    pub p50_download_ms: u64,
+    pub p95_download_ms: u64,
+    pub p99_download_ms: u64,
+    pub p50_resize_ms: u64,
+    pub p95_resize_ms: u64,
+    pub p99_resize_ms: u64,
+    pub stddev_download_ms: f64,
+    pub stddev_resize_ms: f64,
+    /// Average time to the first body byte, mostly network latency
+    pub avg_ttfb_ms: u64,
+    /// Average time to the last body byte; the gap after `avg_ttfb_ms` is down to bandwidth
+    pub avg_ttlb_ms: u64,
+    /// Every image's download time, sorted
+    pub download_samples: Vec<u64>,
+    /// Images skipped because their output already existed, not counted in `total_images`
+    pub skipped_count: usize,
+    pub avg_sharpen_ms: u64,
+    /// Average encoded file size, useful for comparing sharpened and unsharpened runs
+    pub avg_saved_bytes: u64,
+    /// Downloads aborted for exceeding `max_image_bytes`
+    pub oversized_rejections: usize,
+    /// Downloads rejected by `config.verify_content_type` or `config.verify_magic_bytes`
+    pub invalid_content_rejections: usize,
+    /// Images turned away for being smaller than `config.download`'s minimum size
+    pub rejected_count: usize,
+    /// URLs turned away by `config.preflight_check` before downloading
+    pub preflight_rejected: usize,
+    /// Downloads that still failed after `config.download_retries` retries
+    pub failed_count: usize,
+    /// Most images downloaded but not yet saved at any one time
+    pub max_in_flight_observed: usize,
+    /// Multipart requests served by `config.batch_download`
+    pub batch_download_requests: usize,
+    pub avg_urls_per_batch_request: f64,
+    /// Downloads answered from `config.disk_cache` because the server's copy was unchanged
+    pub cache_hits: u64,
+    /// Downloads whose body was fetched although `config.disk_cache` was set
+    pub cache_misses: u64,
+    /// Average time spent in `config.result_sink`, 0 when images were saved to disk
+    pub avg_sink_ms: u64,
+    /// Time from the start of the run until the first image was saved
+    pub pipeline_start_latency_ms: u64,
+    /// Time from the start of the run until the last image was saved; minus
+    /// `pipeline_start_latency_ms` this is the steady-state processing time
+    pub time_to_last_save_ms: u64,
+    /// Average original/compressed size of channel payloads, 0.0 unless
+    /// `config.compress_channel` is set
+    pub avg_compression_ratio: f64,
+    pub avg_compress_ms: u64,
+    /// Samples from `config.memory_timeline` taken during the run, as (offset from the
+    /// timeline's creation, MB); empty without a timeline
+    pub memory_timeline: Vec<(Duration, u64)>,
+    /// `config.cancellation` fired before every URL was downloaded; `total_images` then
+    /// counts only the images that made it through
+    pub cancelled: bool,
+    pub dead_letters: Vec<ProcessingError>,
+    /// (URL, error) for every dead letter under [`ErrorPolicy::CollectAndContinue`], when
+    /// `total_images` counts only the images that were saved
+    pub errors: Vec<(String, String)>,
+    /// Stages that failed or panicked under [`ErrorPolicy::CollectAndContinue`]; the stats
+    /// then cover whatever the other stages got through
+    pub pipeline_errors: Vec<PipelineError>,
+}
+
+impl StreamingStats {
+    /// This run's row in a [`MetricsCollector`](crate::metrics::MetricsCollector) comparison
+    pub fn to_run(&self, approach: &str) -> ProcessingRun {
+        ProcessingRun::new(
+            approach,
+            self.total_images,
+            self.total_time_ms,
+            self.peak_memory_mb,
+            self.avg_download_ms,
+            self.avg_resize_ms,
+        )
+        .with_percentiles(
+            [
+                self.p50_download_ms,
+                self.p95_download_ms,
+                self.p99_download_ms,
+            ],
+            [self.p50_resize_ms, self.p95_resize_ms, self.p99_resize_ms],
+        )
+        .with_stddev(self.stddev_download_ms, self.stddev_resize_ms)
+        .with_peak_cpu(self.peak_cpu_percent)
+        .with_download_samples(self.download_samples.clone())
+    }
+}
+
+#[derive(Default)]
+struct SaveSummary {
+    images: usize,
+    avg_download_ms: u64,
+    avg_resize_ms: u64,
+    download_percentiles: [u64; 3],
+    resize_percentiles: [u64; 3],
+    stddev_download_ms: f64,
+    stddev_resize_ms: f64,
+    avg_ttfb_ms: u64,
+    avg_ttlb_ms: u64,
+    download_samples: Vec<u64>,
+    avg_sharpen_ms: u64,
+    avg_saved_bytes: u64,
+    avg_sink_ms: u64,
+    first_save_ms: u64,
+    last_save_ms: u64,
+    avg_compression_ratio: f64,
+    avg_compress_ms: u64,
+}
+
+/// Running totals every save worker adds to
+struct SaveTotals {
+    images: AtomicU64,
+    saved: AtomicU64,
+    download_ms: AtomicU64,
+    ttfb_ms: AtomicU64,
+    ttlb_ms: AtomicU64,
+    resize_ms: AtomicU64,
+    sharpen_ms: AtomicU64,
+    saved_bytes: AtomicU64,
+    sink_ms: AtomicU64,
+    compressed: AtomicU64,
+    compress_ms: AtomicU64,
+    first_save_ms: AtomicU64,
+    last_save_ms: AtomicU64,
+}
+
+impl SaveTotals {
+    fn new() -> Self {
+        SaveTotals {
+            images: AtomicU64::new(0),
+            saved: AtomicU64::new(0),
+            download_ms: AtomicU64::new(0),
+            ttfb_ms: AtomicU64::new(0),
+            ttlb_ms: AtomicU64::new(0),
+            resize_ms: AtomicU64::new(0),
+            sharpen_ms: AtomicU64::new(0),
+            saved_bytes: AtomicU64::new(0),
+            sink_ms: AtomicU64::new(0),
+            compressed: AtomicU64::new(0),
+            compress_ms: AtomicU64::new(0),
+            first_save_ms: AtomicU64::new(u64::MAX),
+            last_save_ms: AtomicU64::new(0),
+        }
+    }
+}
+
+/// What a single save worker collected, in the order it saved
+#[derive(Default)]
+struct WorkerRecords {
+    download_samples: Vec<u64>,
+    resize_samples: Vec<u64>,
+    compression_ratio: f64,
+    manifest: Vec<ManifestEntry>,
+    per_image: Vec<PerImageRecord>,
+}
+
+/// [`ProcessedImage`] ordered by its sequence alone, for the reorder buffer
+struct Sequenced(ProcessedImage);
+
+impl PartialEq for Sequenced {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.sequence == other.0.sequence
+    }
 }
 
+impl Eq for Sequenced {}
+
+impl PartialOrd for Sequenced {
+    fn partial_cmp(&self, other: &Self) -> Option<CmpOrdering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Sequenced {
+    fn cmp(&self, other: &Self) -> CmpOrdering {
+        self.0.sequence.cmp(&other.0.sequence)
+    }
+}
+
+/// Forward images from `input` to `output` in sequence order, holding back any that arrive
+/// before the next expected one. Images that never arrive (failed, rejected or cancelled)
+/// hold back everything after them until `input` closes, when the rest are sent in order.
+///
+/// This costs throughput: a single slow download stalls every later save behind it, so the
+/// save stage runs at the pace of the slowest image seen so far rather than the average, and
+/// the buffer grows by however many images finish in the meantime.
+async fn reorder(mut input: mpsc::Receiver<ProcessedImage>, output: mpsc::Sender<ProcessedImage>) {
+    let mut pending = BinaryHeap::new();
+    let mut next = 0;
+    while let Some(mut image) = input.recv().await {
+        // A held-back image must not keep the in-flight slot the one it waits for needs
+        image.in_flight = None;
+        pending.push(Reverse(Sequenced(image)));
+        while pending
+            .peek()
+            .is_some_and(|Reverse(Sequenced(image))| image.sequence <= next)
+        {
+            let Reverse(Sequenced(image)) = pending.pop().unwrap();
+            next = max(next, image.sequence + 1);
+            if output.send(image).await.is_err() {
+                return;
+            }
+        }
+    }
+    while let Some(Reverse(Sequenced(image))) = pending.pop() {
+        if output.send(image).await.is_err() {
+            return;
+        }
+    }
+}
+
+/// Save images from `input` with `concurrency` workers taking turns on the channel. The
+/// manifest and per-image CSV list each worker's images together, in worker order. With
+/// `config.preserve_order` images are first put back in input order and saved by a single
+/// worker.
+#[instrument(skip_all, fields(concurrency))]
 async fn save_stage(
-    mut input: mpsc::Receiver<ProcessedImage>,
-    output_dir: &Path,
-) -> Result<(u64, u64)> {
+    input: mpsc::Receiver<ProcessedImage>,
+    output: &SinkWriter,
+    concurrency: usize,
+    config: &ProcessorConfig,
+    pipeline_start: Instant,
+    progress: &ProgressBar,
+) -> Result<SaveSummary> {
     // TODO: What if there's a situation where there's no more data and the channel closes, this function returns, but then the data gets added later? Is this kind of situation possible?
-    let mut total_download_ms = 0;
-    let mut total_resize_ms = 0;
-    let mut image_count: u128 = 0;
+    let (input, concurrency) = if config.preserve_order {
+        let (ordered_tx, ordered_rx) = mpsc::channel(config.process_channel_capacity.max(1));
+        spawn(reorder(input, ordered_tx).in_current_span());
+        (ordered_rx, 1)
+    } else {
+        (input, concurrency)
+    };
+    let input = Arc::new(Mutex::new(input));
+    let totals = Arc::new(SaveTotals::new());
+    let workers: Vec<_> = (0..concurrency.max(1))
+        .map(|_| {
+            let input = Arc::clone(&input);
+            let totals = Arc::clone(&totals);
+            let output = output.clone();
+            let config = config.clone();
+            let progress = progress.clone();
+            spawn(
+                async move {
+                    save_worker(&input, &totals, &output, &config, pipeline_start, &progress).await
+                }
+                .in_current_span(),
+            )
+        })
+        .collect();
 
-    let mut saved = 0u128;
-    while let Some(image_data) = input.recv().await {
-        let filename = format!("{:x}.jpg", Sha256::digest(image_data.url.as_bytes()));
-        image_data.image.save(output_dir.join(filename))?;
-        total_download_ms += image_data.download_ms;
-        total_resize_ms += image_data.resize_ms;
-        image_count += 1;
-        saved += 1;
+    let mut records = WorkerRecords::default();
+    for worker in join_all(workers).await {
+        let worker = worker??;
+        records.download_samples.extend(worker.download_samples);
+        records.resize_samples.extend(worker.resize_samples);
+        records.compression_ratio += worker.compression_ratio;
+        records.manifest.extend(worker.manifest);
+        records.per_image.extend(worker.per_image);
     }
+    progress.finish();
 
+    let image_count = totals.images.load(Ordering::Relaxed);
     anyhow::ensure!(image_count > 0, "no images processed");
 
-    let avg_download_ms: u64 = (total_download_ms / image_count) as u64;
-    let avg_resize_ms: u64 = (total_resize_ms / image_count) as u64;
+    if config.output_manifest {
+        write_manifest(output.dir(), &records.manifest)?;
+    }
+    if config.per_image {
+        write_per_image_csv(output.dir(), &records.per_image)?;
+    }
+
+    let saved = totals.saved.load(Ordering::Relaxed);
+    info!(saved, concurrency, "save stage complete");
+
+    let compressed = totals.compressed.load(Ordering::Relaxed);
+    let average = |total: &AtomicU64| total.load(Ordering::Relaxed) / image_count;
+    let (mut download_samples, mut resize_samples) =
+        (records.download_samples, records.resize_samples);
+    Ok(SaveSummary {
+        images: image_count as usize,
+        avg_download_ms: average(&totals.download_ms),
+        avg_resize_ms: average(&totals.resize_ms),
+        download_percentiles: percentiles(&mut download_samples),
+        resize_percentiles: percentiles(&mut resize_samples),
+        stddev_download_ms: stddev(&download_samples),
+        stddev_resize_ms: stddev(&resize_samples),
+        avg_ttfb_ms: average(&totals.ttfb_ms),
+        avg_ttlb_ms: average(&totals.ttlb_ms),
+        download_samples,
+        avg_sharpen_ms: average(&totals.sharpen_ms),
+        avg_saved_bytes: average(&totals.saved_bytes),
+        avg_sink_ms: average(&totals.sink_ms),
+        first_save_ms: totals.first_save_ms.load(Ordering::Relaxed),
+        last_save_ms: totals.last_save_ms.load(Ordering::Relaxed),
+        avg_compression_ratio: if compressed > 0 {
+            records.compression_ratio / compressed as f64
+        } else {
+            0.0
+        },
+        avg_compress_ms: totals
+            .compress_ms
+            .load(Ordering::Relaxed)
+            .checked_div(compressed)
+            .unwrap_or(0),
+    })
+}
+
+async fn save_worker(
+    input: &Mutex<mpsc::Receiver<ProcessedImage>>,
+    totals: &SaveTotals,
+    output: &SinkWriter,
+    config: &ProcessorConfig,
+    pipeline_start: Instant,
+    progress: &ProgressBar,
+) -> Result<WorkerRecords> {
+    let mut records = WorkerRecords::default();
+    loop {
+        // The lock is only held while waiting for the next image, not while saving it
+        let Some(image_data) = input.lock().await.recv().await else {
+            break;
+        };
+        totals
+            .download_ms
+            .fetch_add(image_data.download_ms as u64, Ordering::Relaxed);
+        totals
+            .ttfb_ms
+            .fetch_add(image_data.time_to_first_byte_ms as u64, Ordering::Relaxed);
+        totals
+            .ttlb_ms
+            .fetch_add(image_data.time_to_last_byte_ms as u64, Ordering::Relaxed);
+        totals
+            .resize_ms
+            .fetch_add(image_data.resize_ms as u64, Ordering::Relaxed);
+        totals
+            .sharpen_ms
+            .fetch_add(image_data.sharpen_ms as u64, Ordering::Relaxed);
+        records.download_samples.push(image_data.download_ms as u64);
+        records.resize_samples.push(image_data.resize_ms as u64);
+        if let Some(metrics) = &config.live_metrics {
+            let (download_ms, resize_ms) = (image_data.download_ms, image_data.resize_ms);
+            metrics.record_image("streaming", download_ms as u64, resize_ms as u64);
+        }
+        if let Some(compression) = &image_data.compression {
+            records.compression_ratio += compression.ratio();
+            totals
+                .compress_ms
+                .fetch_add(compression.compress_ms, Ordering::Relaxed);
+            totals.compressed.fetch_add(1, Ordering::Relaxed);
+        }
+
+        let record = config.per_image.then(|| PerImageRecord {
+            url: image_data.url.clone(),
+            download_ms: image_data.download_ms as u64,
+            resize_ms: image_data.resize_ms as u64,
+            bytes: 0,
+        });
+        if let Some(sink) = &config.result_sink {
+            records.per_image.extend(record);
+            let sink_start = Instant::now();
+            sink.call(ImageResult {
+                url: image_data.url,
+                image: image_data.image.into_image()?,
+                download_ms: image_data.download_ms as u64,
+                resize_ms: image_data.resize_ms as u64,
+            })
+            .await;
+            totals
+                .sink_ms
+                .fetch_add(sink_start.elapsed().as_millis() as u64, Ordering::Relaxed);
+        } else if output.writes_records() {
+            records.per_image.extend(record);
+            let (width, height) = image_data.image.dimensions()?;
+            output.write_record(&ImageRecord {
+                url: image_data.url,
+                width,
+                height,
+                download_ms: image_data.download_ms as u64,
+                resize_ms: image_data.resize_ms as u64,
+            })?;
+        } else {
+            let (path, saved_bytes) = match &image_data.image {
+                ProcessedOutput::Image(image) => output.save(&image_data.url, image, config)?,
+                ProcessedOutput::Encoded(bytes) => {
+                    output.save_encoded(&image_data.url, bytes, config)?
+                }
+            };
+            records.per_image.extend(record.map(|record| PerImageRecord {
+                bytes: saved_bytes,
+                ..record
+            }));
+            if config.output_manifest {
+                records.manifest.push(ManifestEntry {
+                    filename: path
+                        .strip_prefix(output.dir())
+                        .unwrap_or(&path)
+                        .display()
+                        .to_string(),
+                    bytes: saved_bytes,
+                    download_ms: image_data.download_ms as u64,
+                    resize_ms: image_data.resize_ms as u64,
+                    saved_at_ms: now_ms(),
+                    url: image_data.url,
+                });
+            }
+            totals.saved_bytes.fetch_add(saved_bytes, Ordering::Relaxed);
+            totals.saved.fetch_add(1, Ordering::Relaxed);
+        }
+
+        let save_ms = pipeline_start.elapsed().as_millis() as u64;
+        totals.first_save_ms.fetch_min(save_ms, Ordering::Relaxed);
+        totals.last_save_ms.fetch_max(save_ms, Ordering::Relaxed);
+        totals.images.fetch_add(1, Ordering::Relaxed);
+        progress.inc(1);
+    }
+    Ok(records)
+}
+
+/// Configures a [`StreamingPipeline`]. Starts from [`ProcessorConfig::default`] unless
+/// [`config`](Self::config) is given; the setters override individual fields of it.
+#[derive(Debug, Clone, Default)]
+pub struct StreamingPipelineBuilder {
+    config: ProcessorConfig,
+    output: Option<OutputSink>,
+}
+
+impl StreamingPipelineBuilder {
+    pub fn config(mut self, config: ProcessorConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn download_concurrency(mut self, concurrency: usize) -> Self {
+        self.config.download_concurrency = concurrency;
+        self
+    }
+
+    /// Capacity of both the download and process channels
+    pub fn channel_capacity(mut self, capacity: usize) -> Self {
+        self.config.download_channel_capacity = capacity;
+        self.config.process_channel_capacity = capacity;
+        self
+    }
+
+    pub fn process_concurrency(mut self, concurrency: usize) -> Self {
+        self.config.process_concurrency = concurrency;
+        self
+    }
+
+    /// Save workers writing files at once; above 1 the manifest is no longer in arrival order
+    pub fn save_concurrency(mut self, concurrency: usize) -> Self {
+        self.config.save_concurrency = concurrency;
+        self
+    }
+
+    /// Save images in URL order instead of arrival order; see [`ProcessorConfig::preserve_order`]
+    pub fn preserve_order(mut self, preserve_order: bool) -> Self {
+        self.config.preserve_order = preserve_order;
+        self
+    }
+
+    pub fn resize_config(mut self, resize: ResizeConfig) -> Self {
+        self.config.resize = Some(resize);
+        self
+    }
+
+    pub fn output_format(mut self, format: OutputFormat) -> Self {
+        self.config.save = Some(SaveConfig { format });
+        self
+    }
+
+    /// Decode, resize and encode with `processor` instead of the built-in steps
+    pub fn image_processor(mut self, processor: Box<dyn ImageProcessor + Send + Sync>) -> Self {
+        self.config.image_processor = Some(Arc::from(processor));
+        self
+    }
+
+    pub fn cancellation_token(mut self, token: CancellationToken) -> Self {
+        self.config.cancellation = Some(token);
+        self
+    }
+
+    /// Shorthand for [`output_sink`](Self::output_sink) with [`OutputSink::Directory`]
+    pub fn output_dir(self, output_dir: PathBuf) -> Self {
+        self.output_sink(OutputSink::Directory(output_dir))
+    }
+
+    pub fn output_sink(mut self, output: OutputSink) -> Self {
+        self.output = Some(output);
+        self
+    }
+
+    /// Fails if no output was set or a concurrency or channel capacity is zero
+    pub fn build(self) -> Result<StreamingPipeline> {
+        let output = self
+            .output
+            .ok_or_else(|| anyhow::anyhow!("streaming pipeline needs an output sink"))?;
+        let config = self.config;
+        for (name, value) in [
+            ("download_concurrency", config.download_concurrency),
+            ("process_concurrency", config.process_concurrency),
+            ("save_concurrency", config.save_concurrency),
+            ("download_channel_capacity", config.download_channel_capacity),
+            ("process_channel_capacity", config.process_channel_capacity),
+        ] {
+            if value == 0 {
+                anyhow::bail!("{} must be at least 1", name);
+            }
+        }
+        Ok(StreamingPipeline { config, output })
+    }
+}
+
+/// Download, process and save stages joined by bounded channels
+#[derive(Debug, Clone)]
+pub struct StreamingPipeline {
+    config: ProcessorConfig,
+    output: OutputSink,
+}
+
+impl StreamingPipeline {
+    pub fn builder() -> StreamingPipelineBuilder {
+        StreamingPipelineBuilder::default()
+    }
 
-    info!(saved, "save stage complete");
+    pub async fn run(&self, count: usize) -> Result<StreamingStats> {
+        validate_count(count)?;
+        run_streaming(count, &self.output, &self.config).await
+    }
 
-    Ok((avg_download_ms, avg_resize_ms))
+    /// Run over `urls` as they arrive rather than a generated list, e.g. a
+    /// [`UrlGenerator::stream`] over a corpus too large to hold in memory. Already saved
+    /// images are skipped one URL at a time as the download stage pulls them.
+    pub async fn run_stream(
+        &self,
+        urls: impl Stream<Item = String> + Send + 'static,
+    ) -> Result<StreamingStats> {
+        run_url_stream(urls, &self.output, &self.config).await
+    }
 }
 
+#[deprecated(note = "use `StreamingPipeline::builder()` instead")]
 pub async fn process_streaming(
     count: usize,
     output_dir: &Path,
-    download_concurrency: usize,
-    process_concurrency: usize,
-    channel_capacity: usize,
+    config: &ProcessorConfig,
 ) -> Result<StreamingStats> {
-    info!(count, download_concurrency, channel_capacity, "starting streaming pipeline");
-    let peak_memory_mb = Arc::new(AtomicU64::new(0));
-    let peak_clone = Arc::clone(&peak_memory_mb);
-
-    let monitor_handle = spawn(async move {
-        let mut memory_monitor = MemoryMonitor::new();
-        loop {
-            let curr_usage = memory_monitor.current_usage_mb();
-            peak_clone.store(
-                max(curr_usage, peak_clone.load(Ordering::Relaxed)),
-                Ordering::Relaxed,
-            );
-            sleep(Duration::from_millis(100)).await;
+    StreamingPipeline::builder()
+        .config(config.clone())
+        .output_dir(output_dir.to_path_buf())
+        .build()?
+        .run(count)
+        .await
+}
+
+/// [`process_streaming`] saving images in URL order rather than arrival order
+pub async fn process_streaming_ordered(
+    count: usize,
+    output_dir: &Path,
+    config: &ProcessorConfig,
+) -> Result<StreamingStats> {
+    StreamingPipeline::builder()
+        .config(config.clone())
+        .preserve_order(true)
+        .output_dir(output_dir.to_path_buf())
+        .build()?
+        .run(count)
+        .await
+}
+
+/// Run `stage` on its own task under the current span. A failure or panic is sent to
+/// `errors` rather than returned, and the task yields `None`.
+fn spawn_stage<T: Send + 'static>(
+    name: &'static str,
+    stage: impl Future<Output = Result<T>> + Send + 'static,
+    errors: mpsc::Sender<PipelineError>,
+) -> JoinHandle<Option<T>> {
+    spawn(
+        async move {
+            let error = match AssertUnwindSafe(stage).catch_unwind().await {
+                Ok(Ok(output)) => return Some(output),
+                Ok(Err(e)) => PipelineError::Failed {
+                    stage: name.to_string(),
+                    message: format!("{:#}", e),
+                },
+                Err(panic) => PipelineError::Panicked {
+                    stage: name.to_string(),
+                    message: panic_message(panic.as_ref()),
+                },
+            };
+            let _ = errors.send(error).await;
+            None
         }
-    });
+        .in_current_span(),
+    )
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> String {
+    match panic.downcast_ref::<&str>() {
+        Some(message) => message.to_string(),
+        None => panic
+            .downcast_ref::<String>()
+            .cloned()
+            .unwrap_or_else(|| "unknown panic".to_string()),
+    }
+}
+
+async fn run_streaming(
+    count: usize,
+    output: &OutputSink,
+    config: &ProcessorConfig,
+) -> Result<StreamingStats> {
+    let urls = UrlGenerator::for_config(count, config).stream();
+    run_url_stream(urls, output, config).await
+}
+
+#[instrument(name = "streaming_pipeline", skip_all, fields(count = urls.size_hint().1))]
+async fn run_url_stream(
+    urls: impl Stream<Item = String> + Send + 'static,
+    output: &OutputSink,
+    config: &ProcessorConfig,
+) -> Result<StreamingStats> {
+    let config = &config.with_shared_client()?;
+    let download_concurrency = config.download_concurrency;
+    let process_concurrency = config.process_concurrency;
+    let save_concurrency = config.save_concurrency;
+    let (min_count, max_count) = urls.size_hint();
+    let progress = progress_bar(max_count.unwrap_or(min_count), config.progress);
+    // Filtered as the download stage pulls them, so skipped images never reach a stage
+    let skipped = Arc::new(AtomicUsize::new(0));
+    let (skip_output, skip_config, skip_count, skip_progress) = (
+        output.clone(),
+        config.clone(),
+        Arc::clone(&skipped),
+        progress.clone(),
+    );
+    let mut urls = Box::pin(
+        urls.filter(move |url| {
+            let saved = skip_output.is_saved(url, &skip_config);
+            if saved {
+                skip_count.fetch_add(1, Ordering::Relaxed);
+                skip_progress.dec_length(1);
+            }
+            future::ready(!saved)
+        })
+        .peekable(),
+    );
+    // Warm-up URLs are pulled ahead of the rest and then queued like any other
+    let warmup_urls: Vec<String> = (&mut urls).take(config.warmup_count).collect().await;
+    warm_up(&warmup_urls, config).await;
+    let skipped_count = skipped.load(Ordering::Relaxed);
+    if warmup_urls.is_empty() && urls.as_mut().peek().await.is_none() && skipped_count > 0 {
+        info!(skipped_count, "every image is already saved");
+        return Ok(StreamingStats {
+            streaming_concurrency: process_concurrency,
+            save_concurrency,
+            skipped_count,
+            ..Default::default()
+        });
+    }
+    let urls = stream::iter(warmup_urls).chain(urls);
+    info!(
+        count = max_count,
+        download_concurrency,
+        process_concurrency,
+        save_concurrency,
+        download_channel_capacity = config.download_channel_capacity,
+        process_channel_capacity = config.process_channel_capacity,
+        "starting streaming pipeline"
+    );
+    debug!(diagram = %config.render_diagram(), "pipeline layout");
+    let memory_pause_count = Arc::new(AtomicU64::new(0));
+    let pause_count_clone = Arc::clone(&memory_pause_count);
+    let (pause_tx, pause_rx) = watch::channel(false);
+    let memory_limit_mb = config.memory_limit_mb;
+
+    let on_sample = move |curr_usage| {
+        let exceeded = memory_limit_mb.is_some_and(|limit| curr_usage > limit);
+        let changed = pause_tx.send_if_modified(|paused| {
+            let changed = *paused != exceeded;
+            *paused = exceeded;
+            changed
+        });
+        if changed && exceeded {
+            pause_count_clone.fetch_add(1, Ordering::Relaxed);
+            warn!(curr_usage, "memory over limit, pausing downloads");
+        } else if changed {
+            info!(curr_usage, "memory back under limit, resuming downloads");
+        }
+    };
+    let (monitor_handle, peak) =
+        spawn_peak_tracker_with(100, MemoryMonitor::with_baseline(), on_sample);
 
     let start_time = Instant::now();
-    let urls = UrlGenerator::new(count).generate();
-    let output_pathbuf = output_dir.to_path_buf();
+    let writer = output.open()?;
+    let save_writer = writer.clone();
+    let mut download_config = config.clone();
+    if memory_limit_mb.is_some() {
+        download_config.memory_pause = Some(pause_rx);
+    }
+    let process_config = config.clone();
+    let save_config = config.clone();
+    let dead_letters = DeadLetterQueue::new();
+    let download_dead_letters = dead_letters.clone();
+    let process_dead_letters = dead_letters.clone();
+
+    let (download_tx, download_rx) = mpsc::channel::<ImageData>(config.download_channel_capacity);
+    let (process_tx, process_rx) = mpsc::channel::<ProcessedImage>(config.process_channel_capacity);
+    let (rejected_tx, mut rejected_rx) =
+        mpsc::channel::<RejectedImage>(config.process_channel_capacity);
+
+    // Shared by every stage: permits are taken on download and released once saved
+    let in_flight = InFlightLimiter::new(config.max_in_flight);
+    let download_in_flight = in_flight.clone();
 
-    let (download_tx, download_rx) = mpsc::channel::<ImageData>(channel_capacity);
-    let (process_tx, process_rx) = mpsc::channel::<ProcessedImage>(channel_capacity);
+    // Each stage runs in its own task but stays under this run's span, reporting failures
+    // to the supervisor below instead of returning them
+    let (error_tx, mut error_rx) = mpsc::channel::<PipelineError>(3);
+    let download_task = spawn_stage(
+        "download",
+        async move {
+            download_stage_with_limit(
+                urls,
+                download_tx,
+                download_concurrency,
+                &download_config,
+                &download_dead_letters,
+                &download_in_flight,
+            )
+            .await
+        },
+        error_tx.clone(),
+    );
+    let process_task = spawn_stage(
+        "process",
+        async move {
+            process_stage(
+                download_rx,
+                process_tx,
+                rejected_tx,
+                process_concurrency,
+                &process_config,
+                &process_dead_letters,
+            )
+            .await
+        },
+        error_tx.clone(),
+    );
+    // Rejections are already logged by the process stage, so they are only counted here
+    let rejected_task = spawn(async move {
+        let mut rejected = 0;
+        while rejected_rx.recv().await.is_some() {
+            rejected += 1;
+        }
+        rejected
+    });
+    let save_task = spawn_stage(
+        "save",
+        async move {
+            let concurrency = save_config.save_concurrency;
+            save_stage(
+                process_rx,
+                &save_writer,
+                concurrency,
+                &save_config,
+                start_time,
+                &progress,
+            )
+            .await
+        },
+        error_tx,
+    );
 
-    let download_task =
-        spawn(async move { download_stage(urls, download_tx, download_concurrency).await });
-    let process_task = spawn(async move { process_stage(download_rx, process_tx, process_concurrency).await });
-    let save_task = spawn(async move { save_stage(process_rx, &output_pathbuf).await });
+    // Under FailFast the first failure stops every stage; otherwise the rest carry on and
+    // the run reports whatever they got through
+    let fail_fast = config.error_policy == ErrorPolicy::FailFast;
+    let stages = [
+        download_task.abort_handle(),
+        process_task.abort_handle(),
+        save_task.abort_handle(),
+    ];
+    let supervisor = spawn(
+        async move {
+            let mut errors = vec![];
+            while let Some(error) = error_rx.recv().await {
+                warn!(error = %error, "pipeline stage failed");
+                if fail_fast {
+                    stages.iter().for_each(|stage| stage.abort());
+                }
+                errors.push(error);
+            }
+            errors
+        }
+        .in_current_span(),
+    );
 
-    let (_, _, save_res) = try_join!(download_task, process_task, save_task)?;
-    let (avg_download_ms, avg_resize_ms) = save_res?;
+    // An aborted stage has nothing to report, and its failure is already with the supervisor
+    let downloads = download_task.await.ok().flatten();
+    process_task.await.ok();
+    let summary = save_task.await.ok().flatten();
+    let rejected_count = rejected_task.await?;
+    let pipeline_errors = supervisor.await?;
+    if let (true, Some(error)) = (fail_fast, pipeline_errors.first()) {
+        monitor_handle.abort();
+        return Err(error.clone().into());
+    }
+    // Failed downloads don't stop the stages, so under FailFast the run fails once they
+    // finish. Images the configured checks turned away are only counted.
+    let dropped = dead_letters.errors();
+    if let (true, Some(error)) = (fail_fast, dropped.iter().find(|e| e.is_failure())) {
+        monitor_handle.abort();
+        return Err(error.clone().into());
+    }
+    let (downloads, summary) = (downloads.unwrap_or_default(), summary.unwrap_or_default());
+    writer.finish()?;
+    let (avg_download_ms, avg_resize_ms) = (summary.avg_download_ms, summary.avg_resize_ms);
 
     let total_time_ms = start_time.elapsed().as_millis() as u64;
 
     monitor_handle.abort();
-    let peak_memory_mb = peak_memory_mb.load(Ordering::Relaxed);
+    let (peak_memory_mb, peak_cpu_percent) = (peak.memory_mb(), peak.cpu_percent());
+    if let Some(metrics) = &config.live_metrics {
+        metrics.record_peak_memory_mb(peak_memory_mb);
+    }
 
     info!(
         total_time_ms,
@@ -113,31 +894,854 @@ pub async fn process_streaming(
         avg_resize_ms,
         "streaming pipeline complete"
     );
+    let skipped_count = skipped.load(Ordering::Relaxed);
+    if skipped_count > 0 {
+        info!(skipped = skipped_count, "skipped images that were already saved");
+    }
+
+    let [p50_download_ms, p95_download_ms, p99_download_ms] = summary.download_percentiles;
+    let [p50_resize_ms, p95_resize_ms, p99_resize_ms] = summary.resize_percentiles;
+
+    let cancelled = config
+        .cancellation
+        .as_ref()
+        .is_some_and(|token| token.is_cancelled());
+    let collect_errors = config.error_policy == ErrorPolicy::CollectAndContinue;
+    let errors = if collect_errors {
+        dead_letters
+            .errors()
+            .iter()
+            .map(|e| (e.url().to_string(), e.to_string()))
+            .collect()
+    } else {
+        vec![]
+    };
 
     Ok(StreamingStats {
-        total_images: count,
+        total_images: summary.images,
+        streaming_concurrency: process_concurrency,
+        save_concurrency,
         total_time_ms,
         peak_memory_mb,
+        peak_cpu_percent,
+        memory_pause_count: memory_pause_count.load(Ordering::Relaxed),
         avg_download_ms,
         avg_resize_ms,
+        p50_download_ms,
+        p95_download_ms,
+        p99_download_ms,
+        p50_resize_ms,
+        p95_resize_ms,
+        p99_resize_ms,
+        stddev_download_ms: summary.stddev_download_ms,
+        stddev_resize_ms: summary.stddev_resize_ms,
+        avg_ttfb_ms: summary.avg_ttfb_ms,
+        avg_ttlb_ms: summary.avg_ttlb_ms,
+        download_samples: summary.download_samples,
+        skipped_count,
+        avg_sharpen_ms: summary.avg_sharpen_ms,
+        avg_saved_bytes: summary.avg_saved_bytes,
+        oversized_rejections: dead_letters
+            .count(|e| matches!(e, ProcessingError::ImageTooLarge { .. })),
+        invalid_content_rejections: dead_letters
+            .count(|e| matches!(e, ProcessingError::NotAnImage { .. })),
+        rejected_count,
+        preflight_rejected: dead_letters
+            .count(|e| matches!(e, ProcessingError::PreflightRejected { .. })),
+        failed_count: dead_letters.count(|e| matches!(e, ProcessingError::Download { .. })),
+        batch_download_requests: downloads.batch_download_requests,
+        avg_urls_per_batch_request: if downloads.batch_download_requests > 0 {
+            downloads.batched_urls as f64 / downloads.batch_download_requests as f64
+        } else {
+            0.0
+        },
+        cache_hits: downloads.cache_hits,
+        cache_misses: downloads.cache_misses,
+        avg_sink_ms: summary.avg_sink_ms,
+        max_in_flight_observed: in_flight.max_observed(),
+        pipeline_start_latency_ms: summary.first_save_ms,
+        time_to_last_save_ms: summary.last_save_ms,
+        avg_compression_ratio: summary.avg_compression_ratio,
+        avg_compress_ms: summary.avg_compress_ms,
+        cancelled,
+        memory_timeline: config
+            .memory_timeline
+            .as_ref()
+            .map(|timeline| timeline.timeline_since(start_time.into_std()))
+            .unwrap_or_default(),
+        dead_letters: dead_letters.errors(),
+        errors,
+        pipeline_errors,
     })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::fs;
+    use crate::{
+        config::{DownloadConfig, ErrorPolicy, OutputSharding, ResultSink},
+        image_processor::{output_name, output_path, NoopImageProcessor},
+        manifest::{read_manifest, MANIFEST_FILENAME},
+        metrics::{MetricsCollector, PER_IMAGE_FILENAME},
+        test_support::jpeg_bytes,
+        url_generator::UrlTemplate,
+    };
+    use image::DynamicImage;
+    use std::{fs, sync::Mutex};
+    use tokio::time::sleep;
+    use wiremock::{
+        matchers::{any, path, path_regex},
+        Mock, MockServer, ResponseTemplate,
+    };
 
     #[tokio::test]
     async fn streams_images() {
         let output = Path::new("test_output_streaming");
         fs::create_dir_all(output).unwrap();
 
-        let stats = process_streaming(10, output, 3, 5, 5).await.unwrap();
+        let pipeline = StreamingPipeline::builder()
+            .download_concurrency(3)
+            .process_concurrency(5)
+            .channel_capacity(5)
+            .output_dir(output.to_path_buf())
+            .build()
+            .unwrap();
+        let stats = pipeline.run(10).await.unwrap();
 
         assert_eq!(stats.total_images, 10);
+        assert_eq!(stats.streaming_concurrency, 5);
         assert!(stats.total_time_ms > 0);
 
         fs::remove_dir_all(output).unwrap();
     }
+
+    #[test]
+    fn round_trips_stats() {
+        let stats = StreamingStats {
+            total_images: 9,
+            cancelled: true,
+            dead_letters: vec![ProcessingError::Cancelled {
+                url: "https://example.com/9.jpg".to_string(),
+            }],
+            ..Default::default()
+        };
+        let json = serde_json::to_string(&stats).unwrap();
+        let loaded: StreamingStats = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.total_images, 9);
+        assert!(loaded.cancelled);
+        assert_eq!(loaded.dead_letters, stats.dead_letters);
+    }
+
+    #[test]
+    fn validates_pipeline_builder() {
+        assert!(StreamingPipeline::builder().build().is_err());
+        assert!(StreamingPipeline::builder()
+            .output_dir(PathBuf::from("unused"))
+            .channel_capacity(0)
+            .build()
+            .is_err());
+
+        let pipeline = StreamingPipeline::builder()
+            .download_concurrency(4)
+            .channel_capacity(8)
+            .output_dir(PathBuf::from("unused"))
+            .build()
+            .unwrap();
+        assert_eq!(pipeline.config.download_concurrency, 4);
+        assert_eq!(pipeline.config.download_channel_capacity, 8);
+        assert_eq!(pipeline.config.process_channel_capacity, 8);
+    }
+
+    #[tokio::test]
+    async fn saves_image_processor_output() {
+        let server = MockServer::start().await;
+        let body = jpeg_bytes(64, 48);
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(body.clone()))
+            .mount(&server)
+            .await;
+        let output = PathBuf::from("test_output_noop_processor");
+        fs::create_dir_all(&output).unwrap();
+
+        let config = ProcessorConfig {
+            url_template: Some(UrlTemplate::Custom(format!("{}/{{seed}}", server.uri()))),
+            ..Default::default()
+        };
+        let stats = StreamingPipeline::builder()
+            .config(config.clone())
+            .image_processor(Box::new(NoopImageProcessor))
+            .output_dir(output.clone())
+            .build()
+            .unwrap()
+            .run(3)
+            .await
+            .unwrap();
+
+        assert_eq!(stats.total_images, 3);
+        let url = format!("{}/0", server.uri());
+        assert_eq!(fs::read(output_path(&url, &output, &config).unwrap()).unwrap(), body);
+
+        fs::remove_dir_all(&output).unwrap();
+    }
+
+    #[tokio::test]
+    async fn streams_urls_skipping_saved_images() {
+        let server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(jpeg_bytes(64, 48)))
+            .mount(&server)
+            .await;
+        let output = PathBuf::from("test_output_url_stream");
+        fs::create_dir_all(&output).unwrap();
+
+        let config = ProcessorConfig {
+            skip_existing: true,
+            warmup_count: 1,
+            ..Default::default()
+        };
+        let urls: Vec<String> = (0..3).map(|i| format!("{}/{}", server.uri(), i)).collect();
+        fs::write(output_path(&urls[1], &output, &config).unwrap(), b"saved").unwrap();
+        let pipeline = StreamingPipeline::builder()
+            .config(config.clone())
+            .output_dir(output.clone())
+            .build()
+            .unwrap();
+
+        let stats = pipeline.run_stream(stream::iter(urls.clone())).await.unwrap();
+        assert_eq!(stats.total_images, 2);
+        assert_eq!(stats.skipped_count, 1);
+        for url in [&urls[0], &urls[2]] {
+            assert!(output_path(url, &output, &config).unwrap().exists());
+        }
+
+        // Nothing left once every URL is saved
+        let stats = pipeline.run_stream(stream::iter(urls)).await.unwrap();
+        assert_eq!(stats.total_images, 0);
+        assert_eq!(stats.skipped_count, 3);
+
+        fs::remove_dir_all(&output).unwrap();
+    }
+
+    #[tokio::test]
+    async fn shards_saved_images() {
+        let output = Path::new("test_output_sharded");
+        fs::create_dir_all(output).unwrap();
+
+        let config = ProcessorConfig {
+            sharding: Some(OutputSharding { depth: 2, chars: 2 }),
+            ..Default::default()
+        };
+        let (tx, rx) = mpsc::channel(100);
+        for i in 0..100 {
+            tx.send(ProcessedImage {
+                url: format!("https://example.com/{}.jpg", i),
+                image: DynamicImage::new_rgb8(8, 8).into(),
+                download_ms: 0,
+                time_to_first_byte_ms: 0,
+                time_to_last_byte_ms: 0,
+                resize_ms: 0,
+                sharpen_ms: 0,
+                in_flight: None,
+                compression: None,
+                sequence: 0,
+            })
+            .await
+            .unwrap();
+        }
+        drop(tx);
+
+        let writer = SinkWriter::directory(output);
+        save_stage(rx, &writer, 1, &config, Instant::now(), &ProgressBar::hidden())
+            .await
+            .unwrap();
+
+        let top_level_dirs = fs::read_dir(output)
+            .unwrap()
+            .filter(|entry| entry.as_ref().unwrap().path().is_dir())
+            .count();
+        assert!(top_level_dirs >= 2);
+
+        fs::remove_dir_all(output).unwrap();
+    }
+
+    #[tokio::test]
+    async fn saves_with_concurrent_workers() {
+        let output = Path::new("test_output_save_concurrency");
+        fs::create_dir_all(output).unwrap();
+
+        let count = 50;
+        let (tx, rx) = mpsc::channel(8);
+        spawn(async move {
+            for i in 0..count {
+                tx.send(ProcessedImage {
+                    url: format!("https://example.com/{}.jpg", i),
+                    image: DynamicImage::new_rgb8(8, 8).into(),
+                    download_ms: 4,
+                    time_to_first_byte_ms: 0,
+                    time_to_last_byte_ms: 0,
+                    resize_ms: 2,
+                    sharpen_ms: 0,
+                    in_flight: None,
+                    compression: None,
+                    sequence: 0,
+                })
+                .await
+                .unwrap();
+            }
+        });
+
+        let writer = SinkWriter::directory(output);
+        let config = ProcessorConfig::default();
+        let summary = save_stage(rx, &writer, 4, &config, Instant::now(), &ProgressBar::hidden())
+            .await
+            .unwrap();
+
+        assert_eq!(summary.images, count);
+        assert_eq!(summary.avg_download_ms, 4);
+        assert_eq!(fs::read_dir(output).unwrap().count(), count);
+        for i in 0..count {
+            let url = format!("https://example.com/{}.jpg", i);
+            assert!(output_path(&url, output, &config).unwrap().exists());
+        }
+
+        fs::remove_dir_all(output).unwrap();
+    }
+
+    #[tokio::test]
+    async fn saves_into_zip_archive() {
+        let archive = PathBuf::from("test_output_streaming.zip");
+        let (tx, rx) = mpsc::channel(10);
+        for i in 0..10 {
+            tx.send(ProcessedImage {
+                url: format!("https://example.com/{}.jpg", i),
+                image: DynamicImage::new_rgb8(8, 8).into(),
+                download_ms: 0,
+                time_to_first_byte_ms: 0,
+                time_to_last_byte_ms: 0,
+                resize_ms: 0,
+                sharpen_ms: 0,
+                in_flight: None,
+                compression: None,
+                sequence: 0,
+            })
+            .await
+            .unwrap();
+        }
+        drop(tx);
+
+        let writer = OutputSink::ZipArchive(archive.clone()).open().unwrap();
+        let config = ProcessorConfig::default();
+        save_stage(rx, &writer, 1, &config, Instant::now(), &ProgressBar::hidden())
+            .await
+            .unwrap();
+        writer.finish().unwrap();
+
+        let zip = zip::ZipArchive::new(fs::File::open(&archive).unwrap()).unwrap();
+        assert_eq!(zip.len(), 10);
+        let name = output_name("https://example.com/0.jpg", &config);
+        assert!(zip.index_for_path(name).is_some());
+
+        fs::remove_file(&archive).unwrap();
+    }
+
+    #[tokio::test]
+    async fn writes_manifest() {
+        let output = Path::new("test_output_manifest_stage");
+        fs::create_dir_all(output).unwrap();
+
+        let config = ProcessorConfig {
+            output_manifest: true,
+            ..Default::default()
+        };
+        let count = 10;
+        let (tx, rx) = mpsc::channel(count);
+        for i in 0..count {
+            tx.send(ProcessedImage {
+                url: format!("https://example.com/{}.jpg", i),
+                image: DynamicImage::new_rgb8(8, 8).into(),
+                download_ms: 5,
+                time_to_first_byte_ms: 0,
+                time_to_last_byte_ms: 0,
+                resize_ms: 2,
+                sharpen_ms: 0,
+                in_flight: None,
+                compression: None,
+                sequence: 0,
+            })
+            .await
+            .unwrap();
+        }
+        drop(tx);
+
+        let writer = SinkWriter::directory(output);
+        save_stage(rx, &writer, 1, &config, Instant::now(), &ProgressBar::hidden())
+            .await
+            .unwrap();
+
+        assert!(fs::read_dir(output)
+            .unwrap()
+            .all(|entry| entry.unwrap().path().extension().unwrap() != "tmp"));
+
+        let manifest_path = output.join(MANIFEST_FILENAME);
+        let contents = fs::read_to_string(&manifest_path).unwrap();
+        assert_eq!(contents.lines().count(), count);
+        assert!(!output.join(PER_IMAGE_FILENAME).exists());
+
+        let collector = MetricsCollector::from_manifest(&manifest_path).unwrap();
+        assert_eq!(collector.summary_statistics().total_images_processed, count);
+
+        fs::remove_dir_all(output).unwrap();
+    }
+
+    #[tokio::test]
+    async fn writes_per_image_csv() {
+        let output = Path::new("test_output_per_image");
+        fs::create_dir_all(output).unwrap();
+
+        let config = ProcessorConfig {
+            per_image: true,
+            ..Default::default()
+        };
+        let (tx, rx) = mpsc::channel(3);
+        for i in 0..3 {
+            tx.send(ProcessedImage {
+                url: format!("https://example.com/{}.jpg", i),
+                image: DynamicImage::new_rgb8(8, 8).into(),
+                download_ms: 10 + i,
+                time_to_first_byte_ms: 0,
+                time_to_last_byte_ms: 0,
+                resize_ms: 2,
+                sharpen_ms: 0,
+                in_flight: None,
+                compression: None,
+                sequence: 0,
+            })
+            .await
+            .unwrap();
+        }
+        drop(tx);
+
+        let writer = SinkWriter::directory(output);
+        save_stage(rx, &writer, 1, &config, Instant::now(), &ProgressBar::hidden())
+            .await
+            .unwrap();
+
+        let contents = fs::read_to_string(output.join(PER_IMAGE_FILENAME)).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0], "url,download_ms,resize_ms,bytes");
+        let fields: Vec<&str> = lines[3].split(',').collect();
+        assert_eq!(fields[..3], ["https://example.com/2.jpg", "12", "2"]);
+        assert!(fields[3].parse::<u64>().unwrap() > 0);
+
+        fs::remove_dir_all(output).unwrap();
+    }
+
+    #[tokio::test]
+    async fn sends_results_to_sink() {
+        let output = Path::new("test_output_sink");
+        fs::create_dir_all(output).unwrap();
+
+        let received = Arc::new(Mutex::new(vec![]));
+        let sink_received = Arc::clone(&received);
+        let config = ProcessorConfig {
+            result_sink: Some(ResultSink::new(move |result: ImageResult| {
+                let received = Arc::clone(&sink_received);
+                async move {
+                    sleep(Duration::from_millis(5)).await;
+                    received.lock().unwrap().push(result.url);
+                }
+            })),
+            ..Default::default()
+        };
+        let (tx, rx) = mpsc::channel(3);
+        for i in 0..3 {
+            tx.send(ProcessedImage {
+                url: format!("https://example.com/{}.jpg", i),
+                image: DynamicImage::new_rgb8(8, 8).into(),
+                download_ms: 0,
+                time_to_first_byte_ms: 0,
+                time_to_last_byte_ms: 0,
+                resize_ms: 0,
+                sharpen_ms: 0,
+                in_flight: None,
+                compression: None,
+                sequence: 0,
+            })
+            .await
+            .unwrap();
+        }
+        drop(tx);
+
+        let writer = SinkWriter::directory(output);
+        let summary = save_stage(rx, &writer, 1, &config, Instant::now(), &ProgressBar::hidden())
+            .await
+            .unwrap();
+
+        assert_eq!(received.lock().unwrap().len(), 3);
+        assert!(summary.avg_sink_ms >= 5);
+        assert_eq!(fs::read_dir(output).unwrap().count(), 0);
+
+        fs::remove_dir_all(output).unwrap();
+    }
+
+    #[tokio::test]
+    async fn records_first_and_last_save() {
+        let output = Path::new("test_output_save_latency");
+        fs::create_dir_all(output).unwrap();
+
+        let start_time = Instant::now();
+        let (tx, rx) = mpsc::channel(1);
+        spawn(async move {
+            for i in 0..3 {
+                sleep(Duration::from_millis(20)).await;
+                tx.send(ProcessedImage {
+                    url: format!("https://example.com/{}.jpg", i),
+                    image: DynamicImage::new_rgb8(8, 8).into(),
+                    download_ms: 0,
+                    time_to_first_byte_ms: 0,
+                    time_to_last_byte_ms: 0,
+                    resize_ms: 0,
+                    sharpen_ms: 0,
+                    in_flight: None,
+                    compression: None,
+                    sequence: 0,
+                })
+                .await
+                .unwrap();
+            }
+        });
+
+        let summary = save_stage(
+            rx,
+            &SinkWriter::directory(output),
+            1,
+            &ProcessorConfig::default(),
+            start_time,
+            &ProgressBar::hidden(),
+        )
+        .await
+        .unwrap();
+        let total_time_ms = start_time.elapsed().as_millis() as u64;
+
+        assert!(summary.first_save_ms >= 20);
+        assert!(summary.first_save_ms < summary.last_save_ms);
+        assert!(summary.first_save_ms < total_time_ms);
+
+        fs::remove_dir_all(output).unwrap();
+    }
+
+    #[tokio::test]
+    async fn collects_errors_and_continues() {
+        let server = MockServer::start().await;
+        Mock::given(path_regex("^/good/"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(jpeg_bytes(16, 16)))
+            .mount(&server)
+            .await;
+        Mock::given(path("/bad"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("not an image"))
+            .mount(&server)
+            .await;
+
+        let output = Path::new("test_output_streaming_collect_errors");
+        fs::create_dir_all(output).unwrap();
+
+        let urls: Vec<String> = ["good/1", "good/2", "bad", "good/3", "good/4"]
+            .iter()
+            .map(|path| format!("{}/{}", server.uri(), path))
+            .collect();
+        let config = ProcessorConfig {
+            url_template: Some(UrlTemplate::Fixed(urls.clone())),
+            error_policy: ErrorPolicy::CollectAndContinue,
+            ..Default::default()
+        };
+
+        let stats = run_streaming(urls.len(), &OutputSink::Directory(output.into()), &config)
+            .await
+            .unwrap();
+
+        assert_eq!(stats.total_images, 4);
+        assert_eq!(stats.invalid_content_rejections, 1);
+        assert_eq!(stats.errors.len(), 1);
+        assert_eq!(stats.errors[0].0, urls[2]);
+        assert_eq!(fs::read_dir(output).unwrap().count(), 4);
+
+        fs::remove_dir_all(output).unwrap();
+    }
+
+    #[tokio::test]
+    async fn applies_error_policy_to_failed_downloads() {
+        let server = MockServer::start().await;
+        Mock::given(path("/missing"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&server)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(jpeg_bytes(8, 8)))
+            .mount(&server)
+            .await;
+
+        let output = Path::new("test_output_streaming_error_policy");
+        fs::create_dir_all(output).unwrap();
+        let sink = OutputSink::Directory(output.into());
+        let urls: Vec<String> = ["1", "missing", "2"]
+            .iter()
+            .map(|path| format!("{}/{}", server.uri(), path))
+            .collect();
+
+        let config = ProcessorConfig {
+            url_template: Some(UrlTemplate::Fixed(urls.clone())),
+            error_policy: ErrorPolicy::CollectAndContinue,
+            ..Default::default()
+        };
+        let stats = run_streaming(urls.len(), &sink, &config).await.unwrap();
+        assert_eq!(stats.total_images, 2);
+        assert_eq!(stats.failed_count, 1);
+        assert_eq!(stats.errors[0].0, urls[1]);
+
+        let config = ProcessorConfig {
+            error_policy: ErrorPolicy::FailFast,
+            ..config
+        };
+        let Err(err) = run_streaming(urls.len(), &sink, &config).await else {
+            panic!("run should fail under FailFast");
+        };
+        assert!(err.to_string().contains("missing"));
+
+        fs::remove_dir_all(output).unwrap();
+    }
+
+    #[tokio::test]
+    async fn counts_rejections_under_default_policy() {
+        let server = MockServer::start().await;
+        Mock::given(path("/text"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("not an image"))
+            .mount(&server)
+            .await;
+        Mock::given(path("/large"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("content-type", "image/jpeg")
+                    .set_body_bytes(vec![0u8; 64 * 1024]),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(any())
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("content-type", "image/jpeg")
+                    .set_body_bytes(jpeg_bytes(8, 8)),
+            )
+            .mount(&server)
+            .await;
+
+        let output = Path::new("test_output_streaming_default_rejections");
+        fs::create_dir_all(output).unwrap();
+        let urls: Vec<String> = ["1", "text", "large", "2"]
+            .iter()
+            .map(|path| format!("{}/{}", server.uri(), path))
+            .collect();
+        let config = ProcessorConfig {
+            url_template: Some(UrlTemplate::Fixed(urls.clone())),
+            verify_content_type: true,
+            max_image_bytes: Some(16 * 1024),
+            ..Default::default()
+        };
+        assert_eq!(config.error_policy, ErrorPolicy::FailFast);
+
+        let stats = run_streaming(urls.len(), &OutputSink::Directory(output.into()), &config)
+            .await
+            .unwrap();
+        assert_eq!(stats.total_images, 2);
+        assert_eq!(stats.invalid_content_rejections, 1);
+        assert_eq!(stats.oversized_rejections, 1);
+
+        fs::remove_dir_all(output).unwrap();
+    }
+
+    #[tokio::test]
+    async fn reports_failed_stages() {
+        let server = MockServer::start().await;
+        Mock::given(path("/bad"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("not an image"))
+            .mount(&server)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(jpeg_bytes(8, 8)))
+            .mount(&server)
+            .await;
+
+        let output = Path::new("test_output_streaming_failed_stages");
+        fs::create_dir_all(output).unwrap();
+        let sink = OutputSink::Directory(output.into());
+
+        // Nothing makes it to the save stage, which fails for having no images
+        let bad = vec![format!("{}/bad", server.uri())];
+        let config = ProcessorConfig {
+            url_template: Some(UrlTemplate::Fixed(bad.clone())),
+            error_policy: ErrorPolicy::CollectAndContinue,
+            ..Default::default()
+        };
+        let stats = run_streaming(1, &sink, &config).await.unwrap();
+        assert_eq!(stats.total_images, 0);
+        assert_eq!(stats.pipeline_errors.len(), 1);
+        assert!(matches!(
+            &stats.pipeline_errors[0],
+            PipelineError::Failed { stage, message }
+                if stage == "save" && message.contains("no images processed")
+        ));
+
+        // A panicking save worker fails its stage, and the run under FailFast
+        let good = vec![format!("{}/good", server.uri())];
+        let config = ProcessorConfig {
+            url_template: Some(UrlTemplate::Fixed(good)),
+            result_sink: Some(ResultSink::new(|_| async { panic!("sink exploded") })),
+            error_policy: ErrorPolicy::CollectAndContinue,
+            ..Default::default()
+        };
+        let stats = run_streaming(1, &sink, &config).await.unwrap();
+        assert_eq!(stats.pipeline_errors.len(), 1);
+        assert_eq!(stats.pipeline_errors[0].stage(), "save");
+        let message = stats.pipeline_errors[0].to_string();
+        assert!(message.contains("sink exploded"));
+        let config = ProcessorConfig {
+            error_policy: ErrorPolicy::FailFast,
+            ..config
+        };
+        let Err(err) = run_streaming(1, &sink, &config).await else {
+            panic!("run should fail under FailFast");
+        };
+        assert!(err.to_string().starts_with("save stage failed"));
+
+        fs::remove_dir_all(output).unwrap();
+    }
+
+    #[tokio::test]
+    async fn reports_stage_panics() {
+        let (errors, mut error_rx) = mpsc::channel(1);
+        let stage = spawn_stage("process", async { panic!("stage exploded") }, errors);
+
+        assert_eq!(stage.await.unwrap(), None::<()>);
+        assert_eq!(
+            error_rx.recv().await.unwrap(),
+            PipelineError::Panicked {
+                stage: "process".to_string(),
+                message: "stage exploded".to_string(),
+            }
+        );
+    }
+
+    fn sequenced_image(sequence: usize) -> ProcessedImage {
+        ProcessedImage {
+            url: format!("https://example.com/{}.jpg", sequence),
+            image: DynamicImage::new_rgb8(8, 8).into(),
+            download_ms: 0,
+            time_to_first_byte_ms: 0,
+            time_to_last_byte_ms: 0,
+            resize_ms: 0,
+            sharpen_ms: 0,
+            in_flight: None,
+            compression: None,
+            sequence,
+        }
+    }
+
+    #[tokio::test]
+    async fn reorders_by_sequence() {
+        let (input_tx, input_rx) = mpsc::channel(10);
+        let (output_tx, mut output_rx) = mpsc::channel(10);
+        // 4 never arrives, so 5 and 6 are only released once the input closes
+        for sequence in [2, 0, 3, 6, 1, 5] {
+            input_tx.send(sequenced_image(sequence)).await.unwrap();
+        }
+        drop(input_tx);
+
+        reorder(input_rx, output_tx).await;
+
+        let mut order = vec![];
+        while let Some(image) = output_rx.recv().await {
+            order.push(image.sequence);
+        }
+        assert_eq!(order, [0, 1, 2, 3, 5, 6]);
+    }
+
+    #[tokio::test]
+    async fn saves_in_url_order() {
+        let server = MockServer::start().await;
+        let count = 6;
+        // Earlier URLs answer later, so arrival order is the reverse of URL order
+        for i in 0..count {
+            Mock::given(path(format!("/{}", i)))
+                .respond_with(
+                    ResponseTemplate::new(200)
+                        .set_body_bytes(jpeg_bytes(16, 16))
+                        .set_delay(Duration::from_millis(40 * (count - i) as u64)),
+                )
+                .mount(&server)
+                .await;
+        }
+
+        let output = Path::new("test_output_streaming_ordered");
+        fs::create_dir_all(output).unwrap();
+
+        let urls: Vec<String> = (0..count).map(|i| format!("{}/{}", server.uri(), i)).collect();
+        let config = ProcessorConfig {
+            url_template: Some(UrlTemplate::Fixed(urls.clone())),
+            download_concurrency: count,
+            output_manifest: true,
+            ..Default::default()
+        };
+        let stats = process_streaming_ordered(count, output, &config).await.unwrap();
+        assert_eq!(stats.total_images, count);
+
+        let manifest = read_manifest(&output.join(MANIFEST_FILENAME)).unwrap();
+        let saved: Vec<_> = manifest.iter().map(|entry| entry.filename.clone()).collect();
+        let expected: Vec<_> = urls
+            .iter()
+            .map(|url| output_name(url, &config).display().to_string())
+            .collect();
+        assert_eq!(saved, expected);
+
+        fs::remove_dir_all(output).unwrap();
+    }
+
+    #[tokio::test]
+    async fn counts_rejected_small_images() {
+        let server = MockServer::start().await;
+        Mock::given(path_regex("^/small/"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(jpeg_bytes(16, 16)))
+            .mount(&server)
+            .await;
+        Mock::given(path_regex("^/large/"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(jpeg_bytes(200, 200)))
+            .mount(&server)
+            .await;
+
+        let output = Path::new("test_output_streaming_rejected");
+        fs::create_dir_all(output).unwrap();
+
+        let urls: Vec<String> = ["large/1", "small/1", "large/2", "small/2"]
+            .iter()
+            .map(|path| format!("{}/{}", server.uri(), path))
+            .collect();
+        let config = ProcessorConfig {
+            url_template: Some(UrlTemplate::Fixed(urls.clone())),
+            download: Some(DownloadConfig {
+                min_width: Some(100),
+                min_height: Some(100),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let stats = run_streaming(urls.len(), &OutputSink::Directory(output.into()), &config)
+            .await
+            .unwrap();
+
+        assert_eq!(stats.total_images, 2);
+        assert_eq!(stats.rejected_count, 2);
+        assert_eq!(fs::read_dir(output).unwrap().count(), 2);
+
+        fs::remove_dir_all(output).unwrap();
+    }
 }
@@ -1,53 +1,219 @@
-use std::sync::Arc;
+use std::{io::Cursor, sync::Arc};
 
 use anyhow::Result;
 use futures::future::join_all;
-use image::{load_from_memory, DynamicImage};
+use image::{load_from_memory, DynamicImage, GenericImageView, ImageReader};
 use tokio::{
+    spawn,
     sync::{mpsc, Semaphore},
     task::spawn_blocking,
     time::Instant,
 };
-use tracing::{debug, info};
+use tracing::{debug, info, instrument, warn};
 
-use crate::streaming::download::ImageData;
+use crate::{
+    compression::{decompress, ChannelCompression},
+    config::{DownloadConfig, ErrorPolicy, ProcessorConfig},
+    error::{DeadLetterQueue, ProcessingError},
+    image_processor::{resize_to, sharpen},
+    streaming::{download::ImageData, in_flight::InFlightPermit},
+};
+
+/// What the process stage hands to the save stage
+pub enum ProcessedOutput {
+    /// Resized image, encoded when it is saved
+    Image(DynamicImage),
+    /// Output of a configured [`ImageProcessor`](crate::image_processor::ImageProcessor),
+    /// saved as is
+    Encoded(Vec<u8>),
+}
+
+impl ProcessedOutput {
+    /// The image itself, decoding backend output if needed
+    pub fn into_image(self) -> Result<DynamicImage> {
+        match self {
+            ProcessedOutput::Image(image) => Ok(image),
+            ProcessedOutput::Encoded(bytes) => Ok(load_from_memory(&bytes)?),
+        }
+    }
+
+    /// Width and height, read from the header of backend output without decoding it
+    pub fn dimensions(&self) -> Result<(u32, u32)> {
+        match self {
+            ProcessedOutput::Image(image) => Ok(image.dimensions()),
+            ProcessedOutput::Encoded(bytes) => Ok(ImageReader::new(Cursor::new(bytes))
+                .with_guessed_format()?
+                .into_dimensions()?),
+        }
+    }
+}
+
+impl From<DynamicImage> for ProcessedOutput {
+    fn from(image: DynamicImage) -> Self {
+        ProcessedOutput::Image(image)
+    }
+}
 
 pub struct ProcessedImage {
     pub url: String,
-    pub image: DynamicImage,
+    pub image: ProcessedOutput,
     pub download_ms: u128,
+    /// Carried over from [`ImageData::time_to_first_byte_ms`]
+    pub time_to_first_byte_ms: u128,
+    /// Carried over from [`ImageData::time_to_last_byte_ms`]
+    pub time_to_last_byte_ms: u128,
     pub resize_ms: u128,
+    pub sharpen_ms: u128,
+    /// Carried over from the download stage, released once the image is saved
+    pub in_flight: Option<InFlightPermit>,
+    /// Channel compression applied to the downloaded bytes, if any
+    pub compression: Option<ChannelCompression>,
+    /// Carried over from [`ImageData::sequence`]
+    pub sequence: usize,
+}
+
+/// A decoded image smaller than [`DownloadConfig::min_width`] or
+/// [`DownloadConfig::min_height`], dropped instead of resized
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RejectedImage {
+    pub url: String,
+    pub actual_width: u32,
+    pub actual_height: u32,
+    pub reason: String,
 }
 
+/// Why a `width`×`height` image falls short of `download`'s minimum size, if it does
+fn size_rejection(width: u32, height: u32, download: &DownloadConfig) -> Option<String> {
+    let min_width = download.min_width.unwrap_or(0);
+    let min_height = download.min_height.unwrap_or(0);
+    (width < min_width || height < min_height).then(|| {
+        format!(
+            "{}x{} is below the minimum of {}x{}",
+            width, height, min_width, min_height
+        )
+    })
+}
+
+/// Decode, resize and sharpen every image from `input`. Under
+/// [`ErrorPolicy::CollectAndContinue`] images that fail are pushed to `dead_letters`. Decoded
+/// images below the configured minimum size are sent to `rejected` instead of `output`.
+#[instrument(skip_all, fields(concurrency = process_concurrency))]
 pub async fn process_stage(
     mut input: mpsc::Receiver<ImageData>,
     output: mpsc::Sender<ProcessedImage>,
+    rejected: mpsc::Sender<RejectedImage>,
     process_concurrency: usize,
+    config: &ProcessorConfig,
+    dead_letters: &DeadLetterQueue,
 ) -> Result<()> {
+    let download_config = config.download.unwrap_or_default();
+    let collect_errors = config.error_policy == ErrorPolicy::CollectAndContinue;
     let mut handles = vec![];
     let mut processed = 0usize;
     let sem = Arc::new(Semaphore::new(process_concurrency));
 
     info!("process stage started");
-    while let Some(img_data) = input.recv().await {
+    while let Some(mut img_data) = input.recv().await {
         let local_sender = output.clone();
+        let rejected_sender = rejected.clone();
         processed += 1;
         let permit = Arc::clone(&sem).acquire_owned().await.unwrap();
+        let sharpen_config = config.sharpen;
+        let resize_mode = config.resize_mode;
+        let resize = config.resize.unwrap_or_default();
+        let dead_letters = dead_letters.clone();
         debug!(url = %img_data.url, "processing image");
 
+        if let Some(processor) = config.image_processor.clone() {
+            handles.push(spawn(async move {
+                let _permit = permit;
+                let bytes = match &img_data.compression {
+                    Some(stats) => decompress(&img_data.bytes, stats).unwrap(),
+                    None => std::mem::take(&mut img_data.bytes),
+                };
+                let processed = match processor.process(&bytes).await {
+                    Ok(processed) => processed,
+                    Err(e) => {
+                        warn!(url = %img_data.url, error = %e, "image processor failed");
+                        if collect_errors {
+                            dead_letters.push(ProcessingError::NotAnImage {
+                                url: img_data.url,
+                                reason: e.to_string(),
+                            });
+                        }
+                        return;
+                    }
+                };
+
+                let processed_img_data = ProcessedImage {
+                    url: img_data.url,
+                    image: ProcessedOutput::Encoded(processed.bytes),
+                    download_ms: img_data.download_ms,
+                    time_to_first_byte_ms: img_data.time_to_first_byte_ms,
+                    time_to_last_byte_ms: img_data.time_to_last_byte_ms,
+                    resize_ms: (processed.decode_ms + processed.resize_ms) as u128,
+                    sharpen_ms: 0,
+                    in_flight: img_data.in_flight,
+                    compression: img_data.compression,
+                    sequence: img_data.sequence,
+                };
+                local_sender.send(processed_img_data).await.unwrap();
+            }));
+            continue;
+        }
+
         let handle = spawn_blocking(move || {
             let _permit = permit;
             let start_resize = Instant::now();
-            let original_img = load_from_memory(&img_data.bytes).unwrap();
-            let resized_img =
-                original_img.resize_exact(256, 256, image::imageops::FilterType::Lanczos3);
+            let original_img = match &img_data.compression {
+                Some(stats) => load_from_memory(&decompress(&img_data.bytes, stats).unwrap()),
+                None => load_from_memory(&img_data.bytes),
+            };
+            let original_img = match original_img {
+                Ok(img) => img,
+                Err(e) if collect_errors => {
+                    warn!(url = %img_data.url, error = %e, "failed to decode image, continuing");
+                    dead_letters.push(ProcessingError::NotAnImage {
+                        url: img_data.url,
+                        reason: e.to_string(),
+                    });
+                    return;
+                }
+                Err(e) => panic!("failed to decode {}: {}", img_data.url, e),
+            };
+            let (width, height) = original_img.dimensions();
+            if let Some(reason) = size_rejection(width, height, &download_config) {
+                warn!(url = %img_data.url, width, height, reason = %reason, "image too small, rejecting");
+                let rejection = RejectedImage {
+                    url: img_data.url,
+                    actual_width: width,
+                    actual_height: height,
+                    reason,
+                };
+                rejected_sender.blocking_send(rejection).unwrap();
+                return;
+            }
+            let resized_img = resize_to(&original_img, &resize, resize_mode);
             let resize_time = start_resize.elapsed().as_millis();
 
+            let start_sharpen = Instant::now();
+            let final_img = match &sharpen_config {
+                Some(sharpen_config) => sharpen(&resized_img, sharpen_config),
+                None => resized_img,
+            };
+            let sharpen_time = start_sharpen.elapsed().as_millis();
+
             let processed_img_data = ProcessedImage {
                 url: img_data.url,
-                image: resized_img,
+                image: final_img.into(),
                 download_ms: img_data.download_ms,
+                time_to_first_byte_ms: img_data.time_to_first_byte_ms,
+                time_to_last_byte_ms: img_data.time_to_last_byte_ms,
                 resize_ms: resize_time,
+                sharpen_ms: sharpen_time,
+                in_flight: img_data.in_flight,
+                compression: img_data.compression,
+                sequence: img_data.sequence,
             };
 
             local_sender.blocking_send(processed_img_data).unwrap();
@@ -66,38 +232,107 @@ pub async fn process_stage(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_support::jpeg_bytes;
+    use reqwest::header::HeaderMap;
 
     #[tokio::test]
     async fn processes_images() {
         let (input_tx, input_rx) = mpsc::channel(10);
         let (output_tx, mut output_rx) = mpsc::channel(10);
+        let (rejected_tx, _rejected_rx) = mpsc::channel(10);
 
         tokio::spawn(async move {
-            let bytes = reqwest::get("https://picsum.photos/seed/1/400/300")
-                .await
-                .unwrap()
-                .bytes()
-                .await
-                .unwrap()
-                .to_vec();
-
             input_tx
                 .send(ImageData {
                     url: "test".to_string(),
-                    bytes,
+                    bytes: jpeg_bytes(400, 300),
                     download_ms: 0,
+                    time_to_first_byte_ms: 0,
+                    time_to_last_byte_ms: 0,
+                    connection_retries: 0,
+                    jitter_applied_ms: 0,
+                    server_timing: None,
+                    in_flight: None,
+                    compression: None,
+                    content_type: None,
+                    sequence: 0,
+                    headers: HeaderMap::new(),
+                    from_cache: false,
                 })
                 .await
                 .unwrap();
         });
 
         tokio::spawn(async move {
-            process_stage(input_rx, output_tx, 10).await.unwrap();
+            let dead_letters = DeadLetterQueue::new();
+            process_stage(
+                input_rx,
+                output_tx,
+                rejected_tx,
+                10,
+                &ProcessorConfig::default(),
+                &dead_letters,
+            )
+            .await
+            .unwrap();
         });
 
-        if let Some(processed) = output_rx.recv().await {
-            assert_eq!(processed.image.width(), 256);
-            assert_eq!(processed.image.height(), 256);
+        let processed = output_rx.recv().await.unwrap();
+        let image = processed.image.into_image().unwrap();
+        assert_eq!(image.width(), 256);
+        assert_eq!(image.height(), 256);
+    }
+
+    #[tokio::test]
+    async fn rejects_small_images() {
+        let (input_tx, input_rx) = mpsc::channel(10);
+        let (output_tx, mut output_rx) = mpsc::channel(10);
+        let (rejected_tx, mut rejected_rx) = mpsc::channel(10);
+
+        for (url, width, height) in [("small", 400, 300), ("large", 800, 600)] {
+            input_tx
+                .send(ImageData {
+                    url: url.to_string(),
+                    bytes: jpeg_bytes(width, height),
+                    download_ms: 0,
+                    time_to_first_byte_ms: 0,
+                    time_to_last_byte_ms: 0,
+                    connection_retries: 0,
+                    jitter_applied_ms: 0,
+                    server_timing: None,
+                    in_flight: None,
+                    compression: None,
+                    content_type: None,
+                    sequence: 0,
+                    headers: HeaderMap::new(),
+                    from_cache: false,
+                })
+                .await
+                .unwrap();
         }
+        drop(input_tx);
+
+        let config = ProcessorConfig {
+            download: Some(DownloadConfig {
+                min_width: Some(500),
+                min_height: Some(500),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let dead_letters = DeadLetterQueue::new();
+        process_stage(input_rx, output_tx, rejected_tx, 10, &config, &dead_letters)
+            .await
+            .unwrap();
+
+        assert_eq!(output_rx.recv().await.unwrap().url, "large");
+        assert!(output_rx.recv().await.is_none());
+        let rejection = rejected_rx.recv().await.unwrap();
+        assert_eq!(rejection.url, "small");
+        assert_eq!(
+            (rejection.actual_width, rejection.actual_height),
+            (400, 300)
+        );
+        assert!(rejected_rx.recv().await.is_none());
     }
 }
@@ -0,0 +1,49 @@
+// src/streaming/rate_limit.rs
+
+use std::{fmt, sync::Arc, time::Duration};
+
+use tokio::{
+    sync::Mutex,
+    time::{self, Interval, MissedTickBehavior},
+};
+
+/// Leaky bucket letting one request start every `1 / per_second` seconds, independent of
+/// how many may run at once. Clones share the same bucket.
+#[derive(Clone)]
+pub struct RateLimiter {
+    period: Duration,
+    // Created on first use, since an interval needs a running Tokio runtime
+    interval: Arc<Mutex<Option<Interval>>>,
+}
+
+impl RateLimiter {
+    /// Rates that aren't positive are treated as the slowest representable rate
+    pub fn new(per_second: f64) -> Self {
+        RateLimiter {
+            period: Duration::from_secs_f64(1.0 / per_second.max(f64::EPSILON)),
+            interval: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Wait until the next request may start. The first one goes through immediately.
+    pub async fn acquire(&self) {
+        let mut interval = self.interval.lock().await;
+        interval
+            .get_or_insert_with(|| {
+                let mut interval = time::interval(self.period);
+                // A quiet spell doesn't bank requests for a burst afterwards
+                interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                interval
+            })
+            .tick()
+            .await;
+    }
+}
+
+impl fmt::Debug for RateLimiter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RateLimiter")
+            .field("period", &self.period)
+            .finish()
+    }
+}
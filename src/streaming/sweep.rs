@@ -0,0 +1,77 @@
+// src/streaming/sweep.rs
+
+use std::{fs, path::Path};
+
+use anyhow::Result;
+use tracing::info;
+
+use crate::{
+    config::ProcessorConfig,
+    streaming::pipeline::{StreamingPipeline, StreamingStats},
+};
+
+/// Run the streaming pipeline over the same `count` images once per channel capacity, each
+/// into its own `cap-{N}` directory under `output_dir`. Returns one stats entry per capacity,
+/// in the order given.
+pub async fn sweep_channel_capacity(
+    count: usize,
+    capacities: &[usize],
+    output_dir: &Path,
+    config: &ProcessorConfig,
+) -> Result<Vec<StreamingStats>> {
+    let mut results = Vec::with_capacity(capacities.len());
+    for &capacity in capacities {
+        let dir = output_dir.join(format!("cap-{}", capacity));
+        fs::create_dir_all(&dir)?;
+        let stats = StreamingPipeline::builder()
+            .config(config.clone())
+            .channel_capacity(capacity)
+            .output_dir(dir)
+            .build()?
+            .run(count)
+            .await?;
+        info!(
+            capacity,
+            total_time_ms = stats.total_time_ms,
+            peak_memory_mb = stats.peak_memory_mb,
+            "capacity sweep run"
+        );
+        results.push(stats);
+    }
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use wiremock::{matchers::any, Mock, MockServer, ResponseTemplate};
+
+    use super::*;
+    use crate::{test_support::jpeg_bytes, url_generator::UrlTemplate};
+
+    #[tokio::test]
+    async fn runs_once_per_capacity() {
+        let server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(jpeg_bytes(16, 16)))
+            .mount(&server)
+            .await;
+
+        let output = Path::new("test_output_capacity_sweep");
+        let config = ProcessorConfig {
+            url_template: Some(UrlTemplate::Custom(format!("{}/{{seed}}", server.uri()))),
+            ..Default::default()
+        };
+        let results = sweep_channel_capacity(4, &[1, 8], output, &config)
+            .await
+            .unwrap();
+
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(|stats| stats.total_images == 4));
+        for capacity in [1, 8] {
+            let dir = output.join(format!("cap-{}", capacity));
+            assert_eq!(fs::read_dir(dir).unwrap().count(), 4);
+        }
+
+        fs::remove_dir_all(output).unwrap();
+    }
+}
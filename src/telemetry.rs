@@ -0,0 +1,30 @@
+// src/telemetry.rs
+
+use anyhow::Result;
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_otlp::SpanExporter;
+use opentelemetry_sdk::{trace::SdkTracerProvider, Resource};
+use tracing::{Level, Subscriber};
+use tracing_subscriber::{filter::Targets, registry::LookupSpan, Layer};
+
+/// Batches spans to an OTLP/HTTP collector such as Jaeger or Tempo. The endpoint comes from
+/// `OTEL_EXPORTER_OTLP_ENDPOINT`, defaulting to `http://localhost:4318`. Call
+/// [`SdkTracerProvider::shutdown`] before exiting so the last batch is flushed.
+pub fn otlp_provider() -> Result<SdkTracerProvider> {
+    let exporter = SpanExporter::builder().with_http().build()?;
+    Ok(SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(Resource::builder().with_service_name("flux").build())
+        .build())
+}
+
+/// Layer forwarding this crate's spans and events, down to `debug`, to `provider`.
+/// Dependencies are left out so the exporter's own HTTP requests aren't traced.
+pub fn otel_layer<S>(provider: &SdkTracerProvider) -> impl Layer<S>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    tracing_opentelemetry::layer()
+        .with_tracer(provider.tracer("flux"))
+        .with_filter(Targets::new().with_target("flux", Level::DEBUG))
+}
@@ -0,0 +1,14 @@
+// src/test_support.rs
+
+use std::io::Cursor;
+
+use image::{DynamicImage, ImageFormat};
+
+/// Encode a solid `width`×`height` image as JPEG
+pub fn jpeg_bytes(width: u32, height: u32) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    DynamicImage::new_rgb8(width, height)
+        .write_to(&mut Cursor::new(&mut bytes), ImageFormat::Jpeg)
+        .unwrap();
+    bytes
+}
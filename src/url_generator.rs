@@ -1,24 +1,431 @@
 // src/url_generator.rs
 
+use std::{
+    collections::HashSet,
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use futures::{stream, Stream, StreamExt};
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tracing::warn;
+
+use crate::config::ProcessorConfig;
+
+/// Anything that can hand a processor the list of image URLs to work on
+pub trait ImageSource {
+    fn urls(&self) -> Vec<String>;
+}
+
+/// Where a processor reads one image from
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum InputSource {
+    /// Fetched with a GET request
+    Http(String),
+    /// Read from disk
+    LocalFile(PathBuf),
+}
+
+impl InputSource {
+    /// `http://` and `https://` URLs are fetched; anything else is a path, with any
+    /// `file://` prefix stripped
+    pub fn parse(location: &str) -> Self {
+        if location.starts_with("http://") || location.starts_with("https://") {
+            InputSource::Http(location.to_string())
+        } else {
+            let path = location.strip_prefix("file://").unwrap_or(location);
+            InputSource::LocalFile(PathBuf::from(path))
+        }
+    }
+}
+
+impl fmt::Display for InputSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InputSource::Http(url) => f.write_str(url),
+            InputSource::LocalFile(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+/// Image format requested through the URL's file extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UrlImageFormat {
+    Jpeg,
+    Png,
+    Webp,
+}
+
+impl UrlImageFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            UrlImageFormat::Jpeg => "jpg",
+            UrlImageFormat::Png => "png",
+            UrlImageFormat::Webp => "webp",
+        }
+    }
+}
+
+/// Where generated URLs point
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UrlTemplate {
+    /// Lorem Picsum images of this size, seeded by index
+    Picsum { width: u32, height: u32 },
+    /// Any server: `{seed}` is replaced by the index, `{width}` and `{height}` by 800x600
+    /// unless filled in through [`UrlTemplate::custom`]
+    Custom(String),
+    /// Exactly these URLs, cut short at the requested count
+    Fixed(Vec<String>),
+    /// One URL per line of this file, skipping blank lines and `#` comments, read each time
+    /// URLs are generated
+    File(PathBuf),
+}
+
+impl Default for UrlTemplate {
+    fn default() -> Self {
+        UrlTemplate::Picsum {
+            width: 800,
+            height: 600,
+        }
+    }
+}
+
+impl UrlTemplate {
+    /// A custom template with `{width}` and `{height}` filled in, leaving `{seed}` per URL
+    pub fn custom(template: &str, width: u32, height: u32) -> Self {
+        UrlTemplate::Custom(fill_dimensions(template, width, height))
+    }
+}
+
+fn fill_dimensions(template: &str, width: u32, height: u32) -> String {
+    template
+        .replace("{width}", &width.to_string())
+        .replace("{height}", &height.to_string())
+}
+
+#[derive(Clone)]
 pub struct UrlGenerator {
     count: usize,
+    format: Option<UrlImageFormat>,
+    quality: Option<u8>,
+    template: UrlTemplate,
+    deduplicate: bool,
+    shuffle_seed: Option<u64>,
 }
 
 impl UrlGenerator {
     pub fn new(count: usize) -> Self {
-        UrlGenerator { count }
+        Self::from_template(count, UrlTemplate::default())
+    }
+
+    pub fn from_template(count: usize, template: UrlTemplate) -> Self {
+        UrlGenerator {
+            count,
+            format: None,
+            quality: None,
+            template,
+            deduplicate: false,
+            shuffle_seed: None,
+        }
+    }
+
+    /// `count` URLs from the run's `url_template`, Picsum 800x600 when unset
+    pub fn for_config(count: usize, config: &ProcessorConfig) -> Self {
+        Self::from_template(count, config.url_template.clone().unwrap_or_default())
+    }
+
+    /// A generator that returns exactly these URLs
+    pub fn from_iterator<I: IntoIterator<Item = String>>(iter: I) -> Self {
+        let urls: Vec<String> = iter.into_iter().collect();
+        Self::from_template(urls.len(), UrlTemplate::Fixed(urls))
+    }
+
+    /// Every URL in `path`, one per line, skipping blank lines and `#` comments. Only
+    /// checks the file can be opened; it's read when URLs are generated, and line by line
+    /// through [`UrlGenerator::stream`].
+    pub fn from_file(path: &Path) -> Result<Self> {
+        fs::File::open(path)?;
+        Ok(Self::from_template(
+            usize::MAX,
+            UrlTemplate::File(path.to_path_buf()),
+        ))
+    }
+
+    /// Every file under `dir`, including subdirectories, whose extension is one of
+    /// `extensions` (ignoring case), sorted by path
+    pub fn from_directory(dir: &Path, extensions: &[&str]) -> Result<Self> {
+        let mut files = vec![];
+        let mut dirs = vec![dir.to_path_buf()];
+        while let Some(dir) = dirs.pop() {
+            for entry in fs::read_dir(&dir)? {
+                let path = entry?.path();
+                if path.is_dir() {
+                    dirs.push(path);
+                } else if path
+                    .extension()
+                    .and_then(|ext| ext.to_str())
+                    .is_some_and(|ext| extensions.iter().any(|want| want.eq_ignore_ascii_case(ext)))
+                {
+                    files.push(path);
+                }
+            }
+        }
+        files.sort();
+        Ok(Self::from_iterator(
+            files.iter().map(|path| path.to_string_lossy().into_owned()),
+        ))
+    }
+
+    pub fn builder() -> UrlGeneratorBuilder {
+        UrlGeneratorBuilder::default()
+    }
+
+    /// Picsum: request `format` by appending its extension, e.g. `/800/600.webp`
+    pub fn with_format(mut self, format: UrlImageFormat) -> Self {
+        self.format = Some(format);
+        self
+    }
+
+    /// Picsum: append `?quality=N`
+    pub fn with_quality(mut self, quality: u8) -> Self {
+        self.quality = Some(quality);
+        self
+    }
+
+    /// Drop repeated URLs, keeping the first occurrence. The result can be shorter than the
+    /// requested count, e.g. for a custom template without `{seed}`.
+    pub fn deduplicate(mut self) -> Self {
+        self.deduplicate = true;
+        self
+    }
+
+    /// Fisher-Yates shuffle the URLs, after any deduplication, with an RNG seeded by `seed`
+    /// so the order is the same on every run
+    pub fn shuffle(mut self, seed: u64) -> Self {
+        self.shuffle_seed = Some(seed);
+        self
     }
 
     /// Generate URLs for random images from Lorem Picsum
-    /// Format: https://picsum.photos/seed/{i}/800/600
+    /// Format: https://picsum.photos/seed/{i}/{width}/{height}
     /// Using seed ensures same images across runs
-    pub fn generate(&self) -> Vec<String> {
-        let mut urls: Vec<String> = Vec::new();
-        for i in 0..self.count {
-            urls.push(format!("https://picsum.photos/seed/{}/800/600", i));
+    /// Custom templates are filled in the same way; fixed lists are returned unchanged
+    pub fn generate(&self) -> Vec<InputSource> {
+        self.locations()
+            .iter()
+            .map(|location| InputSource::parse(location))
+            .collect()
+    }
+
+    /// [`ImageSource::urls`] one at a time, for corpora too large to hold in memory.
+    /// Generated URLs are built and file lines read as the stream is polled; fixed lists,
+    /// and any generator that deduplicates or shuffles, still produce every URL up front.
+    pub fn stream(&self) -> impl Stream<Item = String> + Send + 'static {
+        let reorders = self.deduplicate || self.shuffle_seed.is_some();
+        match &self.template {
+            UrlTemplate::File(path) if !reorders => {
+                read_url_lines(path.clone()).take(self.count).boxed()
+            }
+            UrlTemplate::Picsum { .. } | UrlTemplate::Custom(_) if !reorders => {
+                let generator = self.clone();
+                stream::iter((0..self.count).map(move |i| generator.template_url(i))).boxed()
+            }
+            _ => stream::iter(self.locations()).boxed(),
+        }
+    }
+
+    /// [`UrlGenerator::generate`] before parsing, as the processors key their output on them
+    fn locations(&self) -> Vec<String> {
+        let mut urls = self.template_urls();
+        if self.deduplicate {
+            let mut seen = HashSet::new();
+            urls.retain(|url| seen.insert(url.clone()));
+        }
+        if let Some(seed) = self.shuffle_seed {
+            urls.shuffle(&mut StdRng::seed_from_u64(seed));
         }
         urls
     }
+
+    fn template_urls(&self) -> Vec<String> {
+        match &self.template {
+            UrlTemplate::Fixed(urls) => urls.iter().take(self.count).cloned().collect(),
+            UrlTemplate::File(path) => match read_url_file(path) {
+                Ok(urls) => urls.into_iter().take(self.count).collect(),
+                Err(e) => {
+                    warn!(path = %path.display(), error = %e, "failed to read URL file");
+                    vec![]
+                }
+            },
+            UrlTemplate::Picsum { .. } | UrlTemplate::Custom(_) => {
+                (0..self.count).map(|i| self.template_url(i)).collect()
+            }
+        }
+    }
+
+    /// The `i`th URL of a Picsum or custom template
+    fn template_url(&self, i: usize) -> String {
+        let (width, height) = match &self.template {
+            UrlTemplate::Picsum { width, height } => (*width, *height),
+            UrlTemplate::Custom(template) => {
+                return fill_dimensions(template, 800, 600).replace("{seed}", &i.to_string());
+            }
+            UrlTemplate::Fixed(_) | UrlTemplate::File(_) => {
+                unreachable!("only Picsum and custom templates are built per index")
+            }
+        };
+        let extension = self
+            .format
+            .map(|format| format!(".{}", format.extension()))
+            .unwrap_or_default();
+        let query = self
+            .quality
+            .map(|quality| format!("?quality={}", quality))
+            .unwrap_or_default();
+        format!(
+            "https://picsum.photos/seed/{}/{}/{}{}{}",
+            i, width, height, extension, query
+        )
+    }
+}
+
+fn read_url_file(path: &Path) -> Result<Vec<String>> {
+    Ok(fs::read_to_string(path)?
+        .lines()
+        .filter_map(url_line)
+        .map(str::to_string)
+        .collect())
+}
+
+/// The URL on `line`, or `None` for blank lines and `#` comments
+fn url_line(line: &str) -> Option<&str> {
+    let line = line.trim();
+    (!line.is_empty() && !line.starts_with('#')).then_some(line)
+}
+
+/// [`read_url_file`] a line at a time. Opening or reading errors end the stream early.
+fn read_url_lines(path: PathBuf) -> impl Stream<Item = String> + Send {
+    stream::unfold(None, move |lines| {
+        let path = path.clone();
+        async move {
+            let mut lines = match lines {
+                Some(lines) => lines,
+                None => match tokio::fs::File::open(&path).await {
+                    Ok(file) => BufReader::new(file).lines(),
+                    Err(e) => {
+                        warn!(path = %path.display(), error = %e, "failed to open URL file");
+                        return None;
+                    }
+                },
+            };
+            loop {
+                match lines.next_line().await {
+                    Ok(Some(line)) => {
+                        if let Some(url) = url_line(&line) {
+                            let url = url.to_string();
+                            return Some((url, Some(lines)));
+                        }
+                    }
+                    Ok(None) => return None,
+                    Err(e) => {
+                        warn!(path = %path.display(), error = %e, "failed to read URL file");
+                        return None;
+                    }
+                }
+            }
+        }
+    })
+}
+
+/// `count` URLs built as `base` + `template`, with `{i}` in the template replaced by the index
+struct TemplateSource {
+    base: String,
+    template: String,
+    count: usize,
+}
+
+impl ImageSource for TemplateSource {
+    fn urls(&self) -> Vec<String> {
+        (0..self.count)
+            .map(|i| {
+                format!(
+                    "{}{}",
+                    self.base,
+                    self.template.replace("{i}", &i.to_string())
+                )
+            })
+            .collect()
+    }
+}
+
+/// Combines several URL sources into one [`UrlGenerator`], in the order they were added
+#[derive(Default)]
+pub struct UrlGeneratorBuilder {
+    sources: Vec<Box<dyn ImageSource>>,
+    deduplicate: bool,
+}
+
+impl UrlGeneratorBuilder {
+    pub fn add_picsum(mut self, count: usize) -> Self {
+        self.sources.push(Box::new(UrlGenerator::new(count)));
+        self
+    }
+
+    /// Read one URL per line from `path`, skipping blank lines and `#` comments
+    pub fn add_file(mut self, path: &Path) -> Result<Self> {
+        self.sources.push(Box::new(read_url_file(path)?));
+        Ok(self)
+    }
+
+    pub fn add_list(mut self, urls: Vec<String>) -> Self {
+        self.sources.push(Box::new(urls));
+        self
+    }
+
+    pub fn add_template(mut self, count: usize, template: UrlTemplate) -> Self {
+        self.sources
+            .push(Box::new(UrlGenerator::from_template(count, template)));
+        self
+    }
+
+    pub fn add_custom(mut self, base: &str, template: &str, count: usize) -> Self {
+        self.sources.push(Box::new(TemplateSource {
+            base: base.to_string(),
+            template: template.to_string(),
+            count,
+        }));
+        self
+    }
+
+    /// Drop repeated URLs, keeping the first occurrence
+    pub fn deduplicate(mut self) -> Self {
+        self.deduplicate = true;
+        self
+    }
+
+    pub fn build(self) -> UrlGenerator {
+        let urls = self.sources.iter().flat_map(|source| source.urls());
+        let generator = UrlGenerator::from_iterator(urls);
+        if self.deduplicate {
+            generator.deduplicate()
+        } else {
+            generator
+        }
+    }
+}
+
+impl ImageSource for UrlGenerator {
+    fn urls(&self) -> Vec<String> {
+        self.locations()
+    }
+}
+
+impl ImageSource for Vec<String> {
+    fn urls(&self) -> Vec<String> {
+        self.clone()
+    }
 }
 
 #[cfg(test)]
@@ -35,8 +442,196 @@ mod tests {
     #[test]
     fn urls_have_correct_format() {
         let gen = UrlGenerator::new(5);
-        let urls = gen.generate();
+        let urls = gen.urls();
         assert!(urls[0].contains("picsum.photos"));
         assert!(urls[0].contains("/800/600"));
     }
+
+    #[test]
+    fn applies_format_and_quality() {
+        let formats = [
+            (UrlImageFormat::Jpeg, "jpg"),
+            (UrlImageFormat::Png, "png"),
+            (UrlImageFormat::Webp, "webp"),
+        ];
+        for (format, extension) in formats {
+            let urls = UrlGenerator::new(1).with_format(format).urls();
+            assert_eq!(
+                urls[0],
+                format!("https://picsum.photos/seed/0/800/600.{}", extension)
+            );
+
+            let urls = UrlGenerator::new(1)
+                .with_format(format)
+                .with_quality(80)
+                .urls();
+            assert_eq!(
+                urls[0],
+                format!(
+                    "https://picsum.photos/seed/0/800/600.{}?quality=80",
+                    extension
+                )
+            );
+        }
+
+        let urls = UrlGenerator::new(1).with_quality(50).urls();
+        assert_eq!(urls[0], "https://picsum.photos/seed/0/800/600?quality=50");
+    }
+
+    #[test]
+    fn generates_from_templates() {
+        let picsum = UrlTemplate::Picsum {
+            width: 1024,
+            height: 768,
+        };
+        let urls = UrlGenerator::from_template(2, picsum).urls();
+        assert_eq!(urls[1], "https://picsum.photos/seed/1/1024/768");
+
+        let custom = UrlTemplate::custom("http://img.internal/{seed}?w={width}&h={height}", 64, 32);
+        let urls = UrlGenerator::from_template(3, custom).urls();
+        assert_eq!(urls.len(), 3);
+        assert_eq!(urls[2], "http://img.internal/2?w=64&h=32");
+
+        let no_size =
+            UrlTemplate::Custom("http://img.internal/{seed}/{width}x{height}".to_string());
+        let urls = UrlGenerator::from_template(1, no_size).urls();
+        assert_eq!(urls[0], "http://img.internal/0/800x600");
+
+        let fixed = UrlTemplate::Fixed(vec!["a".to_string(), "b".to_string(), "c".to_string()]);
+        assert_eq!(UrlGenerator::from_template(2, fixed).urls(), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn deduplicates_and_shuffles() {
+        let fixed = UrlTemplate::Fixed(["a", "b", "a", "b", "a"].map(str::to_string).to_vec());
+        let urls = UrlGenerator::from_template(5, fixed).deduplicate().urls();
+        assert_eq!(urls, vec!["a", "b"]);
+        // No `{seed}`, so every index maps onto the same URL
+        let constant = UrlTemplate::Custom("http://img.internal/static.jpg".to_string());
+        assert_eq!(
+            UrlGenerator::from_template(10, constant)
+                .deduplicate()
+                .generate()
+                .len(),
+            1
+        );
+
+        let shuffled = UrlGenerator::new(20).shuffle(7).generate();
+        assert_eq!(shuffled, UrlGenerator::new(20).shuffle(7).generate());
+        assert_ne!(shuffled, UrlGenerator::new(20).generate());
+        let mut sorted = shuffled.clone();
+        sorted.sort();
+        let mut expected = UrlGenerator::new(20).generate();
+        expected.sort();
+        assert_eq!(sorted, expected);
+    }
+
+    #[test]
+    fn loads_urls_from_file() {
+        let path = Path::new("test_urls_from_file.txt");
+        fs::write(
+            path,
+            "# corpus\n  https://example.com/a.jpg  \n\n#https://example.com/skipped.jpg\nhttps://example.com/b.jpg\n",
+        )
+        .unwrap();
+
+        let urls = UrlGenerator::from_file(path).unwrap().urls();
+        assert_eq!(
+            urls,
+            vec!["https://example.com/a.jpg", "https://example.com/b.jpg"]
+        );
+
+        fs::remove_file(path).unwrap();
+        assert!(UrlGenerator::from_file(path).is_err());
+    }
+
+    #[tokio::test]
+    async fn streams_urls() {
+        let path = Path::new("test_urls_stream.txt");
+        fs::write(
+            path,
+            "# corpus\nhttps://example.com/a.jpg\n\n  https://example.com/b.jpg\n",
+        )
+        .unwrap();
+
+        let generator = UrlGenerator::from_file(path).unwrap();
+        let streamed: Vec<String> = generator.stream().collect().await;
+        assert_eq!(
+            streamed,
+            vec!["https://example.com/a.jpg", "https://example.com/b.jpg"]
+        );
+        assert_eq!(streamed, generator.urls());
+
+        for generator in [
+            UrlGenerator::new(5).with_format(UrlImageFormat::Png),
+            UrlGenerator::from_template(3, UrlTemplate::custom("http://img/{seed}", 8, 8)),
+            UrlGenerator::new(20).shuffle(7),
+        ] {
+            let streamed: Vec<String> = generator.stream().collect().await;
+            assert_eq!(streamed, generator.urls());
+        }
+
+        fs::remove_file(path).unwrap();
+        let streamed: Vec<String> = generator.stream().collect().await;
+        assert!(streamed.is_empty());
+    }
+
+    #[test]
+    fn builder_merges_sources() {
+        let path = Path::new("test_urls.txt");
+        fs::write(
+            path,
+            "# mirror\nhttps://example.com/a.jpg\n\nhttps://picsum.photos/seed/0/800/600\n",
+        )
+        .unwrap();
+
+        let urls = UrlGenerator::builder()
+            .add_picsum(3)
+            .add_file(path)
+            .unwrap()
+            .add_list(vec!["https://example.com/a.jpg".to_string()])
+            .add_custom("https://cdn.example.com", "/img/{i}.png", 2)
+            .add_template(
+                1,
+                UrlTemplate::custom("http://img.internal/{seed}/{width}", 64, 64),
+            )
+            .deduplicate()
+            .build()
+            .urls();
+
+        // 3 + 2 + 1 + 2 + 1, minus the repeated picsum seed 0 and example.com/a.jpg
+        assert_eq!(urls.len(), 7);
+        assert_eq!(urls[4], "https://cdn.example.com/img/0.png");
+        assert_eq!(urls[6], "http://img.internal/0/64");
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn lists_local_files() {
+        let dir = Path::new("test_input_dir");
+        fs::create_dir_all(dir.join("nested")).unwrap();
+        for name in ["b.JPG", "a.png", "notes.txt", "nested/c.jpg"] {
+            fs::write(dir.join(name), b"").unwrap();
+        }
+
+        let sources = UrlGenerator::from_directory(dir, &["jpg", "png"])
+            .unwrap()
+            .generate();
+        assert_eq!(
+            sources,
+            ["a.png", "b.JPG", "nested/c.jpg"].map(|name| InputSource::LocalFile(dir.join(name)))
+        );
+        assert_eq!(
+            InputSource::parse("https://example.com/a.jpg"),
+            InputSource::Http("https://example.com/a.jpg".to_string())
+        );
+        assert_eq!(
+            InputSource::parse("file:///tmp/a.jpg"),
+            InputSource::LocalFile(PathBuf::from("/tmp/a.jpg"))
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+        assert!(UrlGenerator::from_directory(dir, &["jpg"]).is_err());
+    }
 }
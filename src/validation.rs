@@ -0,0 +1,20 @@
+// src/validation.rs
+
+use anyhow::Result;
+
+/// Reject a zero image count before a processor generates URLs or starts any stage
+pub fn validate_count(count: usize) -> Result<()> {
+    anyhow::ensure!(count > 0, "count must be > 0");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_zero_count() {
+        assert!(validate_count(0).is_err());
+        assert!(validate_count(1).is_ok());
+    }
+}
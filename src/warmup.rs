@@ -0,0 +1,62 @@
+// src/warmup.rs
+
+use std::sync::Arc;
+
+use futures::future::join_all;
+use tokio::sync::Semaphore;
+use tracing::{info, warn};
+
+use crate::{config::ProcessorConfig, streaming::download::fetch_image};
+
+/// Download the first `config.warmup_count` of `urls` and throw them away, so DNS, the
+/// connection pool and OS and server caches are warm before a processor starts timing.
+/// Processors call this before creating any timers or counters, so nothing downloaded here
+/// shows up in their stats. Failed downloads are only logged.
+pub async fn warm_up(urls: &[String], config: &ProcessorConfig) {
+    let urls = &urls[..config.warmup_count.min(urls.len())];
+    if urls.is_empty() {
+        return;
+    }
+    info!(count = urls.len(), "warming up");
+    let sem = Arc::new(Semaphore::new(config.download_concurrency.max(1)));
+    let downloads = urls
+        .iter()
+        .map(|url| fetch_image(url.clone(), Arc::clone(&sem), config.clone()));
+    let failed = join_all(downloads)
+        .await
+        .iter()
+        .filter(|res| res.is_err())
+        .count();
+    if failed > 0 {
+        warn!(failed, "warmup downloads failed");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::jpeg_bytes;
+    use wiremock::{matchers::any, Mock, MockServer, ResponseTemplate};
+
+    #[tokio::test]
+    async fn downloads_only_the_warmup_images() {
+        let server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(jpeg_bytes(8, 8)))
+            .mount(&server)
+            .await;
+        let urls: Vec<String> = (0..5).map(|i| format!("{}/{}", server.uri(), i)).collect();
+
+        warm_up(&urls, &ProcessorConfig::default()).await;
+        assert!(server.received_requests().await.unwrap().is_empty());
+
+        let config = ProcessorConfig {
+            warmup_count: 2,
+            ..Default::default()
+        };
+        warm_up(&urls, &config).await;
+        let requests = server.received_requests().await.unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(requests.iter().all(|request| request.url.path() != "/4"));
+    }
+}